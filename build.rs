@@ -0,0 +1,52 @@
+//! With the `embed` feature, generates a table of every file under `pages/`
+//! (or `RCOMM_EMBED_ROOT`) for `src/embed.rs` to `include!`. Without it the
+//! table is empty.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-env-changed=RCOMM_EMBED_ROOT");
+
+    let mut files = Vec::new();
+    if env::var_os("CARGO_FEATURE_EMBED").is_some() {
+        let root = env::var_os("RCOMM_EMBED_ROOT").map(PathBuf::from).unwrap_or_else(|| {
+            PathBuf::from(env::var_os("CARGO_MANIFEST_DIR").unwrap()).join("pages")
+        });
+        let root = fs::canonicalize(&root).unwrap_or_else(|e| panic!("embed root {}: {e}", root.display()));
+        println!("cargo:rerun-if-changed={}", root.display());
+        collect(&root, &root, &mut files);
+    }
+    files.sort();
+
+    let mut table = String::from("pub static EMBEDDED_FILES: &[(&str, &[u8])] = &[\n");
+    for (name, path) in files {
+        table.push_str(&format!("    ({name:?}, include_bytes!({:?})),\n", path.display().to_string()));
+    }
+    table.push_str("];\n");
+
+    let out = PathBuf::from(env::var_os("OUT_DIR").unwrap()).join("embedded_pages.rs");
+    fs::write(out, table).unwrap();
+}
+
+/// Pushes `(path relative to root with / separators, absolute path)` for
+/// every file below `dir`.
+fn collect(root: &Path, dir: &Path, files: &mut Vec<(String, PathBuf)>) {
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            collect(root, &path, files);
+        } else if path.is_file() {
+            let relative = path.strip_prefix(root).unwrap();
+            let name = relative
+                .components()
+                .map(|part| part.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            files.push((name, path));
+        }
+    }
+}
@@ -0,0 +1,218 @@
+use std::{
+    fmt::Write as _,
+    io::{self, Write},
+    net::IpAddr,
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+use crate::models::http_date::{format_http_date, format_rfc3339};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogFormat {
+    Plain,
+    Json,
+}
+
+pub fn log_format_from_string(format: &str) -> Option<LogFormat> {
+    match format.to_lowercase().as_str() {
+        "plain" | "text" => Some(LogFormat::Plain),
+        "json" => Some(LogFormat::Json),
+        _ => None,
+    }
+}
+
+/// One served request, as recorded in the access log.
+pub struct AccessLogEntry<'a> {
+    pub time: SystemTime,
+    pub method: &'a str,
+    pub path: &'a str,
+    pub version: &'a str,
+    pub status: u16,
+    pub bytes: usize,
+    pub duration: Duration,
+    pub remote: Option<IpAddr>,
+    pub request_id: &'a str,
+}
+
+impl AccessLogEntry<'_> {
+    pub fn format(&self, format: LogFormat) -> String {
+        let remote = self.remote.map_or_else(|| String::from("-"), |addr| addr.to_string());
+        let duration_ms = self.duration.as_secs_f64() * 1000.0;
+        match format {
+            LogFormat::Plain => format!(
+                "{remote} [{}] \"{} {} {}\" {} {} {duration_ms:.3}ms {}",
+                format_http_date(self.time),
+                self.method,
+                self.path,
+                self.version,
+                self.status,
+                self.bytes,
+                self.request_id,
+            ),
+            LogFormat::Json => format!(
+                "{{\"ts\":{},\"method\":{},\"path\":{},\"status\":{},\"bytes\":{},\"duration_ms\":{duration_ms:.3},\"remote\":{},\"request_id\":{}}}",
+                json_string(&format_rfc3339(self.time)),
+                json_string(self.method),
+                json_string(self.path),
+                self.status,
+                self.bytes,
+                json_string(&remote),
+                json_string(self.request_id),
+            ),
+        }
+    }
+}
+
+/// Quotes `value` as a JSON string, escaping quotes, backslashes and
+/// control characters.
+fn json_string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Writes one line per request to a shared sink (stdout by default),
+/// separate from the diagnostic `Logger` on stderr.
+pub struct AccessLog {
+    format: LogFormat,
+    sink: Mutex<Box<dyn Write + Send>>,
+}
+
+impl AccessLog {
+    pub fn new(format: LogFormat, sink: Box<dyn Write + Send>) -> AccessLog {
+        AccessLog { format, sink: Mutex::new(sink) }
+    }
+
+    pub fn stdout(format: LogFormat) -> AccessLog {
+        AccessLog::new(format, Box::new(io::stdout()))
+    }
+
+    pub fn log(&self, entry: &AccessLogEntry) {
+        let line = entry.format(self.format);
+        if let Ok(mut sink) = self.sink.lock() {
+            let _ = writeln!(sink, "{line}");
+            let _ = sink.flush();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::time::UNIX_EPOCH;
+
+    fn entry(path: &str) -> AccessLogEntry<'_> {
+        AccessLogEntry {
+            time: UNIX_EPOCH + Duration::from_secs(784_111_777),
+            method: "GET",
+            path,
+            version: "HTTP/1.1",
+            status: 200,
+            bytes: 14,
+            duration: Duration::from_micros(1500),
+            remote: Some("127.0.0.1".parse().unwrap()),
+            request_id: "00000001",
+        }
+    }
+
+    /// Minimal parser for a flat JSON object of string and number values,
+    /// enough to check the access log emits well-formed lines.
+    fn parse_flat_json(line: &str) -> Option<HashMap<String, String>> {
+        fn parse_string(chars: &mut std::iter::Peekable<std::str::Chars>) -> Option<String> {
+            if chars.next()? != '"' {
+                return None;
+            }
+            let mut out = String::new();
+            loop {
+                match chars.next()? {
+                    '"' => return Some(out),
+                    '\\' => match chars.next()? {
+                        'n' => out.push('\n'),
+                        'r' => out.push('\r'),
+                        't' => out.push('\t'),
+                        'u' => {
+                            let hex: String = (0..4).map(|_| chars.next()).collect::<Option<_>>()?;
+                            out.push(char::from_u32(u32::from_str_radix(&hex, 16).ok()?)?);
+                        }
+                        c @ ('"' | '\\' | '/') => out.push(c),
+                        _ => return None,
+                    },
+                    c if (c as u32) < 0x20 => return None,
+                    c => out.push(c),
+                }
+            }
+        }
+
+        let mut chars = line.trim().chars().peekable();
+        let mut fields = HashMap::new();
+        if chars.next()? != '{' {
+            return None;
+        }
+        loop {
+            let key = parse_string(&mut chars)?;
+            if chars.next()? != ':' {
+                return None;
+            }
+            let value = if chars.peek() == Some(&'"') {
+                parse_string(&mut chars)?
+            } else {
+                let mut number = String::new();
+                while let Some(c) = chars.peek().filter(|c| c.is_ascii_digit() || **c == '.' || **c == '-') {
+                    number.push(*c);
+                    chars.next();
+                }
+                number.parse::<f64>().ok()?;
+                number
+            };
+            fields.insert(key, value);
+            match chars.next()? {
+                ',' => continue,
+                '}' => return chars.next().is_none().then_some(fields),
+                _ => return None,
+            }
+        }
+    }
+
+    #[test]
+    fn json_line_parses_with_expected_keys() {
+        let line = entry("/say \"hi\"\\\n").format(LogFormat::Json);
+        let fields = parse_flat_json(&line).expect("access log line is not valid JSON");
+
+        for key in ["ts", "method", "path", "status", "bytes", "duration_ms", "remote", "request_id"] {
+            assert!(fields.contains_key(key), "missing {key} in {line}");
+        }
+        assert_eq!(fields["ts"], "1994-11-06T08:49:37Z");
+        assert_eq!(fields["path"], "/say \"hi\"\\\n");
+        assert_eq!(fields["status"], "200");
+        assert_eq!(fields["duration_ms"], "1.500");
+        assert_eq!(fields["remote"], "127.0.0.1");
+    }
+
+    #[test]
+    fn plain_line_is_default_style() {
+        assert_eq!(
+            entry("/howdy").format(LogFormat::Plain),
+            "127.0.0.1 [Sun, 06 Nov 1994 08:49:37 GMT] \"GET /howdy HTTP/1.1\" 200 14 1.500ms 00000001"
+        );
+    }
+
+    #[test]
+    fn json_string_escapes_control_characters() {
+        assert_eq!(json_string("a\u{1}b"), "\"a\\u0001b\"");
+    }
+}
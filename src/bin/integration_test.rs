@@ -9,6 +9,8 @@ use std::{
     thread,
     time::{Duration, Instant},
 };
+use rcomm::client::HttpClient;
+use rcomm::models::{http_methods::http_method_from_string, http_response::HttpResponse};
 
 // ---------------------------------------------------------------------------
 // Server lifecycle
@@ -53,11 +55,16 @@ fn find_project_root() -> PathBuf {
 }
 
 fn start_server(port: u16) -> Child {
+    start_server_with_env(port, &[])
+}
+
+fn start_server_with_env(port: u16, env: &[(&str, &str)]) -> Child {
     let binary = find_server_binary();
     let project_root = find_project_root();
     Command::new(binary)
         .env("RCOMM_PORT", port.to_string())
         .env("RCOMM_ADDRESS", "127.0.0.1")
+        .envs(env.iter().copied())
         .current_dir(project_root)
         .stdout(Stdio::null())
         .stderr(Stdio::null())
@@ -65,6 +72,21 @@ fn start_server(port: u16) -> Child {
         .expect("failed to start rcomm server")
 }
 
+/// Runs `f` against a dedicated server started with extra environment
+/// variables, for tests that need non-default configuration.
+fn with_server<F>(env: &[(&str, &str)], f: F) -> Result<(), String>
+where
+    F: FnOnce(&str) -> Result<(), String>,
+{
+    let port = pick_free_port();
+    let addr = format!("127.0.0.1:{port}");
+    let mut server = start_server_with_env(port, env);
+    let result = wait_for_server(&addr, Duration::from_secs(5)).and_then(|()| f(&addr));
+    let _ = server.kill();
+    let _ = server.wait();
+    result
+}
+
 fn wait_for_server(addr: &str, timeout: Duration) -> Result<(), String> {
     let start = Instant::now();
     loop {
@@ -93,78 +115,70 @@ struct TestResponse {
     body: String,
 }
 
-fn read_response(stream: &mut TcpStream) -> Result<TestResponse, String> {
+fn read_response(stream: &mut TcpStream, method: &str) -> Result<TestResponse, String> {
     let mut reader = BufReader::new(stream.try_clone().map_err(|e| e.to_string())?);
+    read_response_from(&mut reader, method)
+}
 
-    // Status line
-    let mut status_line = String::new();
-    reader
-        .read_line(&mut status_line)
-        .map_err(|e| format!("reading status line: {e}"))?;
-    let parts: Vec<&str> = status_line.trim().splitn(3, ' ').collect();
-    if parts.len() < 2 {
-        return Err(format!("malformed status line: {status_line}"));
-    }
-    let status_code: u16 = parts[1]
-        .parse()
-        .map_err(|_| format!("bad status code: {}", parts[1]))?;
-    let status_phrase = if parts.len() == 3 {
-        parts[2].to_string()
+/// Reads one response from `reader`, leaving any bytes of later responses
+/// buffered so pipelined responses can be read in sequence. Responses to
+/// HEAD carry a `Content-Length` but no body, so none is read for them.
+fn read_response_from<R: BufRead>(reader: &mut R, method: &str) -> Result<TestResponse, String> {
+    let response = if method.eq_ignore_ascii_case("HEAD") {
+        HttpResponse::build_head_from_reader(reader)
     } else {
-        String::new()
+        HttpResponse::build_from_reader(reader)
     };
+    let response = response.map_err(|e| format!("reading response: {e}"))?;
+    Ok(TestResponse::from(response))
+}
 
-    // Headers
-    let mut headers = HashMap::new();
-    loop {
-        let mut line = String::new();
-        reader
-            .read_line(&mut line)
-            .map_err(|e| format!("reading header: {e}"))?;
-        let trimmed = line.trim();
-        if trimmed.is_empty() {
-            break;
-        }
-        if let Some((key, val)) = trimmed.split_once(':') {
-            headers.insert(
-                key.trim().to_lowercase(),
-                val.trim().to_string(),
-            );
+impl From<HttpResponse> for TestResponse {
+    fn from(response: HttpResponse) -> TestResponse {
+        let headers = response
+            .headers()
+            .map(|(title, value)| (title.to_string(), value.to_string()))
+            .collect();
+        TestResponse {
+            status_code: response.status_code(),
+            status_phrase: response.status_phrase().to_string(),
+            headers,
+            body: String::from_utf8_lossy(&response.try_get_body().unwrap_or_default()).to_string(),
         }
     }
+}
 
-    // Body via Content-Length
-    let body = if let Some(cl) = headers.get("content-length") {
-        let len: usize = cl
-            .parse()
-            .map_err(|_| format!("bad content-length: {cl}"))?;
-        let mut buf = vec![0u8; len];
-        reader
-            .read_exact(&mut buf)
-            .map_err(|e| format!("reading body: {e}"))?;
-        String::from_utf8_lossy(&buf).to_string()
-    } else {
-        String::new()
-    };
+fn send_request(addr: &str, method: &str, path: &str) -> Result<TestResponse, String> {
+    send_request_with_headers(addr, method, path, &[])
+}
 
-    Ok(TestResponse {
-        status_code,
-        status_phrase,
-        headers,
-        body,
-    })
+fn send_request_with_headers(
+    addr: &str,
+    method: &str,
+    path: &str,
+    headers: &[(&str, &str)],
+) -> Result<TestResponse, String> {
+    let method = http_method_from_string(method).ok_or_else(|| format!("bad method: {method}"))?;
+    let mut client = HttpClient::new();
+    client.set_timeout(Duration::from_secs(5));
+    let response = client
+        .request(method, &format!("http://{addr}{path}"), headers, None)
+        .map_err(|e| e.to_string())?;
+    Ok(TestResponse::from(response))
 }
 
-fn send_request(addr: &str, method: &str, path: &str) -> Result<TestResponse, String> {
+/// Writes `raw` verbatim and reads back one response, for requests the
+/// helpers above can't express.
+fn send_raw(addr: &str, raw: &str) -> Result<TestResponse, String> {
     let mut stream = TcpStream::connect(addr).map_err(|e| format!("connect: {e}"))?;
     stream
         .set_read_timeout(Some(Duration::from_secs(5)))
         .map_err(|e| format!("set timeout: {e}"))?;
-    let request = format!("{method} {path} HTTP/1.1\r\nHost: {addr}\r\nConnection: close\r\n\r\n");
     stream
-        .write_all(request.as_bytes())
+        .write_all(raw.as_bytes())
         .map_err(|e| format!("write: {e}"))?;
-    read_response(&mut stream)
+    let method = raw.split_whitespace().next().unwrap_or("");
+    read_response(&mut stream, method)
 }
 
 // ---------------------------------------------------------------------------
@@ -180,12 +194,21 @@ struct TestResult {
 fn run_test<F>(name: &str, f: F) -> TestResult
 where
     F: FnOnce() -> Result<(), String>,
+{
+    run_test_with_detail(name, || f().map(|()| "OK".to_string()))
+}
+
+/// Like `run_test`, for tests that report something (such as timings) on
+/// success.
+fn run_test_with_detail<F>(name: &str, f: F) -> TestResult
+where
+    F: FnOnce() -> Result<String, String>,
 {
     match f() {
-        Ok(()) => TestResult {
+        Ok(detail) => TestResult {
             name: name.to_string(),
             passed: true,
-            detail: "OK".to_string(),
+            detail,
         },
         Err(detail) => TestResult {
             name: name.to_string(),
@@ -237,6 +260,19 @@ fn test_index_css(addr: &str) -> Result<(), String> {
     Ok(())
 }
 
+fn test_accept_ranges(addr: &str) -> Result<(), String> {
+    let full = send_request(addr, "GET", "/index.css")?;
+    assert_eq_or_err(&full.status_code, &200, "status")?;
+    assert_eq_or_err(&full.headers.get("accept-ranges").map(String::as_str), &Some("bytes"), "accept-ranges")?;
+
+    let partial = send_request_with_headers(addr, "GET", "/index.css", &[("Range", "bytes=0-4")])?;
+    assert_eq_or_err(&partial.status_code, &206, "range status")?;
+    assert_eq_or_err(&partial.body.as_str(), &&full.body[..5], "range body")?;
+    let content_range = format!("bytes 0-4/{}", full.body.len());
+    assert_eq_or_err(&partial.headers.get("content-range"), &Some(&content_range), "content-range")?;
+    Ok(())
+}
+
 fn test_howdy_route(addr: &str) -> Result<(), String> {
     let resp = send_request(addr, "GET", "/howdy")?;
     assert_eq_or_err(&resp.status_code, &200, "status")?;
@@ -335,6 +371,572 @@ fn test_concurrent_requests(addr: &str) -> Result<(), String> {
     Ok(())
 }
 
+fn test_cors_preflight(_addr: &str) -> Result<(), String> {
+    with_server(&[("RCOMM_CORS_ORIGINS", "http://app.example")], |addr| {
+        let resp = send_request_with_headers(addr, "OPTIONS", "/", &[
+            ("Origin", "http://app.example"),
+            ("Access-Control-Request-Method", "GET"),
+        ])?;
+        assert_eq_or_err(&resp.status_code, &204, "status")?;
+        assert_eq_or_err(
+            &resp.headers.get("access-control-allow-origin").map(String::as_str),
+            &Some("http://app.example"),
+            "allow-origin",
+        )?;
+        let methods = resp
+            .headers
+            .get("access-control-allow-methods")
+            .ok_or("missing Access-Control-Allow-Methods")?;
+        assert_contains_or_err(methods, "GET", "allow-methods")?;
+        Ok(())
+    })
+}
+
+fn test_cors_simple_get(_addr: &str) -> Result<(), String> {
+    with_server(&[("RCOMM_CORS_ORIGINS", "http://app.example")], |addr| {
+        let resp = send_request_with_headers(addr, "GET", "/", &[("Origin", "http://app.example")])?;
+        assert_eq_or_err(&resp.status_code, &200, "status")?;
+        assert_eq_or_err(
+            &resp.headers.get("access-control-allow-origin").map(String::as_str),
+            &Some("http://app.example"),
+            "allow-origin",
+        )?;
+
+        let resp = send_request_with_headers(addr, "GET", "/", &[("Origin", "http://other.example")])?;
+        assert_eq_or_err(
+            &resp.headers.get("access-control-allow-origin"),
+            &None,
+            "allow-origin for other origin",
+        )?;
+        Ok(())
+    })
+}
+
+fn test_keepalive_idle_timeout(_addr: &str) -> Result<(), String> {
+    with_server(&[("RCOMM_KEEPALIVE_TIMEOUT", "1")], |addr| {
+        let mut stream = TcpStream::connect(addr).map_err(|e| format!("connect: {e}"))?;
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .map_err(|e| format!("set timeout: {e}"))?;
+
+        // Two requests reuse the same socket.
+        for i in 0..2 {
+            let request = format!("GET / HTTP/1.1\r\nHost: {addr}\r\n\r\n");
+            stream
+                .write_all(request.as_bytes())
+                .map_err(|e| format!("write: {e}"))?;
+            let resp = read_response(&mut stream, "GET")?;
+            assert_eq_or_err(&resp.status_code, &200, &format!("request {i} status"))?;
+            assert_eq_or_err(
+                &resp.headers.get("connection").map(String::as_str),
+                &Some("keep-alive"),
+                &format!("request {i} connection"),
+            )?;
+        }
+
+        // Left idle, the server closes the connection after the timeout.
+        let start = Instant::now();
+        let mut buf = [0u8; 1];
+        let n = stream
+            .read(&mut buf)
+            .map_err(|e| format!("waiting for close: {e}"))?;
+        assert_eq_or_err(&n, &0, "bytes read after idle timeout")?;
+        if start.elapsed() > Duration::from_secs(3) {
+            return Err(format!("connection closed after {:?}, expected ~1s", start.elapsed()));
+        }
+        Ok(())
+    })
+}
+
+fn test_pipelined_requests(addr: &str) -> Result<(), String> {
+    let mut stream = TcpStream::connect(addr).map_err(|e| format!("connect: {e}"))?;
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .map_err(|e| format!("set timeout: {e}"))?;
+
+    // Both requests go out in a single write before any response is read.
+    let requests = format!(
+        "GET / HTTP/1.1\r\nHost: {addr}\r\n\r\nGET /howdy HTTP/1.1\r\nHost: {addr}\r\nConnection: close\r\n\r\n"
+    );
+    stream
+        .write_all(requests.as_bytes())
+        .map_err(|e| format!("write: {e}"))?;
+
+    let mut reader = BufReader::new(stream);
+    let first = read_response_from(&mut reader, "GET")?;
+    assert_eq_or_err(&first.status_code, &200, "first status")?;
+    assert_contains_or_err(&first.body, "Hello!", "first body")?;
+
+    let second = read_response_from(&mut reader, "GET")?;
+    assert_eq_or_err(&second.status_code, &200, "second status")?;
+    assert_contains_or_err(&second.body, "Howdy!", "second body")?;
+    Ok(())
+}
+
+fn test_head_request(addr: &str) -> Result<(), String> {
+    let mut stream = TcpStream::connect(addr).map_err(|e| format!("connect: {e}"))?;
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .map_err(|e| format!("set timeout: {e}"))?;
+
+    // A GET follows on the same connection, so reading a body for the HEAD
+    // would either block or swallow the next response.
+    let requests = format!(
+        "HEAD / HTTP/1.1\r\nHost: {addr}\r\n\r\nGET / HTTP/1.1\r\nHost: {addr}\r\nConnection: close\r\n\r\n"
+    );
+    stream
+        .write_all(requests.as_bytes())
+        .map_err(|e| format!("write: {e}"))?;
+
+    let mut reader = BufReader::new(stream);
+    let head = read_response_from(&mut reader, "HEAD")?;
+    assert_eq_or_err(&head.status_code, &200, "head status")?;
+    assert_eq_or_err(&head.body.as_str(), &"", "head body")?;
+    let get = read_response_from(&mut reader, "GET")?;
+    assert_eq_or_err(
+        &head.headers.get("content-length"),
+        &Some(&get.body.len().to_string()),
+        "head content-length",
+    )?;
+    assert_contains_or_err(&get.body, "Hello!", "get body")?;
+    Ok(())
+}
+
+fn test_pipelined_head_and_get(addr: &str) -> Result<(), String> {
+    let mut stream = TcpStream::connect(addr).map_err(|e| format!("connect: {e}"))?;
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .map_err(|e| format!("set timeout: {e}"))?;
+    let sequence = [
+        ("GET", "/", 200, "Hello!"),
+        ("HEAD", "/howdy", 200, ""),
+        ("HEAD", "/nope", 404, ""),
+        ("GET", "/howdy", 200, "Howdy!"),
+        ("HEAD", "/", 200, ""),
+        ("GET", "/nope", 404, ""),
+    ];
+    let mut requests = String::new();
+    for (i, (method, path, _, _)) in sequence.iter().enumerate() {
+        let connection = if i + 1 == sequence.len() { "close" } else { "keep-alive" };
+        requests.push_str(&format!("{method} {path} HTTP/1.1\r\nHost: {addr}\r\nConnection: {connection}\r\n\r\n"));
+    }
+    stream
+        .write_all(requests.as_bytes())
+        .map_err(|e| format!("write: {e}"))?;
+
+    // Any body bytes written for a HEAD would be parsed as the next status line.
+    let mut reader = BufReader::new(stream);
+    for (i, (method, path, status, body)) in sequence.into_iter().enumerate() {
+        let resp = read_response_from(&mut reader, method)?;
+        let label = format!("#{i} {method} {path}");
+        assert_eq_or_err(&resp.status_code, &status, &format!("{label} status"))?;
+        if method == "HEAD" {
+            assert_eq_or_err(&resp.body.as_str(), &"", &format!("{label} body"))?;
+        } else {
+            assert_contains_or_err(&resp.body, body, &format!("{label} body"))?;
+        }
+    }
+    let mut rest = Vec::new();
+    reader.read_to_end(&mut rest).map_err(|e| format!("reading trailing bytes: {e}"))?;
+    assert_eq_or_err(&rest.len(), &0, "bytes after last response")
+}
+
+fn test_head_get_etag_match(addr: &str) -> Result<(), String> {
+    for path in ["/", "/index.css"] {
+        let head = send_request(addr, "HEAD", path)?;
+        let get = send_request(addr, "GET", path)?;
+        let etag = head.headers.get("etag").ok_or(format!("{path}: HEAD has no etag"))?;
+        assert_eq_or_err(&Some(etag), &get.headers.get("etag"), &format!("{path} etag"))?;
+        assert_eq_or_err(
+            &head.headers.get("content-length"),
+            &get.headers.get("content-length"),
+            &format!("{path} content-length"),
+        )?;
+    }
+    Ok(())
+}
+
+fn test_head_compressed_length(_addr: &str) -> Result<(), String> {
+    with_server(&[("RCOMM_COMPRESSION", "1"), ("RCOMM_COMPRESSION_THRESHOLD", "0")], |addr| {
+        let gzip = [("Accept-Encoding", "gzip")];
+        let head = send_request_with_headers(addr, "HEAD", "/", &gzip)?;
+        let get = send_request_with_headers(addr, "GET", "/", &gzip)?;
+        assert_eq_or_err(&get.headers.get("content-encoding").map(String::as_str), &Some("gzip"), "get encoding")?;
+        assert_eq_or_err(&head.headers.get("content-encoding"), &get.headers.get("content-encoding"), "head encoding")?;
+        assert_eq_or_err(&head.headers.get("content-length"), &get.headers.get("content-length"), "content-length")?;
+        assert_eq_or_err(&head.headers.get("etag"), &get.headers.get("etag"), "etag")
+    })
+}
+
+fn test_head_not_found(addr: &str) -> Result<(), String> {
+    let resp = send_request(addr, "HEAD", "/nope")?;
+    assert_eq_or_err(&resp.status_code, &404, "status")?;
+    assert_eq_or_err(&resp.body.as_str(), &"", "body")?;
+    let len: usize = resp
+        .headers
+        .get("content-length")
+        .and_then(|len| len.parse().ok())
+        .ok_or("missing content-length")?;
+    let get = send_request(addr, "GET", "/nope")?;
+    assert_eq_or_err(&len, &get.body.len(), "content-length matches GET body")?;
+    if len == 0 {
+        return Err("content-length is zero".to_string());
+    }
+    Ok(())
+}
+
+fn test_options_allow(addr: &str) -> Result<(), String> {
+    let resp = send_request(addr, "OPTIONS", "/howdy")?;
+    assert_eq_or_err(&resp.status_code, &204, "status")?;
+    assert_eq_or_err(
+        &resp.headers.get("allow").map(String::as_str),
+        &Some("GET, HEAD, OPTIONS"),
+        "allow",
+    )?;
+    Ok(())
+}
+
+fn test_get_with_body_rejected(addr: &str) -> Result<(), String> {
+    let raw = format!("GET / HTTP/1.1\r\nHost: {addr}\r\nContent-Length: 5\r\nConnection: close\r\n\r\nhello");
+    let resp = send_raw(addr, &raw)?;
+    assert_eq_or_err(&resp.status_code, &400, "status")?;
+    assert_eq_or_err(
+        &resp.headers.get("connection").map(String::as_str),
+        &Some("close"),
+        "connection",
+    )?;
+    Ok(())
+}
+
+/// Sends `raw` and checks the size-limit error that comes back: the status,
+/// a short body naming it, and `Connection: close`.
+fn expect_limit_error(addr: &str, raw: &str, status: u16) -> Result<(), String> {
+    let resp = send_raw(addr, raw)?;
+    assert_eq_or_err(&resp.status_code, &status, "status")?;
+    assert_contains_or_err(&resp.body, &status.to_string(), "body")?;
+    assert_eq_or_err(
+        &resp.headers.get("connection").map(String::as_str),
+        &Some("close"),
+        "connection",
+    )
+}
+
+fn test_uri_too_long(addr: &str) -> Result<(), String> {
+    let target = format!("/{}", "a".repeat(20 * 1024));
+    expect_limit_error(addr, &format!("GET {target} HTTP/1.1\r\nHost: {addr}\r\n\r\n"), 414)
+}
+
+fn test_header_too_long(addr: &str) -> Result<(), String> {
+    let value = "v".repeat(9 * 1024);
+    expect_limit_error(addr, &format!("GET / HTTP/1.1\r\nHost: {addr}\r\nX-Big: {value}\r\n\r\n"), 431)
+}
+
+fn test_too_many_headers(addr: &str) -> Result<(), String> {
+    let headers: String = (0..150).map(|i| format!("X-H{i}: v\r\n")).collect();
+    expect_limit_error(addr, &format!("GET / HTTP/1.1\r\nHost: {addr}\r\n{headers}\r\n"), 431)
+}
+
+fn test_body_too_large(_addr: &str) -> Result<(), String> {
+    with_server(&[("RCOMM_MAX_BODY", "16")], |addr| {
+        let body = "b".repeat(64);
+        let raw = format!("POST / HTTP/1.1\r\nHost: {addr}\r\nContent-Length: 64\r\n\r\n{body}");
+        expect_limit_error(addr, &raw, 413)
+    })
+}
+
+fn test_connect_not_implemented(addr: &str) -> Result<(), String> {
+    let resp = send_raw(addr, "CONNECT example.com:443 HTTP/1.1\r\nHost: example.com:443\r\nConnection: close\r\n\r\n")?;
+    assert_eq_or_err(&resp.status_code, &501, "status")?;
+    Ok(())
+}
+
+fn test_h2c_upgrade_declined(addr: &str) -> Result<(), String> {
+    let mut stream = TcpStream::connect(addr).map_err(|e| format!("connect: {e}"))?;
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .map_err(|e| format!("set timeout: {e}"))?;
+
+    let upgrade = format!(
+        "GET / HTTP/1.1\r\nHost: {addr}\r\nConnection: Upgrade, HTTP2-Settings\r\nUpgrade: h2c\r\nHTTP2-Settings: AAMAAABkAAQAAP__\r\n\r\n"
+    );
+    stream
+        .write_all(upgrade.as_bytes())
+        .map_err(|e| format!("write: {e}"))?;
+    let resp = read_response(&mut stream, "GET")?;
+    assert_eq_or_err(&resp.status_code, &200, "upgrade request status")?;
+    assert_eq_or_err(&resp.headers.get("upgrade"), &None, "upgrade header")?;
+    assert_contains_or_err(&resp.body, "Hello!", "upgrade request body")?;
+
+    // The connection is still plain HTTP/1.1 afterwards.
+    let follow_up = format!("GET /howdy HTTP/1.1\r\nHost: {addr}\r\nConnection: close\r\n\r\n");
+    stream
+        .write_all(follow_up.as_bytes())
+        .map_err(|e| format!("write: {e}"))?;
+    let resp = read_response(&mut stream, "GET")?;
+    assert_eq_or_err(&resp.status_code, &200, "follow-up status")?;
+    assert_contains_or_err(&resp.body, "Howdy!", "follow-up body")?;
+    Ok(())
+}
+
+fn test_get_with_body_allowed(_addr: &str) -> Result<(), String> {
+    with_server(&[("RCOMM_ALLOW_GET_BODY", "1")], |addr| {
+        let raw = format!("GET / HTTP/1.1\r\nHost: {addr}\r\nContent-Length: 5\r\nConnection: close\r\n\r\nhello");
+        let resp = send_raw(addr, &raw)?;
+        assert_eq_or_err(&resp.status_code, &200, "status")?;
+        Ok(())
+    })
+}
+
+fn test_trailing_slash_strip(_addr: &str) -> Result<(), String> {
+    with_server(&[("RCOMM_TRAILING_SLASH", "strip")], |addr| {
+        let resp = send_request(addr, "GET", "/howdy/")?;
+        assert_eq_or_err(&resp.status_code, &301, "status")?;
+        assert_eq_or_err(
+            &resp.headers.get("location").map(String::as_str),
+            &Some("/howdy"),
+            "location",
+        )?;
+        let resp = send_request(addr, "GET", "/howdy")?;
+        assert_eq_or_err(&resp.status_code, &200, "canonical status")?;
+        let resp = send_request(addr, "GET", "/index.css/")?;
+        assert_eq_or_err(&resp.status_code, &200, "file status")?;
+        Ok(())
+    })
+}
+
+fn test_path_prefix(_addr: &str) -> Result<(), String> {
+    with_server(&[("RCOMM_PATH_PREFIX", "/app")], |addr| {
+        let resp = send_request(addr, "GET", "/app/howdy")?;
+        assert_eq_or_err(&resp.status_code, &200, "prefixed status")?;
+        assert_contains_or_err(&resp.body, "Howdy!", "prefixed body")?;
+        let resp = send_request(addr, "GET", "/howdy")?;
+        assert_eq_or_err(&resp.status_code, &404, "unprefixed status")?;
+        Ok(())
+    })
+}
+
+fn test_trailing_slash_append(_addr: &str) -> Result<(), String> {
+    with_server(&[("RCOMM_TRAILING_SLASH", "append")], |addr| {
+        let resp = send_request(addr, "GET", "/howdy/howdyagain")?;
+        assert_eq_or_err(&resp.status_code, &301, "status")?;
+        assert_eq_or_err(
+            &resp.headers.get("location").map(String::as_str),
+            &Some("/howdy/howdyagain/"),
+            "location",
+        )?;
+        let resp = send_request(addr, "GET", "/howdy/howdyagain/")?;
+        assert_eq_or_err(&resp.status_code, &200, "canonical status")?;
+        let resp = send_request(addr, "GET", "/howdy/page.css")?;
+        assert_eq_or_err(&resp.status_code, &200, "file status")?;
+        Ok(())
+    })
+}
+
+fn test_missing_not_found_page(_addr: &str) -> Result<(), String> {
+    with_server(&[("RCOMM_NOT_FOUND_PAGE", "pages/does_not_exist.html")], |addr| {
+        let resp = send_request(addr, "GET", "/does-not-exist")?;
+        assert_eq_or_err(&resp.status_code, &404, "status")?;
+        assert_contains_or_err(&resp.body, "404 Not Found", "body")?;
+        let resp = send_request(addr, "GET", "/")?;
+        assert_eq_or_err(&resp.status_code, &200, "server still up")?;
+        Ok(())
+    })
+}
+
+fn test_maintenance_mode(_addr: &str) -> Result<(), String> {
+    let root = env::temp_dir().join(format!("rcomm_it_maintenance_{}", std::process::id()));
+    std::fs::create_dir_all(&root).map_err(|e| e.to_string())?;
+    std::fs::write(root.join("index.html"), "home").map_err(|e| e.to_string())?;
+    let marker = root.join("MAINTENANCE");
+    let root_env = root.to_string_lossy().to_string();
+    let result = with_server(&[("RCOMM_ROOT", &root_env), ("RCOMM_MAINTENANCE_RETRY_AFTER", "120")], |addr| {
+        assert_eq_or_err(&send_request(addr, "GET", "/")?.status_code, &200, "before maintenance")?;
+        std::fs::write(&marker, "").map_err(|e| e.to_string())?;
+        let resp = send_request(addr, "GET", "/")?;
+        assert_eq_or_err(&resp.status_code, &503, "during maintenance")?;
+        assert_eq_or_err(&resp.headers.get("retry-after").map(String::as_str), &Some("120"), "retry-after")?;
+        assert_contains_or_err(&resp.body, "maintenance", "page")?;
+        let health = send_request(addr, "GET", "/healthz")?;
+        assert_eq_or_err(&health.status_code, &200, "healthz during maintenance")?;
+        std::fs::remove_file(&marker).map_err(|e| e.to_string())?;
+        assert_eq_or_err(&send_request(addr, "GET", "/")?.status_code, &200, "after maintenance")?;
+        Ok(())
+    });
+    let _ = std::fs::remove_dir_all(&root);
+    result
+}
+
+fn test_virtual_hosts(_addr: &str) -> Result<(), String> {
+    let base = env::temp_dir().join(format!("rcomm_it_vhosts_{}", std::process::id()));
+    for site in ["alpha", "beta"] {
+        std::fs::create_dir_all(base.join(site)).map_err(|e| e.to_string())?;
+        std::fs::write(base.join(site).join("index.html"), format!("{site} home")).map_err(|e| e.to_string())?;
+    }
+    let vhosts = format!(
+        "alpha.test={},beta.test={}",
+        base.join("alpha").display(),
+        base.join("beta").display()
+    );
+    let result = with_server(&[("RCOMM_VHOSTS", &vhosts)], |addr| {
+        let get = |host: &str| send_raw(addr, &format!("GET / HTTP/1.1\r\nHost: {host}\r\nConnection: close\r\n\r\n"));
+        assert_eq_or_err(&get("alpha.test")?.body.as_str(), &"alpha home", "alpha")?;
+        assert_eq_or_err(&get("BETA.test:8080")?.body.as_str(), &"beta home", "beta, normalized")?;
+        let default = get("other.test")?;
+        assert_eq_or_err(&default.status_code, &200, "default status")?;
+        assert_contains_or_err(&default.body, "<html", "default site")?;
+        Ok(())
+    });
+    let _ = std::fs::remove_dir_all(&base);
+    result
+}
+
+fn test_default_cache_control(addr: &str) -> Result<(), String> {
+    let css = send_request(addr, "GET", "/index.css")?;
+    assert_contains_or_err(
+        css.headers.get("cache-control").map_or("", String::as_str),
+        "max-age=",
+        "css cache-control",
+    )?;
+    let html = send_request(addr, "GET", "/")?;
+    assert_eq_or_err(
+        &html.headers.get("cache-control").map(String::as_str),
+        &Some("no-cache"),
+        "html cache-control",
+    )?;
+    Ok(())
+}
+
+fn test_multiple_bind_addresses(_addr: &str) -> Result<(), String> {
+    with_server(&[("RCOMM_ADDRESS", "127.0.0.1,[::1]")], |addr| {
+        let port = addr.rsplit(':').next().unwrap_or_default();
+        for host in ["127.0.0.1", "[::1]"] {
+            let resp = send_request(&format!("{host}:{port}"), "GET", "/")?;
+            assert_eq_or_err(&resp.status_code, &200, &format!("{host} status"))?;
+        }
+        Ok(())
+    })
+}
+
+fn test_graceful_shutdown_drains_in_flight(_addr: &str) -> Result<(), String> {
+    if !cfg!(unix) {
+        return Ok(());
+    }
+    let port = pick_free_port();
+    let addr = format!("127.0.0.1:{port}");
+    let mut server = start_server_with_env(port, &[("RCOMM_SHUTDOWN_GRACE", "5")]);
+    let result = (|| {
+        wait_for_server(&addr, Duration::from_secs(5))?;
+
+        // Start a request but hold back the blank line that ends its head.
+        let mut stream = TcpStream::connect(&addr).map_err(|e| format!("connect: {e}"))?;
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .map_err(|e| format!("set timeout: {e}"))?;
+        stream
+            .write_all(format!("GET / HTTP/1.1\r\nHost: {addr}\r\n").as_bytes())
+            .map_err(|e| format!("write: {e}"))?;
+        thread::sleep(Duration::from_millis(200));
+
+        let status = Command::new("kill")
+            .args(["-TERM", &server.id().to_string()])
+            .status()
+            .map_err(|e| format!("kill: {e}"))?;
+        assert_eq_or_err(&status.success(), &true, "kill succeeded")?;
+        thread::sleep(Duration::from_millis(500));
+
+        if TcpStream::connect(&addr).is_ok() {
+            return Err("server still accepting after shutdown".to_string());
+        }
+
+        stream.write_all(b"\r\n").map_err(|e| format!("write: {e}"))?;
+        let resp = read_response(&mut stream, "GET")?;
+        assert_eq_or_err(&resp.status_code, &200, "in-flight status")?;
+        assert_eq_or_err(
+            &resp.headers.get("connection").map(String::as_str),
+            &Some("close"),
+            "in-flight connection",
+        )?;
+
+        let start = Instant::now();
+        loop {
+            match server.try_wait().map_err(|e| format!("wait: {e}"))? {
+                Some(status) => {
+                    assert_eq_or_err(&status.success(), &true, "server exit status")?;
+                    return Ok(());
+                }
+                None if start.elapsed() > Duration::from_secs(5) => {
+                    return Err("server did not exit after draining".to_string());
+                }
+                None => thread::sleep(Duration::from_millis(50)),
+            }
+        }
+    })();
+    let _ = server.kill();
+    let _ = server.wait();
+    result
+}
+
+const SOAK_WAVES: usize = 5;
+const SOAK_CONNECTIONS_PER_WAVE: usize = 20;
+const SOAK_KEEPALIVE_REQUESTS: usize = 5;
+const SOAK_MAX_P99: Duration = Duration::from_secs(2);
+
+/// Opens `SOAK_WAVES` waves of concurrent connections, every other one
+/// sending several keep-alive requests before closing. Fails on any error,
+/// dropped connection or 5xx, or when p99 latency exceeds `SOAK_MAX_P99`.
+fn test_soak(addr: &str) -> Result<String, String> {
+    let mut latencies = Vec::new();
+    for wave in 0..SOAK_WAVES {
+        let handles: Vec<_> = (0..SOAK_CONNECTIONS_PER_WAVE)
+            .map(|i| {
+                let addr = addr.to_string();
+                let requests = if i % 2 == 0 { SOAK_KEEPALIVE_REQUESTS } else { 1 };
+                thread::spawn(move || soak_connection(&addr, requests))
+            })
+            .collect();
+        for (i, handle) in handles.into_iter().enumerate() {
+            let timings = handle
+                .join()
+                .map_err(|_| "thread panicked".to_string())?
+                .map_err(|e| format!("wave {wave} connection {i}: {e}"))?;
+            latencies.extend(timings);
+        }
+    }
+
+    latencies.sort();
+    let percentile = |p: usize| latencies[(latencies.len() * p / 100).min(latencies.len() - 1)];
+    let (p50, p99) = (percentile(50), percentile(99));
+    if p99 > SOAK_MAX_P99 {
+        return Err(format!("p99 latency {p99:?} exceeds {SOAK_MAX_P99:?} (p50 {p50:?})"));
+    }
+    Ok(format!("{} requests, p50 {p50:?}, p99 {p99:?}", latencies.len()))
+}
+
+/// Sends `requests` requests over one connection, closing it with the last,
+/// and returns the latency of each.
+fn soak_connection(addr: &str, requests: usize) -> Result<Vec<Duration>, String> {
+    let mut stream = TcpStream::connect(addr).map_err(|e| format!("connect: {e}"))?;
+    stream
+        .set_read_timeout(Some(Duration::from_secs(10)))
+        .map_err(|e| format!("set timeout: {e}"))?;
+    let mut reader = BufReader::new(stream.try_clone().map_err(|e| e.to_string())?);
+    let mut timings = Vec::with_capacity(requests);
+    for n in 1..=requests {
+        let connection = if n == requests { "close" } else { "keep-alive" };
+        let raw = format!("GET / HTTP/1.1\r\nHost: {addr}\r\nConnection: {connection}\r\n\r\n");
+        let start = Instant::now();
+        stream
+            .write_all(raw.as_bytes())
+            .map_err(|e| format!("request {n}: write: {e}"))?;
+        let resp = read_response_from(&mut reader, "GET").map_err(|e| format!("request {n}: {e}"))?;
+        timings.push(start.elapsed());
+        if resp.status_code >= 500 {
+            return Err(format!("request {n}: status {}", resp.status_code));
+        }
+    }
+    Ok(timings)
+}
+
 // ---------------------------------------------------------------------------
 // Main orchestration
 // ---------------------------------------------------------------------------
@@ -353,9 +955,10 @@ fn main() {
     }
     println!("Server is ready.\n");
 
-    let results = vec![
+    let mut results = vec![
         run_test("root_route", || test_root_route(&addr)),
         run_test("index_css", || test_index_css(&addr)),
+        run_test("accept_ranges", || test_accept_ranges(&addr)),
         run_test("howdy_route", || test_howdy_route(&addr)),
         run_test("howdy_page_css", || test_howdy_page_css(&addr)),
         run_test("howdy_howdyagain", || test_howdy_howdyagain(&addr)),
@@ -370,7 +973,40 @@ fn main() {
         run_test("trailing_slash", || test_trailing_slash(&addr)),
         run_test("double_slash", || test_double_slash(&addr)),
         run_test("concurrent_requests", || test_concurrent_requests(&addr)),
+        run_test("cors_preflight", || test_cors_preflight(&addr)),
+        run_test("cors_simple_get", || test_cors_simple_get(&addr)),
+        run_test("keepalive_idle_timeout", || test_keepalive_idle_timeout(&addr)),
+        run_test("pipelined_requests", || test_pipelined_requests(&addr)),
+        run_test("get_with_body_rejected", || test_get_with_body_rejected(&addr)),
+        run_test("head_request", || test_head_request(&addr)),
+        run_test("options_allow", || test_options_allow(&addr)),
+        run_test("pipelined_head_and_get", || test_pipelined_head_and_get(&addr)),
+        run_test("head_get_etag_match", || test_head_get_etag_match(&addr)),
+        run_test("head_compressed_length", || test_head_compressed_length(&addr)),
+        run_test("head_not_found", || test_head_not_found(&addr)),
+        run_test("h2c_upgrade_declined", || test_h2c_upgrade_declined(&addr)),
+        run_test("uri_too_long", || test_uri_too_long(&addr)),
+        run_test("header_too_long", || test_header_too_long(&addr)),
+        run_test("too_many_headers", || test_too_many_headers(&addr)),
+        run_test("body_too_large", || test_body_too_large(&addr)),
+        run_test("connect_not_implemented", || test_connect_not_implemented(&addr)),
+        run_test("get_with_body_allowed", || test_get_with_body_allowed(&addr)),
+        run_test("trailing_slash_strip", || test_trailing_slash_strip(&addr)),
+        run_test("path_prefix", || test_path_prefix(&addr)),
+        run_test("trailing_slash_append", || test_trailing_slash_append(&addr)),
+        run_test("missing_not_found_page", || test_missing_not_found_page(&addr)),
+        run_test("virtual_hosts", || test_virtual_hosts(&addr)),
+        run_test("maintenance_mode", || test_maintenance_mode(&addr)),
+        run_test("default_cache_control", || test_default_cache_control(&addr)),
+        run_test("multiple_bind_addresses", || test_multiple_bind_addresses(&addr)),
+        run_test("graceful_shutdown_drains_in_flight", || {
+            test_graceful_shutdown_drains_in_flight(&addr)
+        }),
     ];
+    // The soak test takes a while, so it only runs when asked for.
+    if env::args().any(|arg| arg == "--soak") {
+        results.push(run_test_with_detail("soak", || test_soak(&addr)));
+    }
 
     let mut passed = 0;
     let mut failed = 0;
@@ -0,0 +1,140 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+};
+
+/// Default byte budget of a `CompressedCache`.
+pub const DEFAULT_CACHE_BUDGET: usize = 16 * 1024 * 1024;
+
+type Key = (String, String);
+
+struct Entry {
+    bytes: Arc<Vec<u8>>,
+    /// Validator (such as the `ETag`) of the source the bytes were encoded from.
+    version: String,
+    /// When the entry was last used; its key in `CompressedCache::order`.
+    tick: u64,
+}
+
+/// Least-recently-used cache of encoded bodies keyed by `(path, encoding)`.
+/// The total size of the cached bytes stays within a fixed budget, and an
+/// entry is dropped as soon as it is looked up with a different source version.
+pub struct CompressedCache {
+    budget: usize,
+    used: usize,
+    next_tick: u64,
+    entries: HashMap<Key, Entry>,
+    order: BTreeMap<u64, Key>,
+}
+
+impl CompressedCache {
+    pub fn new(budget: usize) -> CompressedCache {
+        CompressedCache {
+            budget,
+            used: 0,
+            next_tick: 0,
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+        }
+    }
+
+    /// Total size of the cached bytes.
+    pub fn used(&self) -> usize {
+        self.used
+    }
+
+    /// The bytes cached for `path` in `encoding`, if they were encoded from
+    /// the source at `version`. A stale entry is removed.
+    pub fn get(&mut self, path: &str, encoding: &str, version: &str) -> Option<Arc<Vec<u8>>> {
+        let key = (path.to_string(), encoding.to_string());
+        let entry = self.entries.get(&key)?;
+        if entry.version != version {
+            self.remove(&key);
+            return None;
+        }
+
+        let tick = self.tick();
+        let entry = self.entries.get_mut(&key)?;
+        self.order.remove(&entry.tick);
+        entry.tick = tick;
+        self.order.insert(tick, key);
+        Some(Arc::clone(&entry.bytes))
+    }
+
+    /// Caches `bytes`, evicting the least recently used entries until they
+    /// fit. Anything larger than the whole budget is not cached.
+    pub fn insert(&mut self, path: &str, encoding: &str, version: &str, bytes: Arc<Vec<u8>>) {
+        let key = (path.to_string(), encoding.to_string());
+        self.remove(&key);
+        if bytes.len() > self.budget {
+            return;
+        }
+        while self.used + bytes.len() > self.budget {
+            let Some((_, oldest)) = self.order.pop_first() else { break; };
+            if let Some(evicted) = self.entries.remove(&oldest) {
+                self.used -= evicted.bytes.len();
+            }
+        }
+
+        let tick = self.tick();
+        self.used += bytes.len();
+        self.order.insert(tick, key.clone());
+        self.entries.insert(key, Entry { bytes, version: version.to_string(), tick });
+    }
+
+    fn remove(&mut self, key: &Key) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.order.remove(&entry.tick);
+            self.used -= entry.bytes.len();
+        }
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.next_tick += 1;
+        self.next_tick
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bytes(len: usize) -> Arc<Vec<u8>> {
+        Arc::new(vec![0; len])
+    }
+
+    #[test]
+    fn evicts_least_recently_used_over_budget() {
+        let version = "\"1-a\"";
+        let mut cache = CompressedCache::new(100);
+        cache.insert("/a", "gzip", version, bytes(40));
+        cache.insert("/b", "gzip", version, bytes(40));
+        // Touching /a makes /b the eviction candidate.
+        assert!(cache.get("/a", "gzip", version).is_some());
+        cache.insert("/c", "gzip", version, bytes(40));
+
+        assert!(cache.get("/a", "gzip", version).is_some());
+        assert!(cache.get("/b", "gzip", version).is_none());
+        assert!(cache.get("/c", "gzip", version).is_some());
+        assert_eq!(cache.used(), 80);
+
+        cache.insert("/huge", "gzip", version, bytes(101));
+        assert!(cache.get("/huge", "gzip", version).is_none());
+        assert_eq!(cache.used(), 80);
+    }
+
+    #[test]
+    fn entry_is_invalidated_when_source_changes() {
+        // Same second, same size: only a full-precision validator tells them apart.
+        let before = "\"3b9aca00-1e\"";
+        let after = "\"3b9aca01-1e\"";
+        let mut cache = CompressedCache::new(100);
+        cache.insert("/a", "gzip", before, bytes(30));
+        cache.insert("/a", "br", before, bytes(20));
+
+        assert!(cache.get("/a", "gzip", after).is_none());
+        assert!(cache.get("/a", "gzip", before).is_none());
+        assert!(cache.get("/a", "br", before).is_some());
+        assert_eq!(cache.used(), 20);
+    }
+}
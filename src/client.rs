@@ -0,0 +1,151 @@
+use std::{
+    fmt,
+    io::{self, BufReader, Write},
+    net::TcpStream,
+    time::Duration,
+};
+use crate::models::{
+    http_methods::HttpMethods,
+    http_request::{HttpParseError, HttpRequest},
+    http_response::HttpResponse,
+};
+
+#[derive(Debug)]
+pub enum ClientError {
+    InvalidUrl(String),
+    IoError(io::Error),
+    ParseError(HttpParseError),
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::InvalidUrl(url) => write!(f, "Invalid URL: {url}"),
+            ClientError::IoError(e) => write!(f, "IO error: {e}"),
+            ClientError::ParseError(e) => write!(f, "Bad response: {e}"),
+        }
+    }
+}
+
+/// A minimal blocking HTTP/1.1 client. Each request opens a fresh connection
+/// and sends `Connection: close`. Only plain `http://` URLs are supported.
+pub struct HttpClient {
+    timeout: Option<Duration>,
+}
+
+impl Default for HttpClient {
+    fn default() -> Self {
+        HttpClient::new()
+    }
+}
+
+impl HttpClient {
+    pub fn new() -> HttpClient {
+        HttpClient { timeout: Some(Duration::from_secs(30)) }
+    }
+
+    /// Sets the read and write timeout applied to each connection.
+    pub fn set_timeout(&mut self, timeout: Duration) -> &mut HttpClient {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn get(&self, url: &str) -> Result<HttpResponse, ClientError> {
+        self.request(HttpMethods::GET, url, &[], None)
+    }
+
+    pub fn request(
+        &self,
+        method: HttpMethods,
+        url: &str,
+        headers: &[(&str, &str)],
+        body: Option<Vec<u8>>,
+    ) -> Result<HttpResponse, ClientError> {
+        let (authority, target) = parse_url(url)?;
+
+        let head_only = method == HttpMethods::HEAD;
+        let mut request = HttpRequest::build(method, target.to_string(), String::from("HTTP/1.1"))
+            .with_header("Host".to_string(), authority.to_string())
+            .with_header("Connection".to_string(), "close".to_string());
+        for (title, value) in headers {
+            request.add_header(title.to_string(), value.to_string());
+        }
+        if let Some(body) = body {
+            request.add_body(body);
+        }
+
+        let address = if authority.contains(':') { authority.to_string() } else { format!("{authority}:80") };
+        let mut stream = TcpStream::connect(address).map_err(ClientError::IoError)?;
+        stream.set_read_timeout(self.timeout).map_err(ClientError::IoError)?;
+        stream.set_write_timeout(self.timeout).map_err(ClientError::IoError)?;
+        stream.write_all(&request.as_bytes()).map_err(ClientError::IoError)?;
+
+        let mut reader = BufReader::new(&stream);
+        let response = if head_only {
+            HttpResponse::build_head_from_reader(&mut reader)
+        } else {
+            HttpResponse::build_from_reader(&mut reader)
+        };
+        response.map_err(ClientError::ParseError)
+    }
+}
+
+/// Splits an `http://host[:port][/path]` URL into its authority and target.
+fn parse_url(url: &str) -> Result<(&str, &str), ClientError> {
+    let rest = url
+        .strip_prefix("http://")
+        .ok_or_else(|| ClientError::InvalidUrl(url.to_string()))?;
+    let (authority, target) = match rest.find('/') {
+        Some(i) => rest.split_at(i),
+        None => (rest, "/"),
+    };
+    if authority.is_empty() {
+        return Err(ClientError::InvalidUrl(url.to_string()));
+    }
+    Ok((authority, target))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{net::TcpListener, thread};
+
+    #[test]
+    fn parse_url_splits_authority_and_target() {
+        assert_eq!(parse_url("http://127.0.0.1:7878/howdy").unwrap(), ("127.0.0.1:7878", "/howdy"));
+        assert_eq!(parse_url("http://example.com").unwrap(), ("example.com", "/"));
+        assert!(matches!(parse_url("https://example.com/"), Err(ClientError::InvalidUrl(_))));
+        assert!(matches!(parse_url("http:///path"), Err(ClientError::InvalidUrl(_))));
+    }
+
+    #[test]
+    fn request_round_trips_through_local_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let request = HttpRequest::build_from_stream(&stream).unwrap();
+            let mut response = HttpResponse::build(String::from("HTTP/1.1"), 201);
+            response.add_header("X-Method".to_string(), request.method.to_string());
+            response.add_header("X-Target".to_string(), request.target.clone());
+            response.add_body(request.try_get_body().unwrap_or_default());
+            response.write_to(&mut &stream).unwrap();
+        });
+
+        let resp = HttpClient::new()
+            .request(
+                HttpMethods::POST,
+                &format!("http://{addr}/echo"),
+                &[("Content-Type", "text/plain")],
+                Some(b"ping".to_vec()),
+            )
+            .unwrap();
+        server.join().unwrap();
+
+        assert_eq!(resp.status_code(), 201);
+        assert_eq!(resp.status_phrase(), "Created");
+        assert_eq!(resp.try_get_header("x-method".to_string()), Some("POST".to_string()));
+        assert_eq!(resp.try_get_header("x-target".to_string()), Some("/echo".to_string()));
+        assert_eq!(resp.try_get_body(), Some(b"ping".to_vec()));
+    }
+}
@@ -0,0 +1,258 @@
+use std::sync::{Arc, Mutex};
+use crate::cache::{CompressedCache, DEFAULT_CACHE_BUDGET};
+use crate::gzip;
+use crate::middleware::Middleware;
+use crate::models::{
+    http_request::HttpRequest,
+    http_response::HttpResponse,
+};
+
+/// Bodies this size or smaller are sent as-is: the gzip framing alone is
+/// 18 bytes, and tiny responses rarely shrink enough to be worth the CPU.
+pub const DEFAULT_COMPRESSION_THRESHOLD: usize = 1024;
+
+/// A content coding the server can produce.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Identity,
+    Gzip,
+}
+
+/// Picks the response coding from the request's `Accept-Encoding`
+/// (RFC 9110 §12.5.3). Codings not listed fall under `*` if present and
+/// are otherwise unacceptable, except `identity`, which is acceptable unless
+/// excluded. A zero weight (`gzip;q=0`) forbids a coding. Gzip wins ties.
+/// When everything is forbidden (`*;q=0`) the body is sent unencoded anyway
+/// rather than answering 406.
+pub fn select_encoding(request: &HttpRequest) -> Encoding {
+    let Some(accepted) = request.try_get_header("Accept-Encoding".to_string()) else {
+        return Encoding::Identity;
+    };
+    let weights: Vec<(String, f32)> = accepted
+        .split(',')
+        .filter_map(|coding| {
+            let mut params = coding.split(';');
+            let name = params.next()?.trim().to_ascii_lowercase();
+            let weight = params
+                .find_map(|param| param.trim().strip_prefix("q=").map(|q| q.trim().parse::<f32>().unwrap_or(0.0)))
+                .unwrap_or(1.0);
+            (!name.is_empty()).then_some((name, weight))
+        })
+        .collect();
+    let weight_of = |name: &str| weights.iter().find(|(coding, _)| coding == name).map(|&(_, weight)| weight);
+    let wildcard = weight_of("*");
+
+    let gzip = weight_of("gzip").or(wildcard).unwrap_or(0.0);
+    // Unlisted, identity stays acceptable but is preferred least.
+    let identity = weight_of("identity").or(wildcard).unwrap_or(f32::MIN_POSITIVE);
+    if gzip > 0.0 && gzip >= identity {
+        Encoding::Gzip
+    } else {
+        Encoding::Identity
+    }
+}
+
+/// Middleware that gzips successful responses on the fly when the client
+/// accepts it. Pre-compressed `.gz` sidecars are served by the router and
+/// left alone here. Results for files (responses with an `ETag`) are kept
+/// in a `CompressedCache` until the file changes.
+pub struct Compression {
+    threshold: usize,
+    level: u32,
+    cache: Option<Mutex<CompressedCache>>,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Compression::new()
+    }
+}
+
+impl Compression {
+    pub fn new() -> Compression {
+        Compression {
+            threshold: DEFAULT_COMPRESSION_THRESHOLD,
+            level: gzip::DEFAULT_COMPRESSION_LEVEL,
+            cache: Some(Mutex::new(CompressedCache::new(DEFAULT_CACHE_BUDGET))),
+        }
+    }
+
+    /// Bytes of compressed output kept for reuse; `0` turns the cache off.
+    pub fn set_cache_budget(&mut self, budget: usize) -> &mut Compression {
+        self.cache = (budget > 0).then(|| Mutex::new(CompressedCache::new(budget)));
+        self
+    }
+
+    /// Gzip level, from 1 (fastest) to 9 (smallest).
+    pub fn set_level(&mut self, level: u32) -> &mut Compression {
+        self.level = level;
+        self
+    }
+
+    /// Only bodies longer than `threshold` bytes are compressed.
+    pub fn set_threshold(&mut self, threshold: usize) -> &mut Compression {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Whether a body of `len` bytes with this `Content-Type` is worth
+    /// compressing. Images, fonts and archives are usually compressed
+    /// already, so only text-like types qualify.
+    pub fn should_compress(&self, content_type: &str, len: usize) -> bool {
+        let mime = content_type.split(';').next().unwrap_or("").trim().to_ascii_lowercase();
+        let compressible = mime.starts_with("text/")
+            || mime == "application/json"
+            || mime == "application/javascript";
+        compressible && len > self.threshold
+    }
+
+    /// Gzips `body`, reusing the earlier result for a file that hasn't
+    /// changed since.
+    fn gzip(&self, request: &HttpRequest, response: &HttpResponse, body: &[u8]) -> Vec<u8> {
+        // A file's ETag carries its full-precision mtime and length, unlike
+        // the whole seconds of Last-Modified.
+        let etag = response.try_get_header("ETag".to_string());
+        let (Some(cache), Some(etag)) = (&self.cache, etag) else { return gzip::compress_with_level(body, self.level); };
+
+        let path = request.normalized_path();
+        if let Some(compressed) = cache.lock().unwrap().get(&path, "gzip", &etag) {
+            return compressed.to_vec();
+        }
+        let compressed = Arc::new(gzip::compress_with_level(body, self.level));
+        cache.lock().unwrap().insert(&path, "gzip", &etag, Arc::clone(&compressed));
+        Arc::unwrap_or_clone(compressed)
+    }
+}
+
+impl Middleware for Compression {
+    fn handle(&self, request: &HttpRequest, next: &dyn Fn(&HttpRequest) -> HttpResponse) -> HttpResponse {
+        let mut response = next(request);
+        let encoded = response.try_get_header("Content-Encoding".to_string()).is_some();
+        let Some(content_type) = response.try_get_header("Content-Type".to_string()) else { return response; };
+        if response.status_code() != 200 || encoded || !self.should_compress(&content_type, response.body_len()) {
+            return response;
+        }
+
+        // The representation depends on Accept-Encoding whether or not this client gets gzip.
+        response.append_header("Vary".to_string(), "Accept-Encoding".to_string());
+        if select_encoding(request) == Encoding::Gzip && let Some(body) = response.try_get_body() {
+            let compressed = self.gzip(request, &response, &body);
+            response.add_body(compressed);
+            response.add_header("Content-Encoding".to_string(), "gzip".to_string());
+            // The gzipped bytes are a different representation, so they need their own tag.
+            if let Some(etag) = response.try_get_header("ETag".to_string())
+                && let Some(tag) = etag.strip_suffix('"')
+            {
+                response.add_header("ETag".to_string(), format!("{tag}-gzip\""));
+            }
+        }
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use crate::models::http_methods::HttpMethods;
+    use crate::router::Router;
+
+    fn router_serving(content_type: &'static str, body: Vec<u8>) -> Router {
+        let mut router = Router::new(HashMap::new());
+        router.set_fallback(move |_request| {
+            let mut response = HttpResponse::build(String::from("HTTP/1.1"), 200);
+            response.add_header("Content-Type".to_string(), content_type.to_string());
+            response.add_body(body.clone());
+            response
+        });
+        router.add_middleware(Compression::new());
+        router
+    }
+
+    fn get_accepting_gzip() -> HttpRequest {
+        accepting("gzip, deflate")
+    }
+
+    fn accepting(value: &str) -> HttpRequest {
+        HttpRequest::build(HttpMethods::GET, "/".to_string(), "HTTP/1.1".to_string())
+            .with_header("Accept-Encoding".to_string(), value.to_string())
+    }
+
+    #[test]
+    fn select_encoding_honours_identity_wildcard_and_exclusions() {
+        let cases = [
+            ("gzip", Encoding::Gzip),
+            ("*", Encoding::Gzip),
+            ("deflate, gzip;q=0.5", Encoding::Gzip),
+            ("identity", Encoding::Identity),
+            ("gzip;q=0, *", Encoding::Identity),
+            ("*;q=0, identity;q=1", Encoding::Identity),
+            ("*;q=0", Encoding::Identity),
+            ("gzip;q=0.5, identity;q=0.8", Encoding::Identity),
+            ("br", Encoding::Identity),
+        ];
+        for (accept, expected) in cases {
+            assert_eq!(select_encoding(&accepting(accept)), expected, "{accept}");
+        }
+        let no_header = HttpRequest::build(HttpMethods::GET, "/".to_string(), "HTTP/1.1".to_string());
+        assert_eq!(select_encoding(&no_header), Encoding::Identity);
+    }
+
+    #[test]
+    fn should_compress_needs_text_like_type_above_threshold() {
+        let compression = Compression::new();
+        assert!(compression.should_compress("text/html; charset=utf-8", 4096));
+        assert!(compression.should_compress("application/json", 4096));
+        assert!(compression.should_compress("application/javascript", 4096));
+        assert!(!compression.should_compress("text/html", 100));
+        assert!(!compression.should_compress("image/png", 4096));
+    }
+
+    #[test]
+    fn small_responses_and_images_are_not_compressed() {
+        for (content_type, body) in [("text/html; charset=utf-8", vec![b'a'; 100]), ("image/png", vec![0; 8192])] {
+            let resp = router_serving(content_type, body.clone()).handle(&get_accepting_gzip());
+            assert_eq!(resp.try_get_header("content-encoding".to_string()), None);
+            assert_eq!(resp.try_get_body(), Some(body));
+        }
+    }
+
+    #[test]
+    fn large_html_is_compressed() {
+        let html = "<p>Lorem ipsum dolor sit amet.</p>\n".repeat(200).into_bytes();
+        let resp = router_serving("text/html; charset=utf-8", html.clone()).handle(&get_accepting_gzip());
+
+        assert_eq!(resp.try_get_header("content-encoding".to_string()), Some("gzip".to_string()));
+        assert_eq!(resp.try_get_header("vary".to_string()), Some("Accept-Encoding".to_string()));
+        let body = resp.try_get_body().unwrap();
+        assert_eq!(resp.try_get_header("content-length".to_string()), Some(body.len().to_string()));
+        assert_eq!(gzip::decompress(&body, html.len()).unwrap(), html);
+    }
+
+    #[test]
+    fn cached_gzip_is_revalidated_against_etag() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        // Two versions written within the same second share a Last-Modified.
+        let version = Arc::new(AtomicUsize::new(0));
+        let current = Arc::clone(&version);
+        let mut router = Router::new(HashMap::new());
+        router.set_fallback(move |_request| {
+            let v = current.load(Ordering::SeqCst);
+            let mut response = HttpResponse::build(String::from("HTTP/1.1"), 200);
+            response.add_header("Content-Type".to_string(), "text/plain".to_string());
+            response.add_header("Last-Modified".to_string(), "Sun, 06 Nov 1994 08:49:37 GMT".to_string());
+            response.add_header("ETag".to_string(), format!("\"3b9aca0{v}-1000\""));
+            response.add_body(vec![b'a' + v as u8; 4096]);
+            response
+        });
+        router.add_middleware(Compression::new());
+
+        let first = router.handle(&get_accepting_gzip()).try_get_body().unwrap();
+        version.store(1, Ordering::SeqCst);
+        let second = router.handle(&get_accepting_gzip()).try_get_body().unwrap();
+
+        assert_eq!(gzip::decompress(&first, 4096).unwrap(), vec![b'a'; 4096]);
+        assert_eq!(gzip::decompress(&second, 4096).unwrap(), vec![b'b'; 4096]);
+    }
+}
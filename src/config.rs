@@ -0,0 +1,447 @@
+use std::{
+    path::PathBuf,
+    time::Duration,
+};
+use crate::access_log::{LogFormat, log_format_from_string};
+use crate::cache::DEFAULT_CACHE_BUDGET;
+use crate::compression::DEFAULT_COMPRESSION_THRESHOLD;
+use crate::gzip::DEFAULT_COMPRESSION_LEVEL;
+use crate::forwarded::{Cidr, parse_cidr_list};
+use crate::logger::{LogLevel, log_level_from_string};
+use crate::proxy::DEFAULT_UPSTREAM_TIMEOUT;
+use crate::models::http_request::{
+    DEFAULT_HEADER_DEADLINE, MAX_BODY_LEN, MAX_HEADER_LINE_LEN, MAX_REQUEST_LINE_LEN, normalize_host,
+};
+use crate::models::http_response::DEFAULT_SPILL_THRESHOLD;
+use crate::router::{TrailingSlash, trailing_slash_from_string};
+use crate::template::UnknownPlaceholder;
+
+/// Every tunable of a server started with `serve`.
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    /// Comma-separated addresses to listen on; IPv6 literals may be
+    /// bracketed (`[::1]`) or bare.
+    pub address: String,
+    /// `0` lets the OS pick; see `ServerHandle::local_addrs`.
+    pub port: u16,
+    /// Listen backlog; `None` keeps the OS default. Only honoured with the
+    /// `backlog` feature.
+    pub backlog: Option<u32>,
+    /// Document roots in priority order; earlier roots shadow later ones.
+    pub roots: Vec<PathBuf>,
+    /// Hostnames served from their own document root instead of `roots`,
+    /// chosen by the request's `Host`.
+    pub vhosts: Vec<(String, PathBuf)>,
+    /// Answer a `Host` missing from `vhosts` with `421` rather than the
+    /// `roots` site.
+    pub strict_vhosts: bool,
+    /// `host:port` that requests matching no route are forwarded to.
+    pub proxy_upstream: Option<String>,
+    /// How long the upstream may take to connect or send data before the
+    /// client gets a `504`.
+    pub proxy_timeout: Duration,
+    /// Defaults to the first `not_found.html` found across `roots`.
+    pub not_found_page: Option<PathBuf>,
+    pub workers: usize,
+    /// Largest request body buffered in memory, before and after decoding.
+    pub max_body: usize,
+    /// Longest request line (method, URL and version) accepted; longer gets 414.
+    pub max_request_line: usize,
+    /// Longest single header line accepted; longer gets 431.
+    pub max_header_line: usize,
+    /// Bytes buffered when reading requests from a connection.
+    pub read_buffer: usize,
+    /// Bytes buffered when writing a response to a connection.
+    pub write_buffer: usize,
+    /// Size past which a body built with `extend_body` moves to a temp file.
+    pub spill_threshold: usize,
+    pub log_level: LogLevel,
+    pub log_format: LogFormat,
+    /// Warn about unreadable or empty routed files at startup.
+    pub check_routes: bool,
+    pub trailing_slash: TrailingSlash,
+    /// Mount point stripped from request paths before routing, e.g. `/app`.
+    pub path_prefix: Option<String>,
+    /// Send requests outside `path_prefix` to the 404 page.
+    pub require_path_prefix: bool,
+    /// Rescan `roots` this often and reload the routes when files change.
+    pub watch_interval: Option<Duration>,
+    /// Serve the route table at `/__routes`.
+    pub expose_routes: bool,
+    /// Serve traffic and response counters at `/metrics`.
+    pub expose_metrics: bool,
+    /// Include the panic message in the body of a 500 from a panicking handler.
+    pub debug_errors: bool,
+    /// Add `X-Conn-Requests`, the request's position on its connection.
+    pub debug_connections: bool,
+    /// Answer everything but `/healthz` with `503`, as if a `MAINTENANCE` file existed.
+    pub maintenance: bool,
+    /// The `Retry-After` sent with maintenance `503`s.
+    pub maintenance_retry_after: Duration,
+    /// Render `{{ year }}` placeholders in HTML, treating unknown ones as given.
+    pub templates: Option<UnknownPlaceholder>,
+    /// Origins allowed to make cross-origin requests; empty disables CORS.
+    pub cors_origins: Vec<String>,
+    /// How long a persistent connection may sit idle between requests.
+    pub keepalive_timeout: Duration,
+    /// Requests served on one connection before it is closed.
+    pub max_requests_per_conn: usize,
+    /// How long a single read may stall once a request has started arriving.
+    pub request_timeout: Duration,
+    /// How long the request line and headers may take to arrive in total.
+    pub header_timeout: Duration,
+    /// How long `ServerHandle::shutdown` lets in-flight requests finish.
+    pub shutdown_grace: Duration,
+    /// Answer GET/HEAD/DELETE requests that carry a body with 400.
+    pub reject_unexpected_bodies: bool,
+    /// Request paths whose bodies are streamed to a file in `upload_dir`.
+    pub upload_routes: Vec<String>,
+    pub upload_dir: PathBuf,
+    /// Add `default_cache_control` headers to successful responses.
+    pub cache_control: bool,
+    /// Peers allowed to report the client address via `X-Forwarded-For`.
+    pub trusted_proxies: Vec<Cidr>,
+    /// Let PUT and DELETE replace and remove routed files.
+    pub writable: bool,
+    /// Gzip text responses on the fly for clients that accept it.
+    pub compression: bool,
+    /// Bodies this size or smaller are never compressed.
+    pub compression_threshold: usize,
+    /// Bytes of compressed files kept for reuse; `0` disables the cache.
+    pub compression_cache: usize,
+    /// Gzip level, 1 (fastest) to 9 (smallest); others are replaced by the
+    /// default at startup with a warning.
+    pub compression_level: u32,
+}
+
+/// Default capacity of the per-connection read and write buffers, matching
+/// std's `BufReader`/`BufWriter`.
+pub const DEFAULT_BUFFER_SIZE: usize = 8 * 1024;
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            address: String::from("127.0.0.1"),
+            port: 7878,
+            backlog: None,
+            roots: vec![PathBuf::from("./pages")],
+            vhosts: Vec::new(),
+            strict_vhosts: false,
+            proxy_upstream: None,
+            proxy_timeout: DEFAULT_UPSTREAM_TIMEOUT,
+            not_found_page: None,
+            workers: 4,
+            max_body: MAX_BODY_LEN,
+            max_request_line: MAX_REQUEST_LINE_LEN,
+            max_header_line: MAX_HEADER_LINE_LEN,
+            read_buffer: DEFAULT_BUFFER_SIZE,
+            write_buffer: DEFAULT_BUFFER_SIZE,
+            spill_threshold: DEFAULT_SPILL_THRESHOLD,
+            log_level: LogLevel::Info,
+            log_format: LogFormat::Plain,
+            check_routes: true,
+            trailing_slash: TrailingSlash::Ignore,
+            path_prefix: None,
+            require_path_prefix: true,
+            watch_interval: None,
+            expose_routes: false,
+            expose_metrics: false,
+            debug_errors: false,
+            debug_connections: false,
+            maintenance: false,
+            maintenance_retry_after: Duration::from_secs(300),
+            templates: None,
+            cors_origins: Vec::new(),
+            keepalive_timeout: Duration::from_secs(5),
+            max_requests_per_conn: 100,
+            request_timeout: Duration::from_secs(30),
+            header_timeout: DEFAULT_HEADER_DEADLINE,
+            shutdown_grace: Duration::from_secs(10),
+            reject_unexpected_bodies: true,
+            upload_routes: Vec::new(),
+            upload_dir: std::env::temp_dir(),
+            cache_control: true,
+            trusted_proxies: Vec::new(),
+            writable: false,
+            compression: false,
+            compression_threshold: DEFAULT_COMPRESSION_THRESHOLD,
+            compression_cache: DEFAULT_CACHE_BUDGET,
+            compression_level: DEFAULT_COMPRESSION_LEVEL,
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Reads the `RCOMM_*` environment variables documented in the README.
+    /// Unset or unparseable values fall back to the defaults.
+    pub fn from_env() -> ServerConfig {
+        ServerConfig::from_vars(|name| std::env::var(name).ok())
+    }
+
+    fn from_vars<F: Fn(&str) -> Option<String>>(var: F) -> ServerConfig {
+        let defaults = ServerConfig::default();
+        let number = |name: &str| var(name).and_then(|value| value.trim().parse::<usize>().ok()).filter(|&n| n > 0);
+        let seconds = |name: &str, default: Duration| {
+            number(name).map_or(default, |secs| Duration::from_secs(secs as u64))
+        };
+        let flag = |name: &str, default: bool| match var(name).as_deref() {
+            Some("1") | Some("true") | Some("on") => true,
+            Some("0") | Some("false") | Some("off") => false,
+            _ => default,
+        };
+
+        ServerConfig {
+            address: var("RCOMM_ADDRESS").unwrap_or(defaults.address),
+            port: var("RCOMM_PORT").and_then(|port| port.trim().parse().ok()).unwrap_or(defaults.port),
+            backlog: number("RCOMM_BACKLOG").map(|backlog| backlog.min(i32::MAX as usize) as u32),
+            roots: var("RCOMM_ROOT")
+                .map(|roots| parse_roots(&roots))
+                .filter(|roots| !roots.is_empty())
+                .unwrap_or(defaults.roots),
+            vhosts: var("RCOMM_VHOSTS").map(|vhosts| parse_vhosts(&vhosts)).unwrap_or_default(),
+            strict_vhosts: flag("RCOMM_VHOSTS_STRICT", defaults.strict_vhosts),
+            proxy_upstream: var("RCOMM_PROXY")
+                .map(|upstream| upstream.trim().to_string())
+                .filter(|upstream| !upstream.is_empty()),
+            proxy_timeout: seconds("RCOMM_PROXY_TIMEOUT", defaults.proxy_timeout),
+            not_found_page: var("RCOMM_NOT_FOUND_PAGE").map(PathBuf::from),
+            workers: number("RCOMM_WORKERS").unwrap_or(defaults.workers),
+            max_body: number("RCOMM_MAX_BODY").unwrap_or(defaults.max_body),
+            max_request_line: number("RCOMM_MAX_REQUEST_LINE").unwrap_or(defaults.max_request_line),
+            max_header_line: number("RCOMM_MAX_HEADER_LINE").unwrap_or(defaults.max_header_line),
+            read_buffer: number("RCOMM_READ_BUFFER").unwrap_or(defaults.read_buffer),
+            write_buffer: number("RCOMM_WRITE_BUFFER").unwrap_or(defaults.write_buffer),
+            spill_threshold: number("RCOMM_SPILL_THRESHOLD").unwrap_or(defaults.spill_threshold),
+            log_level: var("RCOMM_LOG_LEVEL")
+                .and_then(|level| log_level_from_string(&level))
+                .unwrap_or(defaults.log_level),
+            log_format: var("RCOMM_LOG_FORMAT")
+                .and_then(|format| log_format_from_string(&format))
+                .unwrap_or(defaults.log_format),
+            check_routes: flag("RCOMM_CHECK_ROUTES", defaults.check_routes),
+            trailing_slash: var("RCOMM_TRAILING_SLASH")
+                .and_then(|policy| trailing_slash_from_string(&policy))
+                .unwrap_or(defaults.trailing_slash),
+            path_prefix: var("RCOMM_PATH_PREFIX").filter(|prefix| !prefix.trim_matches('/').is_empty()),
+            require_path_prefix: flag("RCOMM_REQUIRE_PATH_PREFIX", defaults.require_path_prefix),
+            watch_interval: number("RCOMM_WATCH_INTERVAL").map(|secs| Duration::from_secs(secs as u64)),
+            expose_routes: flag("RCOMM_DEBUG_ROUTES", defaults.expose_routes),
+            expose_metrics: flag("RCOMM_METRICS", defaults.expose_metrics),
+            debug_errors: flag("RCOMM_DEBUG_ERRORS", defaults.debug_errors),
+            debug_connections: flag("RCOMM_DEBUG_CONNECTIONS", defaults.debug_connections),
+            maintenance: flag("RCOMM_MAINTENANCE", defaults.maintenance),
+            maintenance_retry_after: seconds("RCOMM_MAINTENANCE_RETRY_AFTER", defaults.maintenance_retry_after),
+            templates: match var("RCOMM_TEMPLATES").as_deref() {
+                Some("strict") => Some(UnknownPlaceholder::Error),
+                _ if flag("RCOMM_TEMPLATES", false) => Some(UnknownPlaceholder::Keep),
+                _ => defaults.templates,
+            },
+            cors_origins: var("RCOMM_CORS_ORIGINS").map(|origins| split_list(&origins)).unwrap_or_default(),
+            keepalive_timeout: seconds("RCOMM_KEEPALIVE_TIMEOUT", defaults.keepalive_timeout),
+            max_requests_per_conn: number("RCOMM_MAX_REQUESTS_PER_CONN").unwrap_or(defaults.max_requests_per_conn),
+            request_timeout: seconds("RCOMM_REQUEST_TIMEOUT", defaults.request_timeout),
+            header_timeout: seconds("RCOMM_HEADER_TIMEOUT", defaults.header_timeout),
+            shutdown_grace: seconds("RCOMM_SHUTDOWN_GRACE", defaults.shutdown_grace),
+            reject_unexpected_bodies: !flag("RCOMM_ALLOW_GET_BODY", !defaults.reject_unexpected_bodies),
+            upload_routes: var("RCOMM_UPLOAD_ROUTES").map(|routes| split_list(&routes)).unwrap_or_default(),
+            upload_dir: var("RCOMM_UPLOAD_DIR").map(PathBuf::from).unwrap_or(defaults.upload_dir),
+            cache_control: flag("RCOMM_CACHE_CONTROL", defaults.cache_control),
+            trusted_proxies: var("RCOMM_TRUSTED_PROXIES").map(|list| parse_cidr_list(&list)).unwrap_or_default(),
+            writable: flag("RCOMM_WRITABLE", defaults.writable),
+            compression: flag("RCOMM_COMPRESSION", defaults.compression),
+            compression_threshold: var("RCOMM_COMPRESSION_THRESHOLD")
+                .and_then(|threshold| threshold.trim().parse().ok())
+                .unwrap_or(defaults.compression_threshold),
+            compression_cache: var("RCOMM_COMPRESSION_CACHE")
+                .and_then(|budget| budget.trim().parse().ok())
+                .unwrap_or(defaults.compression_cache),
+            compression_level: var("RCOMM_COMPRESSION_LEVEL")
+                .and_then(|level| level.trim().parse().ok())
+                .unwrap_or(defaults.compression_level),
+        }
+    }
+}
+
+/// `RCOMM_ROOT` is a list separated like `PATH` (`:` on Unix).
+fn parse_roots(roots: &str) -> Vec<PathBuf> {
+    std::env::split_paths(roots).filter(|root| !root.as_os_str().is_empty()).collect()
+}
+
+/// `RCOMM_VHOSTS` is a comma-separated list of `host=root` pairs. Pairs
+/// without a `=` or with an empty side are skipped.
+fn parse_vhosts(list: &str) -> Vec<(String, PathBuf)> {
+    split_list(list)
+        .iter()
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(host, root)| (normalize_host(host), PathBuf::from(root.trim())))
+        .filter(|(host, root)| !host.is_empty() && !root.as_os_str().is_empty())
+        .collect()
+}
+
+fn split_list(list: &str) -> Vec<String> {
+    list.split(',')
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn from_pairs(pairs: &[(&str, &str)]) -> ServerConfig {
+        let vars: HashMap<String, String> = pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        ServerConfig::from_vars(|name| vars.get(name).cloned())
+    }
+
+    #[test]
+    fn from_vars_parses_valid_values() {
+        let config = from_pairs(&[
+            ("RCOMM_ADDRESS", "0.0.0.0,[::1]"),
+            ("RCOMM_PORT", "8080"),
+            ("RCOMM_BACKLOG", "1024"),
+            ("RCOMM_ROOT", "tenant:pages"),
+            ("RCOMM_VHOSTS", "A.test=/srv/a, b.test:8080 = /srv/b, broken, =/srv/c"),
+            ("RCOMM_VHOSTS_STRICT", "1"),
+            ("RCOMM_PROXY", "127.0.0.1:9000"),
+            ("RCOMM_PROXY_TIMEOUT", "5"),
+            ("RCOMM_WORKERS", "8"),
+            ("RCOMM_MAX_BODY", "1024"),
+            ("RCOMM_MAX_REQUEST_LINE", "65536"),
+            ("RCOMM_MAX_HEADER_LINE", "4096"),
+            ("RCOMM_READ_BUFFER", "65536"),
+            ("RCOMM_WRITE_BUFFER", "131072"),
+            ("RCOMM_SPILL_THRESHOLD", "1048576"),
+            ("RCOMM_LOG_LEVEL", "debug"),
+            ("RCOMM_LOG_FORMAT", "json"),
+            ("RCOMM_TRAILING_SLASH", "strip"),
+            ("RCOMM_PATH_PREFIX", "/app"),
+            ("RCOMM_REQUIRE_PATH_PREFIX", "0"),
+            ("RCOMM_WATCH_INTERVAL", "2"),
+            ("RCOMM_DEBUG_ROUTES", "1"),
+            ("RCOMM_METRICS", "1"),
+            ("RCOMM_DEBUG_ERRORS", "1"),
+            ("RCOMM_DEBUG_CONNECTIONS", "1"),
+            ("RCOMM_MAINTENANCE", "1"),
+            ("RCOMM_MAINTENANCE_RETRY_AFTER", "60"),
+            ("RCOMM_TEMPLATES", "strict"),
+            ("RCOMM_CORS_ORIGINS", "https://a.test, https://b.test,"),
+            ("RCOMM_KEEPALIVE_TIMEOUT", "2"),
+            ("RCOMM_MAX_REQUESTS_PER_CONN", "10"),
+            ("RCOMM_HEADER_TIMEOUT", "3"),
+            ("RCOMM_ALLOW_GET_BODY", "1"),
+            ("RCOMM_UPLOAD_ROUTES", "/upload"),
+            ("RCOMM_CACHE_CONTROL", "0"),
+            ("RCOMM_TRUSTED_PROXIES", "10.0.0.0/8"),
+            ("RCOMM_WRITABLE", "true"),
+            ("RCOMM_COMPRESSION", "on"),
+            ("RCOMM_COMPRESSION_THRESHOLD", "0"),
+            ("RCOMM_COMPRESSION_CACHE", "0"),
+            ("RCOMM_COMPRESSION_LEVEL", "9"),
+        ]);
+
+        assert_eq!(config.address, "0.0.0.0,[::1]");
+        assert_eq!(config.port, 8080);
+        assert_eq!(config.backlog, Some(1024));
+        assert_eq!(config.roots, vec![PathBuf::from("tenant"), PathBuf::from("pages")]);
+        assert_eq!(
+            config.vhosts,
+            vec![
+                (String::from("a.test"), PathBuf::from("/srv/a")),
+                (String::from("b.test"), PathBuf::from("/srv/b")),
+            ]
+        );
+        assert!(config.strict_vhosts);
+        assert_eq!(config.proxy_upstream.as_deref(), Some("127.0.0.1:9000"));
+        assert_eq!(config.proxy_timeout, Duration::from_secs(5));
+        assert_eq!(config.workers, 8);
+        assert_eq!(config.max_body, 1024);
+        assert_eq!(config.max_request_line, 65536);
+        assert_eq!(config.max_header_line, 4096);
+        assert_eq!(config.read_buffer, 65536);
+        assert_eq!(config.write_buffer, 131072);
+        assert_eq!(config.spill_threshold, 1048576);
+        assert_eq!(config.log_level, LogLevel::Debug);
+        assert_eq!(config.log_format, LogFormat::Json);
+        assert_eq!(config.trailing_slash, TrailingSlash::Strip);
+        assert_eq!(config.path_prefix.as_deref(), Some("/app"));
+        assert!(!config.require_path_prefix);
+        assert_eq!(config.watch_interval, Some(Duration::from_secs(2)));
+        assert!(config.expose_routes);
+        assert!(config.expose_metrics);
+        assert!(config.debug_errors);
+        assert!(config.debug_connections);
+        assert!(config.maintenance);
+        assert_eq!(config.maintenance_retry_after, Duration::from_secs(60));
+        assert_eq!(config.templates, Some(UnknownPlaceholder::Error));
+        assert_eq!(config.cors_origins, vec!["https://a.test", "https://b.test"]);
+        assert_eq!(config.keepalive_timeout, Duration::from_secs(2));
+        assert_eq!(config.max_requests_per_conn, 10);
+        assert_eq!(config.header_timeout, Duration::from_secs(3));
+        assert!(!config.reject_unexpected_bodies);
+        assert_eq!(config.upload_routes, vec!["/upload"]);
+        assert!(!config.cache_control);
+        assert_eq!(config.trusted_proxies.len(), 1);
+        assert!(config.writable);
+        assert!(config.compression);
+        assert_eq!(config.compression_threshold, 0);
+        assert_eq!(config.compression_cache, 0);
+        assert_eq!(config.compression_level, 9);
+    }
+
+    #[test]
+    fn from_vars_falls_back_on_invalid_values() {
+        let config = from_pairs(&[
+            ("RCOMM_PORT", "http"),
+            ("RCOMM_BACKLOG", "0"),
+            ("RCOMM_ROOT", ""),
+            ("RCOMM_WORKERS", "0"),
+            ("RCOMM_MAX_BODY", "-5"),
+            ("RCOMM_LOG_LEVEL", "loud"),
+            ("RCOMM_LOG_FORMAT", "xml"),
+            ("RCOMM_TRAILING_SLASH", "sideways"),
+            ("RCOMM_DEBUG_ROUTES", "yes please"),
+            ("RCOMM_TEMPLATES", "maybe"),
+            ("RCOMM_KEEPALIVE_TIMEOUT", "0"),
+            ("RCOMM_MAX_REQUESTS_PER_CONN", "0"),
+            ("RCOMM_REQUEST_TIMEOUT", "soon"),
+            ("RCOMM_CACHE_CONTROL", "nope"),
+            ("RCOMM_COMPRESSION_THRESHOLD", "1k"),
+            ("RCOMM_COMPRESSION_LEVEL", "best"),
+        ]);
+        let defaults = ServerConfig::default();
+
+        assert_eq!(config.port, defaults.port);
+        assert_eq!(config.backlog, None);
+        assert_eq!(config.roots, defaults.roots);
+        assert_eq!(config.workers, defaults.workers);
+        assert_eq!(config.max_body, defaults.max_body);
+        assert_eq!(config.log_level, defaults.log_level);
+        assert_eq!(config.log_format, defaults.log_format);
+        assert_eq!(config.trailing_slash, defaults.trailing_slash);
+        assert!(!config.expose_routes);
+        assert_eq!(config.templates, None);
+        assert_eq!(config.keepalive_timeout, defaults.keepalive_timeout);
+        assert_eq!(config.max_requests_per_conn, defaults.max_requests_per_conn);
+        assert_eq!(config.request_timeout, defaults.request_timeout);
+        assert!(config.cache_control);
+        assert_eq!(config.compression_threshold, defaults.compression_threshold);
+        assert_eq!(config.compression_level, defaults.compression_level);
+    }
+
+    #[test]
+    fn from_vars_with_nothing_set_matches_default() {
+        let config = from_pairs(&[]);
+        let defaults = ServerConfig::default();
+        assert_eq!(config.address, defaults.address);
+        assert_eq!(config.port, defaults.port);
+        assert_eq!(config.not_found_page, None);
+        assert_eq!(config.header_timeout, DEFAULT_HEADER_DEADLINE);
+        assert!(config.reject_unexpected_bodies);
+        assert!(config.cors_origins.is_empty());
+    }
+}
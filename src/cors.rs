@@ -0,0 +1,129 @@
+use crate::models::{
+    http_methods::HttpMethods,
+    http_request::HttpRequest,
+    http_response::HttpResponse,
+};
+
+pub struct CorsConfig {
+    pub allowed_origins: Vec<String>,
+    pub allowed_methods: Vec<String>,
+    pub allowed_headers: Vec<String>,
+    pub max_age: Option<u32>,
+}
+
+impl CorsConfig {
+    /// Creates a policy for `allowed_origins` (use `"*"` to allow any origin)
+    /// permitting simple read-only requests.
+    pub fn new(allowed_origins: Vec<String>) -> CorsConfig {
+        CorsConfig {
+            allowed_origins,
+            allowed_methods: vec![String::from("GET"), String::from("HEAD"), String::from("OPTIONS")],
+            allowed_headers: vec![String::from("Content-Type")],
+            max_age: Some(600),
+        }
+    }
+
+    pub fn is_preflight(request: &HttpRequest) -> bool {
+        request.method == HttpMethods::OPTIONS
+            && request.try_get_header("Origin".to_string()).is_some()
+            && request.try_get_header("Access-Control-Request-Method".to_string()).is_some()
+    }
+
+    /// Returns the `Access-Control-Allow-Origin` value for `origin`: `*` when
+    /// any origin is allowed, the origin itself when allowlisted, else `None`.
+    pub fn allow_origin(&self, origin: &str) -> Option<String> {
+        if self.allowed_origins.iter().any(|allowed| allowed == "*") {
+            Some(String::from("*"))
+        } else if self.allowed_origins.iter().any(|allowed| allowed == origin) {
+            Some(origin.to_string())
+        } else {
+            None
+        }
+    }
+
+    /// Adds `Access-Control-Allow-Origin` to `response` when the request
+    /// carries an allowed `Origin`.
+    pub fn apply(&self, request: &HttpRequest, response: &mut HttpResponse) {
+        let Some(origin) = request.try_get_header("Origin".to_string()) else { return; };
+        let Some(allowed) = self.allow_origin(&origin) else { return; };
+        if allowed != "*" {
+            response.append_header("Vary".to_string(), "Origin".to_string());
+        }
+        response.add_header("Access-Control-Allow-Origin".to_string(), allowed);
+    }
+
+    pub fn preflight_response(&self, request: &HttpRequest) -> HttpResponse {
+        let mut response = HttpResponse::build(String::from("HTTP/1.1"), 204);
+        let origin = request.try_get_header("Origin".to_string()).unwrap_or_default();
+        if self.allow_origin(&origin).is_none() {
+            return response;
+        }
+
+        response.add_header("Access-Control-Allow-Methods".to_string(), self.allowed_methods.join(", "));
+        response.add_header("Access-Control-Allow-Headers".to_string(), self.allowed_headers.join(", "));
+        if let Some(max_age) = self.max_age {
+            response.add_header("Access-Control-Max-Age".to_string(), max_age.to_string());
+        }
+        self.apply(request, &mut response);
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(method: HttpMethods, headers: &[(&str, &str)]) -> HttpRequest {
+        let mut req = HttpRequest::build(method, "/".to_string(), "HTTP/1.1".to_string());
+        for (title, value) in headers {
+            req.add_header(title.to_string(), value.to_string());
+        }
+        req
+    }
+
+    #[test]
+    fn allow_origin_echoes_allowlisted_origin() {
+        let cors = CorsConfig::new(vec![String::from("https://a.example")]);
+        assert_eq!(cors.allow_origin("https://a.example"), Some(String::from("https://a.example")));
+        assert_eq!(cors.allow_origin("https://b.example"), None);
+    }
+
+    #[test]
+    fn allow_origin_reflects_wildcard() {
+        let cors = CorsConfig::new(vec![String::from("*")]);
+        assert_eq!(cors.allow_origin("https://b.example"), Some(String::from("*")));
+    }
+
+    #[test]
+    fn is_preflight_requires_options_origin_and_request_method() {
+        let preflight = request(HttpMethods::OPTIONS, &[
+            ("Origin", "https://a.example"),
+            ("Access-Control-Request-Method", "GET"),
+        ]);
+        let plain_options = request(HttpMethods::OPTIONS, &[("Origin", "https://a.example")]);
+        assert!(CorsConfig::is_preflight(&preflight));
+        assert!(!CorsConfig::is_preflight(&plain_options));
+    }
+
+    #[test]
+    fn preflight_response_lists_allowed_methods() {
+        let cors = CorsConfig::new(vec![String::from("https://a.example")]);
+        let req = request(HttpMethods::OPTIONS, &[
+            ("Origin", "https://a.example"),
+            ("Access-Control-Request-Method", "GET"),
+        ]);
+        let resp = cors.preflight_response(&req);
+        assert!(format!("{resp}").starts_with("HTTP/1.1 204 No Content\r\n"));
+        assert_eq!(resp.try_get_header("access-control-allow-origin".to_string()), Some(String::from("https://a.example")));
+        assert_eq!(resp.try_get_header("access-control-allow-methods".to_string()), Some(String::from("GET, HEAD, OPTIONS")));
+    }
+
+    #[test]
+    fn apply_skips_disallowed_origin() {
+        let cors = CorsConfig::new(vec![String::from("https://a.example")]);
+        let req = request(HttpMethods::GET, &[("Origin", "https://evil.example")]);
+        let mut resp = HttpResponse::build(String::from("HTTP/1.1"), 200);
+        cors.apply(&req, &mut resp);
+        assert_eq!(resp.try_get_header("access-control-allow-origin".to_string()), None);
+    }
+}
@@ -0,0 +1,41 @@
+//! Pages compiled into the binary by `build.rs` when the `embed` feature is
+//! on, so the server can run without its `pages/` directory.
+
+use crate::router::Routes;
+
+include!(concat!(env!("OUT_DIR"), "/embedded_pages.rs"));
+
+/// Contents of the embedded file at `name`, relative to the embedded root
+/// (e.g. `howdy/page.css`).
+pub fn file(name: &str) -> Option<&'static [u8]> {
+    EMBEDDED_FILES.iter().find(|(path, _)| *path == name).map(|(_, contents)| *contents)
+}
+
+/// Routes for the embedded files, named by the same conventions as
+/// `build_routes`.
+pub fn routes() -> Routes {
+    Routes::from_virtual_files(
+        EMBEDDED_FILES.iter().map(|(path, contents)| (path.to_string(), contents.to_vec())).collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use crate::models::http_methods::HttpMethods;
+    use crate::models::http_request::HttpRequest;
+    use crate::router::Router;
+
+    #[test]
+    fn serves_embedded_assets_byte_for_byte() {
+        let router = Router::new(routes());
+        for (target, file) in [("/", "pages/index.html"), ("/howdy/page.css", "pages/howdy/page.css")] {
+            let request = HttpRequest::build(HttpMethods::GET, target.to_string(), "HTTP/1.1".to_string());
+            let resp = router.handle(&request);
+            assert_eq!(resp.status_code(), 200);
+            assert_eq!(resp.try_get_body(), Some(fs::read(file).unwrap()));
+        }
+        assert_eq!(file("not_found.html"), Some(fs::read("pages/not_found.html").unwrap().as_slice()));
+    }
+}
@@ -0,0 +1,111 @@
+use std::net::IpAddr;
+
+/// An IP network in CIDR notation, e.g. `10.0.0.0/8` or `::1/128`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix_len: u8,
+}
+
+impl Cidr {
+    /// Parses `addr/len`, or a bare address as a single-host network.
+    pub fn parse(cidr: &str) -> Option<Cidr> {
+        let (addr, prefix_len) = match cidr.trim().split_once('/') {
+            Some((addr, len)) => (addr.parse::<IpAddr>().ok()?, Some(len.parse::<u8>().ok()?)),
+            None => (cidr.trim().parse::<IpAddr>().ok()?, None),
+        };
+        let max_len = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix_len = prefix_len.unwrap_or(max_len);
+        if prefix_len > max_len {
+            return None;
+        }
+        Some(Cidr { network: addr, prefix_len })
+    }
+
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        match (self.network, addr) {
+            (IpAddr::V4(network), IpAddr::V4(addr)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix_len as u32).unwrap_or(0);
+                u32::from(network) & mask == u32::from(addr) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(addr)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix_len as u32).unwrap_or(0);
+                u128::from(network) & mask == u128::from(addr) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Parses a comma-separated CIDR list, skipping invalid entries.
+pub fn parse_cidr_list(list: &str) -> Vec<Cidr> {
+    list.split(',')
+        .filter(|entry| !entry.trim().is_empty())
+        .filter_map(Cidr::parse)
+        .collect()
+}
+
+/// Works out the originating client address. When `peer` is a trusted
+/// proxy, `X-Forwarded-For` is walked from the nearest hop outwards,
+/// skipping trusted proxies; the first untrusted hop is the client. Hops a
+/// trusted proxy didn't vouch for are never consulted, so clients can't
+/// spoof their address by sending the header themselves.
+pub fn client_ip(peer: IpAddr, forwarded_for: Option<&str>, trusted: &[Cidr]) -> IpAddr {
+    let is_trusted = |addr: IpAddr| trusted.iter().any(|cidr| cidr.contains(addr));
+    let Some(forwarded_for) = forwarded_for else { return peer; };
+
+    let mut client = peer;
+    for hop in forwarded_for.rsplit(',') {
+        if !is_trusted(client) {
+            break;
+        }
+        match hop.trim().parse::<IpAddr>() {
+            Ok(addr) => client = addr,
+            Err(_) => break,
+        }
+    }
+    client
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(addr: &str) -> IpAddr {
+        addr.parse().unwrap()
+    }
+
+    #[test]
+    fn cidr_matches_networks() {
+        let private = Cidr::parse("10.0.0.0/8").unwrap();
+        assert!(private.contains(ip("10.1.2.3")));
+        assert!(!private.contains(ip("11.0.0.1")));
+        assert!(!private.contains(ip("::ffff:10.0.0.1")));
+
+        assert!(Cidr::parse("192.168.1.7").unwrap().contains(ip("192.168.1.7")));
+        assert!(!Cidr::parse("192.168.1.7").unwrap().contains(ip("192.168.1.8")));
+        assert!(Cidr::parse("0.0.0.0/0").unwrap().contains(ip("8.8.8.8")));
+        assert!(Cidr::parse("fd00::/8").unwrap().contains(ip("fd12::1")));
+
+        assert_eq!(Cidr::parse("10.0.0.0/33"), None);
+        assert_eq!(Cidr::parse("not-an-ip"), None);
+    }
+
+    #[test]
+    fn client_ip_honors_forwarded_for_only_from_trusted_peers() {
+        let trusted = parse_cidr_list("10.0.0.0/8, 127.0.0.1");
+        let header = Some("203.0.113.9, 10.0.0.2");
+
+        assert_eq!(client_ip(ip("127.0.0.1"), header, &trusted), ip("203.0.113.9"));
+        assert_eq!(client_ip(ip("198.51.100.1"), header, &trusted), ip("198.51.100.1"));
+        assert_eq!(client_ip(ip("127.0.0.1"), None, &trusted), ip("127.0.0.1"));
+    }
+
+    #[test]
+    fn client_ip_ignores_hops_left_of_the_first_untrusted_one() {
+        let trusted = parse_cidr_list("10.0.0.0/8");
+        let header = Some("1.1.1.1, 203.0.113.9, 10.0.0.2");
+        assert_eq!(client_ip(ip("10.0.0.1"), header, &trusted), ip("203.0.113.9"));
+        assert_eq!(client_ip(ip("10.0.0.1"), Some("garbage"), &trusted), ip("10.0.0.1"));
+    }
+}
@@ -0,0 +1,616 @@
+use std::fmt;
+
+#[derive(Debug, PartialEq)]
+pub enum GzipError {
+    /// The decoded output would exceed the caller's size limit.
+    TooLarge,
+    /// The input is not a well-formed gzip member.
+    Invalid(&'static str),
+}
+
+impl fmt::Display for GzipError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GzipError::TooLarge => write!(f, "Decompressed data exceeds size limit"),
+            GzipError::Invalid(reason) => write!(f, "Invalid gzip data: {reason}"),
+        }
+    }
+}
+
+const FTEXT: u8 = 0x01;
+const FHCRC: u8 = 0x02;
+const FEXTRA: u8 = 0x04;
+const FNAME: u8 = 0x08;
+const FCOMMENT: u8 = 0x10;
+
+/// Decompresses a single gzip member, failing with `TooLarge` as soon as the
+/// output grows past `max_len` bytes so decompression bombs are cut short.
+pub fn decompress(data: &[u8], max_len: usize) -> Result<Vec<u8>, GzipError> {
+    if data.len() < 18 || data[0] != 0x1f || data[1] != 0x8b {
+        return Err(GzipError::Invalid("missing gzip header"));
+    }
+    if data[2] != 8 {
+        return Err(GzipError::Invalid("unsupported compression method"));
+    }
+    let flags = data[3];
+    if flags & !(FTEXT | FHCRC | FEXTRA | FNAME | FCOMMENT) != 0 {
+        return Err(GzipError::Invalid("reserved header flags set"));
+    }
+
+    // Skip MTIME, XFL and OS, then the optional header fields.
+    let mut pos = 10;
+    if flags & FEXTRA != 0 {
+        let len = data.get(pos..pos + 2).ok_or(GzipError::Invalid("truncated header"))?;
+        pos += 2 + u16::from_le_bytes([len[0], len[1]]) as usize;
+    }
+    for flag in [FNAME, FCOMMENT] {
+        if flags & flag != 0 {
+            let end = data
+                .get(pos..)
+                .and_then(|rest| rest.iter().position(|&b| b == 0))
+                .ok_or(GzipError::Invalid("truncated header"))?;
+            pos += end + 1;
+        }
+    }
+    if flags & FHCRC != 0 {
+        pos += 2;
+    }
+    if pos > data.len() {
+        return Err(GzipError::Invalid("truncated header"));
+    }
+
+    let mut reader = BitReader::new(&data[pos..]);
+    let output = inflate(&mut reader, max_len)?;
+
+    let trailer_start = pos + reader.bytes_consumed();
+    let trailer = data
+        .get(trailer_start..trailer_start + 8)
+        .ok_or(GzipError::Invalid("truncated trailer"))?;
+    let expected_crc = u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+    let expected_len = u32::from_le_bytes([trailer[4], trailer[5], trailer[6], trailer[7]]);
+    if crc32(&output) != expected_crc {
+        return Err(GzipError::Invalid("CRC mismatch"));
+    }
+    if output.len() as u32 != expected_len {
+        return Err(GzipError::Invalid("length mismatch"));
+    }
+    Ok(output)
+}
+
+/// Computes the CRC-32 (IEEE 802.3) checksum used by the gzip trailer.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xedb8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+// ---------------------------------------------------------------------------
+// Inflate (RFC 1951)
+// ---------------------------------------------------------------------------
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115,
+    131, 163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+// Order in which code length code lengths are stored in a dynamic block.
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    bit_buf: u32,
+    bit_count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> BitReader<'a> {
+        BitReader { data, pos: 0, bit_buf: 0, bit_count: 0 }
+    }
+
+    fn bits(&mut self, count: u32) -> Result<u32, GzipError> {
+        while self.bit_count < count {
+            let byte = *self.data.get(self.pos).ok_or(GzipError::Invalid("unexpected end of data"))?;
+            self.pos += 1;
+            self.bit_buf |= (byte as u32) << self.bit_count;
+            self.bit_count += 8;
+        }
+        let value = self.bit_buf & ((1u32 << count) - 1);
+        self.bit_buf >>= count;
+        self.bit_count -= count;
+        Ok(value)
+    }
+
+    /// Discards any bits left in the current byte.
+    fn align_to_byte(&mut self) {
+        self.bit_buf = 0;
+        self.bit_count = 0;
+    }
+
+    fn bytes_consumed(&self) -> usize {
+        // Whole bytes still sitting in the bit buffer haven't been used.
+        self.pos - (self.bit_count / 8) as usize
+    }
+}
+
+/// Canonical Huffman decoding table: the number of codes of each length and
+/// the symbols ordered by code.
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Huffman, GzipError> {
+        let mut counts = [0u16; 16];
+        for &len in lengths {
+            counts[len as usize] += 1;
+        }
+        counts[0] = 0;
+
+        // Reject over-subscribed code sets.
+        let mut left = 1i32;
+        for &count in &counts[1..] {
+            left = (left << 1) - count as i32;
+            if left < 0 {
+                return Err(GzipError::Invalid("over-subscribed Huffman code"));
+            }
+        }
+
+        let mut offsets = [0u16; 16];
+        for len in 1..15 {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+        let mut symbols = vec![0u16; lengths.len()];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                symbols[offsets[len as usize] as usize] = symbol as u16;
+                offsets[len as usize] += 1;
+            }
+        }
+        Ok(Huffman { counts, symbols })
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, GzipError> {
+        let mut code = 0i32;
+        let mut first = 0i32;
+        let mut index = 0i32;
+        for len in 1..16 {
+            code |= reader.bits(1)? as i32;
+            let count = self.counts[len] as i32;
+            if code - count < first {
+                return Ok(self.symbols[(index + (code - first)) as usize]);
+            }
+            index += count;
+            first += count;
+            first <<= 1;
+            code <<= 1;
+        }
+        Err(GzipError::Invalid("invalid Huffman code"))
+    }
+}
+
+fn inflate(reader: &mut BitReader, max_len: usize) -> Result<Vec<u8>, GzipError> {
+    let mut output = Vec::new();
+    loop {
+        let is_final = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            0 => inflate_stored(reader, &mut output, max_len)?,
+            1 => {
+                let (literals, distances) = fixed_tables()?;
+                inflate_codes(reader, &mut output, max_len, &literals, &distances)?;
+            }
+            2 => {
+                let (literals, distances) = dynamic_tables(reader)?;
+                inflate_codes(reader, &mut output, max_len, &literals, &distances)?;
+            }
+            _ => return Err(GzipError::Invalid("invalid block type")),
+        }
+        if is_final {
+            return Ok(output);
+        }
+    }
+}
+
+fn inflate_stored(reader: &mut BitReader, output: &mut Vec<u8>, max_len: usize) -> Result<(), GzipError> {
+    reader.align_to_byte();
+    let len = reader.bits(16)?;
+    let nlen = reader.bits(16)?;
+    if len != !nlen & 0xffff {
+        return Err(GzipError::Invalid("stored block length mismatch"));
+    }
+    let start = reader.pos;
+    let bytes = reader
+        .data
+        .get(start..start + len as usize)
+        .ok_or(GzipError::Invalid("unexpected end of data"))?;
+    if output.len() + bytes.len() > max_len {
+        return Err(GzipError::TooLarge);
+    }
+    output.extend_from_slice(bytes);
+    reader.pos += len as usize;
+    Ok(())
+}
+
+fn fixed_tables() -> Result<(Huffman, Huffman), GzipError> {
+    let mut lengths = [0u8; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    Ok((Huffman::new(&lengths)?, Huffman::new(&[5u8; 30])?))
+}
+
+fn dynamic_tables(reader: &mut BitReader) -> Result<(Huffman, Huffman), GzipError> {
+    let literal_count = reader.bits(5)? as usize + 257;
+    let distance_count = reader.bits(5)? as usize + 1;
+    let code_length_count = reader.bits(4)? as usize + 4;
+    if literal_count > 286 || distance_count > 30 {
+        return Err(GzipError::Invalid("too many length or distance codes"));
+    }
+
+    let mut code_lengths = [0u8; 19];
+    for &index in &CODE_LENGTH_ORDER[..code_length_count] {
+        code_lengths[index] = reader.bits(3)? as u8;
+    }
+    let code_length_table = Huffman::new(&code_lengths)?;
+
+    let mut lengths = vec![0u8; literal_count + distance_count];
+    let mut index = 0;
+    while index < lengths.len() {
+        let symbol = code_length_table.decode(reader)?;
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *index
+                    .checked_sub(1)
+                    .and_then(|i| lengths.get(i))
+                    .ok_or(GzipError::Invalid("repeat with no previous length"))?;
+                (previous, 3 + reader.bits(2)? as usize)
+            }
+            17 => (0, 3 + reader.bits(3)? as usize),
+            _ => (0, 11 + reader.bits(7)? as usize),
+        };
+        if index + repeat > lengths.len() {
+            return Err(GzipError::Invalid("too many code lengths"));
+        }
+        lengths[index..index + repeat].fill(value);
+        index += repeat;
+    }
+    if lengths[256] == 0 {
+        return Err(GzipError::Invalid("missing end-of-block code"));
+    }
+
+    let literals = Huffman::new(&lengths[..literal_count])?;
+    let distances = Huffman::new(&lengths[literal_count..])?;
+    Ok((literals, distances))
+}
+
+fn inflate_codes(
+    reader: &mut BitReader,
+    output: &mut Vec<u8>,
+    max_len: usize,
+    literals: &Huffman,
+    distances: &Huffman,
+) -> Result<(), GzipError> {
+    loop {
+        let symbol = literals.decode(reader)? as usize;
+        if symbol < 256 {
+            if output.len() >= max_len {
+                return Err(GzipError::TooLarge);
+            }
+            output.push(symbol as u8);
+            continue;
+        }
+        if symbol == 256 {
+            return Ok(());
+        }
+
+        let length_index = symbol - 257;
+        if length_index >= LENGTH_BASE.len() {
+            return Err(GzipError::Invalid("invalid length code"));
+        }
+        let length = LENGTH_BASE[length_index] as usize
+            + reader.bits(LENGTH_EXTRA[length_index] as u32)? as usize;
+
+        let distance_index = distances.decode(reader)? as usize;
+        if distance_index >= DIST_BASE.len() {
+            return Err(GzipError::Invalid("invalid distance code"));
+        }
+        let distance = DIST_BASE[distance_index] as usize
+            + reader.bits(DIST_EXTRA[distance_index] as u32)? as usize;
+        if distance > output.len() {
+            return Err(GzipError::Invalid("distance too far back"));
+        }
+        if output.len() + length > max_len {
+            return Err(GzipError::TooLarge);
+        }
+
+        // Copy byte by byte since the source may overlap the bytes being written.
+        let start = output.len() - distance;
+        for i in 0..length {
+            output.push(output[start + i]);
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Deflate (RFC 1951): LZ77 over a 32 KiB window, fixed Huffman codes
+// ---------------------------------------------------------------------------
+
+const WINDOW_LEN: usize = 32 * 1024;
+const HASH_BITS: u32 = 15;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const NO_POS: usize = usize::MAX;
+
+/// Levels accepted by `compress_with_level`, fastest first, as in zlib.
+pub const COMPRESSION_LEVELS: std::ops::RangeInclusive<u32> = 1..=9;
+pub const DEFAULT_COMPRESSION_LEVEL: u32 = 6;
+/// How many earlier positions with the same 3-byte hash are tried per
+/// match, indexed by level - 1.
+const MAX_CHAIN: [usize; 9] = [4, 8, 16, 32, 64, 128, 256, 1024, 4096];
+
+/// Compresses `data` into a single gzip member at the default level.
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    compress_with_level(data, DEFAULT_COMPRESSION_LEVEL)
+}
+
+/// Compresses `data` trading speed for size by `level`, clamped to
+/// `COMPRESSION_LEVELS`.
+pub fn compress_with_level(data: &[u8], level: u32) -> Vec<u8> {
+    let level = level.clamp(*COMPRESSION_LEVELS.start(), *COMPRESSION_LEVELS.end());
+    // XFL tells decoders whether the fastest or the slowest setting was used.
+    let xfl = match level {
+        1 => 4,
+        9 => 2,
+        _ => 0,
+    };
+    // ID1, ID2, CM = deflate, no flags, no MTIME, XFL, OS = unknown.
+    let mut output = vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, xfl, 0xff];
+    output.extend(deflate(data, MAX_CHAIN[level as usize - 1]));
+    output.extend(crc32(data).to_le_bytes());
+    output.extend((data.len() as u32).to_le_bytes());
+    output
+}
+
+#[derive(Default)]
+struct BitWriter {
+    output: Vec<u8>,
+    bit_buf: u64,
+    bit_count: u32,
+}
+
+impl BitWriter {
+    /// Writes the low `count` bits of `value`, least significant first.
+    fn write_bits(&mut self, value: u32, count: u32) {
+        self.bit_buf |= (value as u64) << self.bit_count;
+        self.bit_count += count;
+        while self.bit_count >= 8 {
+            self.output.push(self.bit_buf as u8);
+            self.bit_buf >>= 8;
+            self.bit_count -= 8;
+        }
+    }
+
+    /// Huffman codes are packed most significant bit first.
+    fn write_code(&mut self, code: u32, len: u32) {
+        self.write_bits(code.reverse_bits() >> (32 - len), len);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bit_count > 0 {
+            self.output.push(self.bit_buf as u8);
+        }
+        self.output
+    }
+}
+
+/// Emits a literal/length symbol with the fixed code table (RFC 1951 §3.2.6).
+fn write_symbol(writer: &mut BitWriter, symbol: u32) {
+    match symbol {
+        0..=143 => writer.write_code(0x30 + symbol, 8),
+        144..=255 => writer.write_code(0x190 + symbol - 144, 9),
+        256..=279 => writer.write_code(symbol - 256, 7),
+        _ => writer.write_code(0xc0 + symbol - 280, 8),
+    }
+}
+
+fn write_match(writer: &mut BitWriter, len: usize, distance: usize) {
+    let index = LENGTH_BASE.iter().rposition(|&base| base as usize <= len).unwrap_or(0);
+    write_symbol(writer, 257 + index as u32);
+    writer.write_bits((len - LENGTH_BASE[index] as usize) as u32, LENGTH_EXTRA[index] as u32);
+
+    let index = DIST_BASE.iter().rposition(|&base| base as usize <= distance).unwrap_or(0);
+    writer.write_code(index as u32, 5);
+    writer.write_bits((distance - DIST_BASE[index] as usize) as u32, DIST_EXTRA[index] as u32);
+}
+
+fn hash3(data: &[u8], pos: usize) -> usize {
+    let key = (data[pos] as u32) << 16 | (data[pos + 1] as u32) << 8 | data[pos + 2] as u32;
+    (key.wrapping_mul(0x9e37_79b1) >> (32 - HASH_BITS)) as usize
+}
+
+/// Greedy LZ77 matching over hash chains, emitted as one fixed-Huffman block.
+fn deflate(data: &[u8], max_chain: usize) -> Vec<u8> {
+    let mut writer = BitWriter::default();
+    writer.write_bits(1, 1); // BFINAL
+    writer.write_bits(1, 2); // BTYPE = fixed Huffman
+
+    let mut head = vec![NO_POS; 1 << HASH_BITS];
+    let mut prev = vec![NO_POS; WINDOW_LEN];
+    let insert = |head: &mut [usize], prev: &mut [usize], pos: usize| {
+        if pos + MIN_MATCH <= data.len() {
+            let h = hash3(data, pos);
+            prev[pos % WINDOW_LEN] = head[h];
+            head[h] = pos;
+        }
+    };
+
+    let mut pos = 0;
+    while pos < data.len() {
+        let (mut best_len, mut best_distance) = (0, 0);
+        if pos + MIN_MATCH <= data.len() {
+            let max_len = (data.len() - pos).min(MAX_MATCH);
+            let mut candidate = head[hash3(data, pos)];
+            let mut chain = max_chain;
+            while candidate != NO_POS && pos - candidate <= WINDOW_LEN && chain > 0 {
+                let len = data[candidate..]
+                    .iter()
+                    .zip(&data[pos..pos + max_len])
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                if len > best_len {
+                    (best_len, best_distance) = (len, pos - candidate);
+                    if len == max_len {
+                        break;
+                    }
+                }
+                let next = prev[candidate % WINDOW_LEN];
+                // A newer position may have reused this slot; the chain ends there.
+                if next == NO_POS || next >= candidate {
+                    break;
+                }
+                candidate = next;
+                chain -= 1;
+            }
+        }
+
+        if best_len >= MIN_MATCH {
+            write_match(&mut writer, best_len, best_distance);
+            for skipped in pos..pos + best_len {
+                insert(&mut head, &mut prev, skipped);
+            }
+            pos += best_len;
+        } else {
+            write_symbol(&mut writer, data[pos] as u32);
+            insert(&mut head, &mut prev, pos);
+            pos += 1;
+        }
+    }
+
+    write_symbol(&mut writer, 256);
+    writer.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // `gzip.compress(b"hello world", mtime=0)` -- a fixed Huffman block.
+    const HELLO_GZ: [u8; 31] = [
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0xcb, 0x48, 0xcd, 0xc9,
+        0xc9, 0x57, 0x28, 0xcf, 0x2f, 0xca, 0x49, 0x01, 0x00, 0x85, 0x11, 0x4a, 0x0d, 0x0b,
+        0x00, 0x00, 0x00,
+    ];
+
+    // Three pangram sentences plus another -- a dynamic Huffman block.
+    const PANGRAMS_GZ: [u8; 98] = [
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0xb5, 0xcb, 0xd1, 0x01,
+        0x80, 0x10, 0x14, 0x46, 0xe1, 0x55, 0xfe, 0x16, 0x68, 0x96, 0x1e, 0x2c, 0x40, 0x11,
+        0x15, 0x37, 0x84, 0x98, 0xbe, 0xbb, 0x44, 0xcf, 0xe7, 0x3b, 0xc2, 0x6a, 0xc4, 0xe2,
+        0xd6, 0x13, 0x2a, 0x51, 0x0b, 0x30, 0xf4, 0xe2, 0x28, 0xfe, 0xce, 0xa0, 0xaa, 0x13,
+        0x1e, 0xce, 0x97, 0x1c, 0x1d, 0x1b, 0xed, 0x33, 0xc4, 0x6f, 0x78, 0x91, 0xec, 0x7c,
+        0x87, 0x62, 0xd4, 0xdc, 0x63, 0x61, 0x5c, 0xd5, 0x9c, 0x86, 0x0e, 0xb8, 0x5c, 0x2c,
+        0x94, 0xf8, 0xdd, 0xf3, 0xf4, 0x01, 0x83, 0x92, 0xd0, 0xea, 0xaf, 0x00, 0x00, 0x00,
+    ];
+
+    fn pangrams() -> Vec<u8> {
+        let mut expected = b"The quick brown fox jumps over the lazy dog. ".repeat(3);
+        expected.extend_from_slice(b"Pack my box with five dozen liquor jugs!");
+        expected
+    }
+
+    #[test]
+    fn crc32_matches_known_value() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn decompress_fixed_huffman_block() {
+        assert_eq!(decompress(&HELLO_GZ, 1024).unwrap(), b"hello world".to_vec());
+    }
+
+    #[test]
+    fn decompress_dynamic_huffman_block() {
+        assert_eq!(decompress(&PANGRAMS_GZ, 1024).unwrap(), pangrams());
+    }
+
+    #[test]
+    fn decompress_stops_at_size_limit() {
+        assert_eq!(decompress(&PANGRAMS_GZ, 100), Err(GzipError::TooLarge));
+    }
+
+    #[test]
+    fn decompress_rejects_corrupt_checksum() {
+        let mut corrupt = HELLO_GZ;
+        corrupt[23] ^= 0xff;
+        assert_eq!(decompress(&corrupt, 1024), Err(GzipError::Invalid("CRC mismatch")));
+    }
+
+    #[test]
+    fn decompress_rejects_non_gzip_input() {
+        assert!(matches!(decompress(b"plain text body here", 1024), Err(GzipError::Invalid(_))));
+    }
+
+    #[test]
+    fn compress_round_trips_through_decompress() {
+        let mut noisy = Vec::new();
+        let mut state = 12345u32;
+        for _ in 0..50_000 {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            noisy.push((state >> 16) as u8);
+        }
+        let repetitive = "<p>the same paragraph again</p>\n".repeat(3_000).into_bytes();
+        let inputs: [&[u8]; 6] = [b"", b"a", b"hello world", &pangrams(), &noisy, &repetitive];
+
+        for input in inputs {
+            let compressed = compress(input);
+            assert_eq!(decompress(&compressed, input.len() + 1).unwrap(), input);
+        }
+    }
+
+    #[test]
+    fn higher_levels_search_harder() {
+        // Many short repeats at varying distances, so longer hash chains find better matches.
+        let mut state = 7u32;
+        let words = ["alpha", "beta", "gamma", "delta", "epsilon", "zeta", "eta", "theta"];
+        let mut text = String::new();
+        for _ in 0..5_000 {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            text.push_str(words[(state >> 16) as usize % words.len()]);
+            text.push(' ');
+        }
+        let text = text.into_bytes();
+
+        let fast = compress_with_level(&text, 1);
+        let best = compress_with_level(&text, 9);
+        assert_eq!(decompress(&fast, text.len()).unwrap(), text);
+        assert_eq!(decompress(&best, text.len()).unwrap(), text);
+        assert!(best.len() < fast.len(), "level 9 {} vs level 1 {}", best.len(), fast.len());
+    }
+
+    #[test]
+    fn compress_shrinks_repetitive_text() {
+        let html = "<li class=\"item\">An item in a long list</li>\n".repeat(500).into_bytes();
+        assert!(compress(&html).len() < html.len() / 10);
+    }
+}
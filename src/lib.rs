@@ -1,37 +1,171 @@
+pub mod access_log;
+pub mod cache;
+pub mod client;
+pub mod compression;
+pub mod config;
+pub mod cors;
+#[cfg(feature = "embed")]
+pub mod embed;
+pub mod forwarded;
+pub mod gzip;
+pub mod logger;
+pub mod maintenance;
+pub mod metrics;
+pub mod middleware;
 pub mod models;
+pub mod proxy;
+pub mod router;
+pub mod server;
+pub mod sse;
+pub mod template;
+pub mod watch;
 
 use std::{
-    sync::{Arc, Mutex, mpsc}, 
+    any::Any,
+    cell::RefCell,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        Arc, Mutex, mpsc,
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+    },
     thread,
+    time::{Duration, Instant},
 };
+use logger::{LogLevel, Logger, StderrLogger};
+
+pub use config::ServerConfig;
+pub use server::{ServerHandle, serve};
 
 pub struct ThreadPool {
     workers: Vec<Worker>,
+    /// Workers removed by `resize` that may still be finishing a job.
+    retired: Vec<Worker>,
+    next_id: usize,
     sender: Option<mpsc::Sender<Job>>,
+    receiver: Arc<Mutex<mpsc::Receiver<Job>>>,
+    logger: Arc<dyn Logger>,
+    counts: Arc<JobCounts>,
+    traffic: Arc<TrafficCounters>,
+}
+
+/// Jobs submitted but not yet finished, and jobs run to completion.
+#[derive(Default)]
+struct JobCounts {
+    pending: AtomicUsize,
+    completed: AtomicUsize,
+}
+
+/// Bytes read from requests and written in responses. Connection handlers
+/// add to these; `ThreadPool::metrics` reports the totals.
+#[derive(Debug, Default)]
+pub struct TrafficCounters {
+    bytes_in: AtomicU64,
+    bytes_out: AtomicU64,
+}
+
+impl TrafficCounters {
+    pub fn add_in(&self, bytes: u64) {
+        self.bytes_in.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn add_out(&self, bytes: u64) {
+        self.bytes_out.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn bytes_in(&self) -> u64 {
+        self.bytes_in.load(Ordering::Relaxed)
+    }
+
+    pub fn bytes_out(&self) -> u64 {
+        self.bytes_out.load(Ordering::Relaxed)
+    }
+}
+
+/// A point-in-time snapshot of pool activity.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PoolMetrics {
+    pub workers: usize,
+    /// Jobs queued or running.
+    pub pending_jobs: usize,
+    pub completed_jobs: usize,
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+}
+
+/// Outcome of `ThreadPool::shutdown_timeout`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShutdownReport {
+    /// Jobs that ran to completion over the pool's lifetime.
+    pub completed: usize,
+    /// Jobs still queued or running when the grace period ran out.
+    pub abandoned: usize,
 }
 
 struct Worker {
     id: usize,
     thread: thread::JoinHandle<()>,
+    /// Set by `ThreadPool::resize` to retire this worker after its current job.
+    stop: Arc<AtomicBool>,
 }
 
+/// How long an idle worker holds the job queue before letting another
+/// worker, possibly a retired one, take its turn.
+const WORKER_IDLE_POLL: Duration = Duration::from_millis(100);
+
 type Job = Box<dyn FnOnce() + Send + 'static>;
 
+thread_local! {
+    /// What the job running on this worker is doing, for panic reports.
+    static JOB_CONTEXT: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Records what the current job is working on (e.g. `GET /path from
+/// 127.0.0.1`). If the job panics, the worker logs this alongside the panic
+/// message. Cleared when the job ends.
+pub fn set_job_context(context: String) {
+    JOB_CONTEXT.with(|current| *current.borrow_mut() = Some(context));
+}
+
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "non-string panic payload"
+    }
+}
+
 impl ThreadPool {
     pub fn new(size: usize) -> ThreadPool {
+        ThreadPool::new_with_logger(size, Arc::new(StderrLogger::new(LogLevel::Info)))
+    }
+
+    pub fn new_with_logger(size: usize, logger: Arc<dyn Logger>) -> ThreadPool {
         assert!(size > 0);
 
         let (sender, receiver) = mpsc::channel();
 
         let receiver = Arc::new(Mutex::new(receiver));
 
+        let counts = Arc::new(JobCounts::default());
+
         let mut workers = Vec::with_capacity(size);
 
         for id in 0..size {
-            workers.push(Worker::new(id, Arc::clone(&receiver)));
+            workers.push(Worker::new(id, Arc::clone(&receiver), Arc::clone(&logger), Arc::clone(&counts)));
         }
 
-        ThreadPool { workers, sender: Some(sender) }
+        ThreadPool {
+            workers,
+            retired: Vec::new(),
+            next_id: size,
+            sender: Some(sender),
+            receiver,
+            logger,
+            counts,
+            traffic: Arc::new(TrafficCounters::default()),
+        }
     }
 
     pub fn execute<F>(&self, f: F)
@@ -40,16 +174,91 @@ impl ThreadPool {
     {
         let job = Box::new(f);
 
+        self.counts.pending.fetch_add(1, Ordering::SeqCst);
         self.sender.as_ref().unwrap().send(job).unwrap();
     }
+
+    /// Grows or shrinks the pool to `new_size` workers. New workers start
+    /// taking jobs at once; surplus ones exit once their current job, if any,
+    /// is done, and are no longer counted in `metrics`.
+    pub fn resize(&mut self, new_size: usize) {
+        assert!(new_size > 0);
+
+        while self.workers.len() < new_size {
+            let id = self.next_id;
+            self.next_id += 1;
+            self.workers.push(Worker::new(
+                id,
+                Arc::clone(&self.receiver),
+                Arc::clone(&self.logger),
+                Arc::clone(&self.counts),
+            ));
+        }
+        while self.workers.len() > new_size {
+            let worker = self.workers.pop().unwrap();
+            worker.stop.store(true, Ordering::SeqCst);
+            self.retired.push(worker);
+        }
+
+        // Reap workers retired earlier that have since exited.
+        let (finished, running) = self.retired.drain(..).partition(|worker| worker.thread.is_finished());
+        self.retired = running;
+        for worker in finished {
+            let _ = worker.thread.join();
+        }
+    }
+
+    /// The byte counters jobs should report their I/O to.
+    pub fn traffic(&self) -> Arc<TrafficCounters> {
+        Arc::clone(&self.traffic)
+    }
+
+    pub fn metrics(&self) -> PoolMetrics {
+        PoolMetrics {
+            workers: self.workers.len(),
+            pending_jobs: self.counts.pending.load(Ordering::SeqCst),
+            completed_jobs: self.counts.completed.load(Ordering::SeqCst),
+            bytes_in: self.traffic.bytes_in(),
+            bytes_out: self.traffic.bytes_out(),
+        }
+    }
+
+    /// Stops taking jobs and gives queued and running ones up to `grace` to
+    /// finish. Workers still busy after that are detached rather than
+    /// joined, so this never blocks much longer than `grace`.
+    pub fn shutdown_timeout(mut self, grace: Duration) -> ShutdownReport {
+        drop(self.sender.take());
+        self.workers.append(&mut self.retired);
+
+        let deadline = Instant::now() + grace;
+        while self.workers.iter().any(|worker| !worker.thread.is_finished()) && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        let abandoned = self.counts.pending.load(Ordering::SeqCst);
+        for worker in self.workers.drain(..) {
+            if worker.thread.is_finished() {
+                self.logger.info(&format!("Shutting down worker {}", worker.id));
+                let _ = worker.thread.join();
+            } else {
+                self.logger.warn(&format!("Worker {} still busy after grace period; abandoning it", worker.id));
+            }
+        }
+
+        ShutdownReport {
+            completed: self.counts.completed.load(Ordering::SeqCst),
+            abandoned,
+        }
+    }
 }
 
 impl Drop for ThreadPool {
     fn drop(&mut self) {
         drop(self.sender.take());
+        self.workers.append(&mut self.retired);
 
         for worker in self.workers.drain(..) {
-            println!("Shutting down worker {}", worker.id);
+            self.logger.info(&format!("Shutting down worker {}", worker.id));
 
             worker.thread.join().unwrap();
         }
@@ -57,33 +266,69 @@ impl Drop for ThreadPool {
 }
 
 impl Worker {
-    fn new(id: usize, reciever: Arc<Mutex<mpsc::Receiver<Job>>>) -> Worker {
+    fn new(
+        id: usize,
+        reciever: Arc<Mutex<mpsc::Receiver<Job>>>,
+        logger: Arc<dyn Logger>,
+        counts: Arc<JobCounts>,
+    ) -> Worker {
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = Arc::clone(&stop);
         let thread = thread::spawn(move || {
+            logger.debug(&format!("Worker {id} started."));
             loop {
-                let message = reciever.lock().unwrap().recv();
+                let message = {
+                    let reciever = reciever.lock().unwrap();
+                    // Checked once the lock is ours, so a worker retired while
+                    // queued for it leaves at once instead of holding it for
+                    // another poll.
+                    if stopped.load(Ordering::SeqCst) {
+                        logger.debug(&format!("Worker {id} retired; shutting down."));
+                        break;
+                    }
+                    reciever.recv_timeout(WORKER_IDLE_POLL)
+                };
 
                 match message {
                     Ok(job) => {
-                        println!("Worker {id} got a job; executing.");
+                        logger.debug(&format!("Worker {id} got a job; executing."));
 
-                        job();
+                        // A panicking job must not take the worker down with it.
+                        let result = panic::catch_unwind(AssertUnwindSafe(job));
+                        let context = JOB_CONTEXT.with(|current| current.borrow_mut().take());
+                        if let Err(payload) = result {
+                            let context = context.map(|context| format!(" while handling {context}")).unwrap_or_default();
+                            logger.error(&format!("Worker {id} panicked{context}: {}", panic_message(payload.as_ref())));
+                        }
+                        counts.completed.fetch_add(1, Ordering::SeqCst);
+                        counts.pending.fetch_sub(1, Ordering::SeqCst);
                     }
-                    Err(_) => {
-                        println!("Worker {id} disconnected; shutting down.");
+                    Err(mpsc::RecvTimeoutError::Timeout) => {}
+                    Err(mpsc::RecvTimeoutError::Disconnected) => {
+                        logger.debug(&format!("Worker {id} disconnected; shutting down."));
                         break;
                     }
                 }
             }
         });
 
-        Worker { id, thread }
+        Worker { id, thread, stop }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct CapturingLogger {
+        messages: Mutex<Vec<(LogLevel, String)>>,
+    }
+
+    impl Logger for CapturingLogger {
+        fn log(&self, level: LogLevel, message: &str) {
+            self.messages.lock().unwrap().push((level, message.to_string()));
+        }
+    }
 
     #[test]
     fn thread_pool_creates_with_valid_size() {
@@ -97,6 +342,49 @@ mod tests {
         ThreadPool::new(0);
     }
 
+    /// Runs `jobs` jobs on `pool` and waits for all of them to finish.
+    fn run_jobs(pool: &ThreadPool, jobs: usize) {
+        let (done, finished) = mpsc::channel();
+        for _ in 0..jobs {
+            let done = done.clone();
+            pool.execute(move || done.send(()).unwrap());
+        }
+        for _ in 0..jobs {
+            finished.recv_timeout(Duration::from_secs(5)).expect("job ran");
+        }
+    }
+
+    #[test]
+    fn resize_grows_and_shrinks_workers() {
+        let mut pool = ThreadPool::new(2);
+        pool.resize(4);
+        assert_eq!(pool.workers.len(), 4);
+        assert_eq!(pool.metrics().workers, 4);
+        run_jobs(&pool, 8);
+
+        pool.resize(2);
+        assert_eq!(pool.workers.len(), 2);
+        assert_eq!(pool.workers.iter().map(|worker| worker.id).collect::<Vec<_>>(), vec![0, 1]);
+        run_jobs(&pool, 8);
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while pool.retired.iter().any(|worker| !worker.thread.is_finished()) && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(pool.retired.iter().all(|worker| worker.thread.is_finished()), "retired workers exit");
+    }
+
+    #[test]
+    fn shrinking_an_idle_pool_does_not_wait_a_poll_per_worker() {
+        let mut pool = ThreadPool::new(16);
+        let started = Instant::now();
+        pool.resize(1);
+        while pool.retired.iter().any(|worker| !worker.thread.is_finished()) {
+            assert!(started.elapsed() < WORKER_IDLE_POLL * 8, "retiring 15 idle workers took {:?}", started.elapsed());
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
     #[test]
     fn thread_pool_executes_jobs() {
         let counter = Arc::new(AtomicUsize::new(0));
@@ -134,4 +422,82 @@ mod tests {
         pool.execute(|| {});
         drop(pool); // should not panic
     }
+
+    #[test]
+    fn thread_pool_logs_worker_lifecycle_to_logger() {
+        let logger = Arc::new(CapturingLogger { messages: Mutex::new(Vec::new()) });
+        let pool = ThreadPool::new_with_logger(2, logger.clone());
+        drop(pool);
+
+        let messages = logger.messages.lock().unwrap();
+        for id in 0..2 {
+            assert!(messages.contains(&(LogLevel::Debug, format!("Worker {id} started."))));
+            assert!(messages.contains(&(LogLevel::Info, format!("Shutting down worker {id}"))));
+            assert!(messages.contains(&(LogLevel::Debug, format!("Worker {id} disconnected; shutting down."))));
+        }
+    }
+
+    #[test]
+    fn shutdown_timeout_waits_for_jobs_within_grace() {
+        let pool = ThreadPool::new(2);
+        for _ in 0..3 {
+            pool.execute(|| thread::sleep(Duration::from_millis(50)));
+        }
+        let report = pool.shutdown_timeout(Duration::from_secs(5));
+        assert_eq!(report, ShutdownReport { completed: 3, abandoned: 0 });
+    }
+
+    #[test]
+    fn shutdown_timeout_abandons_jobs_past_grace() {
+        let pool = ThreadPool::new(1);
+        pool.execute(|| thread::sleep(Duration::from_millis(500)));
+        pool.execute(|| {});
+
+        let start = Instant::now();
+        let report = pool.shutdown_timeout(Duration::from_millis(50));
+        assert!(start.elapsed() < Duration::from_millis(400));
+        assert_eq!(report, ShutdownReport { completed: 0, abandoned: 2 });
+    }
+
+    #[test]
+    fn metrics_report_jobs_and_traffic() {
+        let pool = ThreadPool::new(2);
+        let traffic = pool.traffic();
+        pool.execute(move || {
+            traffic.add_in(120);
+            traffic.add_out(300);
+        });
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while pool.metrics().completed_jobs < 1 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(pool.metrics(), PoolMetrics {
+            workers: 2,
+            pending_jobs: 0,
+            completed_jobs: 1,
+            bytes_in: 120,
+            bytes_out: 300,
+        });
+    }
+
+    #[test]
+    fn worker_survives_panicking_job_and_logs_context() {
+        let logger = Arc::new(CapturingLogger { messages: Mutex::new(Vec::new()) });
+        let pool = ThreadPool::new_with_logger(1, logger.clone());
+        pool.execute(|| {
+            set_job_context(String::from("GET /boom"));
+            panic!("kaboom");
+        });
+        let counter = Arc::new(AtomicUsize::new(0));
+        let counter_clone = Arc::clone(&counter);
+        pool.execute(move || {
+            counter_clone.fetch_add(1, Ordering::SeqCst);
+        });
+        drop(pool);
+
+        assert_eq!(counter.load(Ordering::SeqCst), 1);
+        let messages = logger.messages.lock().unwrap();
+        assert!(messages.contains(&(LogLevel::Error, String::from("Worker 0 panicked while handling GET /boom: kaboom"))));
+    }
 }
@@ -0,0 +1,91 @@
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+impl fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let level = match self {
+            LogLevel::Error => "ERROR",
+            LogLevel::Warn => "WARN",
+            LogLevel::Info => "INFO",
+            LogLevel::Debug => "DEBUG",
+        };
+        write!(f, "{}", level)
+    }
+}
+
+pub fn log_level_from_string(level: &str) -> Option<LogLevel> {
+    match level.to_lowercase().as_str() {
+        "error" => Some(LogLevel::Error),
+        "warn" => Some(LogLevel::Warn),
+        "info" => Some(LogLevel::Info),
+        "debug" => Some(LogLevel::Debug),
+        _ => None,
+    }
+}
+
+/// Destination for the server's diagnostic output. Only `log` needs to be
+/// implemented; the level helpers forward to it.
+pub trait Logger: Send + Sync {
+    fn log(&self, level: LogLevel, message: &str);
+
+    fn error(&self, message: &str) {
+        self.log(LogLevel::Error, message);
+    }
+
+    fn warn(&self, message: &str) {
+        self.log(LogLevel::Warn, message);
+    }
+
+    fn info(&self, message: &str) {
+        self.log(LogLevel::Info, message);
+    }
+
+    fn debug(&self, message: &str) {
+        self.log(LogLevel::Debug, message);
+    }
+}
+
+/// Writes messages at or above `level` in severity to stderr.
+pub struct StderrLogger {
+    level: LogLevel,
+}
+
+impl StderrLogger {
+    pub fn new(level: LogLevel) -> StderrLogger {
+        StderrLogger { level }
+    }
+}
+
+impl Logger for StderrLogger {
+    fn log(&self, level: LogLevel, message: &str) {
+        if level <= self.level {
+            eprintln!("[{level}] {message}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levels_order_by_verbosity() {
+        assert!(LogLevel::Error < LogLevel::Warn);
+        assert!(LogLevel::Warn < LogLevel::Info);
+        assert!(LogLevel::Info < LogLevel::Debug);
+    }
+
+    #[test]
+    fn parses_level_names_case_insensitively() {
+        assert_eq!(log_level_from_string("WARN"), Some(LogLevel::Warn));
+        assert_eq!(log_level_from_string("debug"), Some(LogLevel::Debug));
+        assert_eq!(log_level_from_string("verbose"), None);
+    }
+}
@@ -1,123 +1,67 @@
-use std::{
-    collections::HashMap,
-    fs,
-    io::prelude::*,
-    net::{TcpListener, TcpStream},
-    path::{Path, PathBuf},
-};
-use rcomm::ThreadPool;
-use rcomm::models::{
-    http_response::HttpResponse,
-    http_request::HttpRequest,
-};
-
-fn get_port() -> String {
-    std::env::var("RCOMM_PORT").unwrap_or_else(|_| String::from("7878"))
-}
-
-fn get_address() -> String {
-    std::env::var("RCOMM_ADDRESS").unwrap_or_else(|_| String::from("127.0.0.1"))
-}
+use std::{thread, time::Duration};
+use rcomm::{ServerConfig, serve};
+use rcomm::logger::{Logger, StderrLogger};
 
 fn main() {
-    let port = get_port();
-    let address = get_address();
-    let full_address = format!("{address}:{port}");
-    let listener = TcpListener::bind(&full_address).unwrap();
-
-    let pool = ThreadPool::new(4);
+    let config = ServerConfig::from_env();
+    let logger = StderrLogger::new(config.log_level);
 
-    let path = Path::new("./pages");
-    let routes = build_routes(String::from(""), path);
+    shutdown_signal::install();
 
-    println!("Routes:\n{routes:#?}\n\n");
-    println!("Listening on {full_address}");
-
-    for stream in listener.incoming() {
-        let routes_clone = routes.clone();
-        let stream = stream.unwrap();
+    let server = match serve(config) {
+        Ok(server) => server,
+        Err(e) => {
+            logger.error(&format!("Failed to start server: {e}"));
+            std::process::exit(1);
+        }
+    };
 
-        pool.execute(move || {
-            handle_connection(stream, routes_clone);
-        });
+    while !shutdown_signal::requested() {
+        thread::sleep(Duration::from_millis(100));
     }
+    server.shutdown();
 }
 
-fn handle_connection(mut stream: TcpStream, routes: HashMap<String, PathBuf>) {
-    let http_request = match HttpRequest::build_from_stream(&stream) {
-        Ok(req) => req,
-        Err(e) => {
-            eprintln!("Bad request: {e}");
-            let mut response = HttpResponse::build(String::from("HTTP/1.1"), 400);
-            let body = format!("Bad Request: {e}");
-            response.add_body(body.into());
-            let _ = stream.write_all(&response.as_bytes());
-            return;
-        }
-    };
-    let clean_target = clean_route(&http_request.target);
+/// Turns SIGINT/SIGTERM into a graceful shutdown request. A second signal
+/// exits immediately.
+#[cfg(unix)]
+mod shutdown_signal {
+    use std::sync::atomic::{AtomicBool, Ordering};
 
-    println!("Request: {http_request}");
+    const SIGINT: i32 = 2;
+    const SIGTERM: i32 = 15;
 
-    let (mut response, filename) = if routes.contains_key(&clean_target) {
-        (HttpResponse::build(String::from("HTTP/1.1"), 200),
-            routes.get(&clean_target).unwrap().to_str().unwrap())
-    } else {
-        (HttpResponse::build(String::from("HTTP/1.1"), 404),
-            "pages/not_found.html")
-    };
+    static REQUESTED: AtomicBool = AtomicBool::new(false);
 
-    let contents = fs::read_to_string(filename).unwrap();
-    response.add_body(contents.into());
+    unsafe extern "C" {
+        fn signal(signum: i32, handler: extern "C" fn(i32)) -> usize;
+        fn _exit(status: i32) -> !;
+    }
 
-    println!("Response: {response}");
-    stream.write_all(&response.as_bytes()).unwrap();
-}
+    extern "C" fn on_signal(signum: i32) {
+        if REQUESTED.swap(true, Ordering::SeqCst) {
+            // Only async-signal-safe calls are allowed here.
+            unsafe { _exit(128 + signum) }
+        }
+    }
 
-fn clean_route(route: &String) -> String {
-    let mut clean_route = String::from("");
-    for part in route.split("/").collect::<Vec<_>>() {
-        if part == "" || part == "." || part == ".." {
-            continue;
+    pub fn install() {
+        unsafe {
+            signal(SIGINT, on_signal);
+            signal(SIGTERM, on_signal);
         }
-        clean_route.push_str(format!("/{part}").as_str());
     }
-    if clean_route == "" {
-        clean_route = String::from("/");
+
+    pub fn requested() -> bool {
+        REQUESTED.load(Ordering::SeqCst)
     }
-    clean_route
 }
 
-fn build_routes(route: String, directory: &Path) -> HashMap<String, PathBuf> {
-    let mut routes: HashMap<String, PathBuf> = HashMap::new();
+#[cfg(not(unix))]
+mod shutdown_signal {
+    pub fn install() {}
 
-    for entry in fs::read_dir(directory).unwrap() {
-        let entry = entry.unwrap();
-        let path = entry.path();
-        let name = path.file_name().unwrap().to_str().unwrap();
-        if path.is_dir() {
-            routes.extend(
-                build_routes(format!("{route}/{name}"), &path)
-            );
-        } else if path.is_file() {
-            match path.extension().unwrap().to_str().unwrap() {
-                "html" | "css" | "js" => {
-                    if name == "index.html" || name == "page.html" {
-                        if route == "" {
-                            routes.insert(String::from("/"), path);
-                        } else {
-                            routes.insert(route.clone(), path);
-                        }
-                    } else if name == "not_found.html" {
-                        continue;
-                    } else {
-                        routes.insert(format!("{route}/{name}"), path);
-                    }
-                }
-                _ => {continue;}
-            }
-        }
+    pub fn requested() -> bool {
+        false
     }
-
-    routes
 }
@@ -0,0 +1,90 @@
+//! Maintenance mode. While it is on, every request except the `/healthz`
+//! probe is answered with `503 Service Unavailable`. It is switched on by
+//! `RCOMM_MAINTENANCE`, or without a restart by creating a `MAINTENANCE`
+//! file in a document root and switched off again by deleting it.
+
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use crate::models::http_response::HttpResponse;
+
+/// The file whose presence in a document root turns maintenance mode on.
+pub const MARKER_FILE: &str = "MAINTENANCE";
+
+/// The path left reachable during maintenance so load balancers keep the
+/// instance in rotation.
+pub const HEALTH_PATH: &str = "/healthz";
+
+const PAGE: &str = "<!DOCTYPE html>\n<html>\n<head><title>Down for maintenance</title></head>\n<body>\n<h1>Down for maintenance</h1>\n<p>This site is undergoing maintenance. Please try again shortly.</p>\n</body>\n</html>\n";
+
+pub struct Maintenance {
+    forced: bool,
+    markers: Vec<PathBuf>,
+    retry_after: Duration,
+}
+
+impl Maintenance {
+    /// Watches for a `MAINTENANCE` file in each of `roots`; `forced` keeps
+    /// maintenance on regardless.
+    pub fn new(forced: bool, roots: &[PathBuf], retry_after: Duration) -> Maintenance {
+        let markers = roots.iter().map(|root| root.join(MARKER_FILE)).collect();
+        Maintenance { forced, markers, retry_after }
+    }
+
+    /// Checked on every request, so a marker file takes effect immediately.
+    pub fn is_active(&self) -> bool {
+        self.forced || self.markers.iter().any(|marker| Path::exists(marker))
+    }
+
+    /// The `503` served in place of any route while maintenance is on.
+    pub fn response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(String::from("HTTP/1.1"), 503);
+        response.add_header("Retry-After".to_string(), self.retry_after.as_secs().to_string());
+        response.add_header("Cache-Control".to_string(), "no-store".to_string());
+        response.set_content_type_charset("text/html", "utf-8");
+        response.add_body(PAGE.into());
+        response
+    }
+}
+
+/// The `/healthz` answer: the process is up and serving.
+pub fn health_response() -> HttpResponse {
+    let mut response = HttpResponse::build(String::from("HTTP/1.1"), 200);
+    response.add_header("Cache-Control".to_string(), "no-store".to_string());
+    response.add_text_body("ok");
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn marker_file_toggles_maintenance() {
+        let dir = std::env::temp_dir().join(format!("rcomm_maintenance_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let maintenance = Maintenance::new(false, std::slice::from_ref(&dir), Duration::from_secs(120));
+        let before = maintenance.is_active();
+        fs::write(dir.join(MARKER_FILE), "").unwrap();
+        let during = maintenance.is_active();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(!before);
+        assert!(during);
+        assert!(!maintenance.is_active());
+        assert!(Maintenance::new(true, &[], Duration::from_secs(120)).is_active());
+    }
+
+    #[test]
+    fn response_is_503_with_retry_after() {
+        let response = Maintenance::new(true, &[], Duration::from_secs(120)).response();
+        assert_eq!(response.status_code(), 503);
+        assert_eq!(response.try_get_header("retry-after".to_string()), Some("120".to_string()));
+        assert_eq!(
+            response.try_get_header("content-type".to_string()),
+            Some("text/html; charset=utf-8".to_string())
+        );
+    }
+}
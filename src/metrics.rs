@@ -0,0 +1,82 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::Mutex,
+};
+use crate::TrafficCounters;
+
+/// Responses sent, counted by status code.
+#[derive(Debug, Default)]
+pub struct ResponseStats {
+    counts: Mutex<BTreeMap<u16, u64>>,
+}
+
+impl ResponseStats {
+    pub fn record(&self, code: u16) {
+        *self.counts.lock().unwrap().entry(code).or_insert(0) += 1;
+    }
+
+    pub fn count(&self, code: u16) -> u64 {
+        self.counts.lock().unwrap().get(&code).copied().unwrap_or(0)
+    }
+
+    /// Responses whose code falls in `class`, e.g. `4` for 4xx.
+    pub fn class_count(&self, class: u16) -> u64 {
+        self.counts
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|&(&code, _)| code / 100 == class)
+            .map(|(_, count)| count)
+            .sum()
+    }
+
+    /// Every code seen so far with its count, in code order.
+    pub fn snapshot(&self) -> BTreeMap<u16, u64> {
+        self.counts.lock().unwrap().clone()
+    }
+}
+
+/// Renders the counters in the Prometheus text exposition format, as served
+/// at `/metrics`.
+pub fn render_metrics(responses: &ResponseStats, traffic: &TrafficCounters) -> String {
+    let mut output = String::new();
+    let _ = writeln!(output, "# TYPE rcomm_bytes_in_total counter");
+    let _ = writeln!(output, "rcomm_bytes_in_total {}", traffic.bytes_in());
+    let _ = writeln!(output, "# TYPE rcomm_bytes_out_total counter");
+    let _ = writeln!(output, "rcomm_bytes_out_total {}", traffic.bytes_out());
+
+    let _ = writeln!(output, "# TYPE rcomm_responses_by_class_total counter");
+    for class in 1..=5 {
+        let _ = writeln!(output, "rcomm_responses_by_class_total{{class=\"{class}xx\"}} {}", responses.class_count(class));
+    }
+    let _ = writeln!(output, "# TYPE rcomm_responses_total counter");
+    for (code, count) in responses.snapshot() {
+        let _ = writeln!(output, "rcomm_responses_total{{code=\"{code}\"}} {count}");
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_by_code_and_class() {
+        let stats = ResponseStats::default();
+        for code in [200, 204, 404, 200] {
+            stats.record(code);
+        }
+        assert_eq!(stats.count(200), 2);
+        assert_eq!(stats.count(500), 0);
+        assert_eq!(stats.class_count(2), 3);
+        assert_eq!(stats.class_count(4), 1);
+
+        let traffic = TrafficCounters::default();
+        traffic.add_in(10);
+        let text = render_metrics(&stats, &traffic);
+        assert!(text.contains("rcomm_bytes_in_total 10\n"));
+        assert!(text.contains("rcomm_responses_by_class_total{class=\"2xx\"} 3\n"));
+        assert!(text.contains("rcomm_responses_total{code=\"404\"} 1\n"));
+    }
+}
@@ -0,0 +1,81 @@
+use crate::models::{
+    http_request::HttpRequest,
+    http_response::HttpResponse,
+};
+
+/// Cross-cutting logic run around route handling. Implementations call
+/// `next` to continue down the chain, and may inspect or modify the request
+/// and response on either side of it, or short-circuit by not calling it.
+pub trait Middleware: Send + Sync {
+    fn handle(&self, request: &HttpRequest, next: &dyn Fn(&HttpRequest) -> HttpResponse) -> HttpResponse;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use crate::models::http_methods::HttpMethods;
+    use crate::router::Router;
+
+    struct AddHeader(&'static str);
+
+    impl Middleware for AddHeader {
+        fn handle(&self, request: &HttpRequest, next: &dyn Fn(&HttpRequest) -> HttpResponse) -> HttpResponse {
+            let mut response = next(request);
+            response.append_header("X-Mw".to_string(), self.0.to_string());
+            response
+        }
+    }
+
+    struct Deny;
+
+    impl Middleware for Deny {
+        fn handle(&self, _request: &HttpRequest, _next: &dyn Fn(&HttpRequest) -> HttpResponse) -> HttpResponse {
+            HttpResponse::build(String::from("HTTP/1.1"), 403)
+        }
+    }
+
+    fn get(target: &str) -> HttpRequest {
+        HttpRequest::build(HttpMethods::GET, target.to_string(), "HTTP/1.1".to_string())
+    }
+
+    fn teapot_router() -> Router {
+        let mut router = Router::new(HashMap::new());
+        router.set_fallback(|_request| HttpResponse::build(String::from("HTTP/1.1"), 418));
+        router
+    }
+
+    #[test]
+    fn middleware_injects_header_into_every_response() {
+        let mut router = teapot_router();
+        router.add_middleware(AddHeader("yes"));
+
+        for target in ["/", "/a", "/b/c"] {
+            let resp = router.handle(&get(target));
+            assert_eq!(resp.try_get_header("x-mw".to_string()), Some("yes".to_string()));
+        }
+    }
+
+    #[test]
+    fn middleware_runs_in_registration_order() {
+        let mut router = teapot_router();
+        router.add_middleware(AddHeader("outer"))
+            .add_middleware(AddHeader("inner"));
+
+        let resp = router.handle(&get("/"));
+        // The innermost middleware sees the response first.
+        assert_eq!(
+            resp.get_header_values("x-mw".to_string()),
+            vec!["inner".to_string(), "outer".to_string()]
+        );
+    }
+
+    #[test]
+    fn middleware_can_short_circuit() {
+        let mut router = teapot_router();
+        router.add_middleware(Deny);
+
+        let resp = router.handle(&get("/"));
+        assert!(format!("{resp}").starts_with("HTTP/1.1 403 Forbidden\r\n"));
+    }
+}
@@ -1,4 +1,8 @@
+pub mod http_conditional;
+pub mod http_date;
 pub mod http_methods;
+pub mod http_range;
 pub mod http_request;
 pub mod http_response;
 pub mod http_status_codes;
+pub mod mime_types;
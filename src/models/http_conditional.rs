@@ -0,0 +1,96 @@
+use std::{
+    fs::Metadata,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use super::http_date::parse_http_date;
+use super::http_request::HttpRequest;
+
+/// What a conditional request is checked against: the `ETag` and
+/// `Last-Modified` of the resource as it is now.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Validators {
+    pub etag: String,
+    pub last_modified: SystemTime,
+}
+
+impl Validators {
+    /// Validators for a file, from its size and modification time. `None`
+    /// if the platform doesn't report modification times.
+    pub fn from_metadata(metadata: &Metadata) -> Option<Validators> {
+        let last_modified = metadata.modified().ok()?;
+        let nanos = last_modified.duration_since(UNIX_EPOCH).map_or(0, |since| since.as_nanos());
+        Some(Validators {
+            etag: format!("\"{nanos:x}-{:x}\"", metadata.len()),
+            last_modified,
+        })
+    }
+}
+
+/// Evaluates `If-Match` and `If-Unmodified-Since` (RFC 9110 §13.2.2) before
+/// a state-changing request. `current` is `None` when the resource doesn't
+/// exist. Returns `false` when the write should be refused with
+/// `412 Precondition Failed`.
+pub fn preconditions_hold(request: &HttpRequest, current: Option<&Validators>) -> bool {
+    if let Some(if_match) = request.try_get_header("If-Match".to_string()) {
+        let Some(current) = current else { return false; };
+        // Strong comparison: weak tags never match.
+        return if_match
+            .split(',')
+            .map(str::trim)
+            .any(|tag| tag == "*" || tag == current.etag);
+    }
+
+    if let Some(since) = request.try_get_header("If-Unmodified-Since".to_string())
+        && let Some(since) = parse_http_date(&since)
+    {
+        let Some(current) = current else { return false; };
+        // HTTP dates have whole-second resolution.
+        let modified = current.last_modified.duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs());
+        let since = since.duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs());
+        return modified <= since;
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::models::http_date::format_http_date;
+    use crate::models::http_methods::HttpMethods;
+
+    fn put_with(title: &str, value: &str) -> HttpRequest {
+        HttpRequest::build(HttpMethods::PUT, "/page".to_string(), "HTTP/1.1".to_string())
+            .with_header(title.to_string(), value.to_string())
+    }
+
+    fn validators() -> Validators {
+        Validators {
+            etag: String::from("\"abc-10\""),
+            last_modified: UNIX_EPOCH + Duration::from_secs(784_111_777),
+        }
+    }
+
+    #[test]
+    fn if_match_needs_a_current_strong_tag() {
+        let current = validators();
+        assert!(preconditions_hold(&put_with("If-Match", "\"abc-10\""), Some(&current)));
+        assert!(preconditions_hold(&put_with("If-Match", "\"old\", \"abc-10\""), Some(&current)));
+        assert!(preconditions_hold(&put_with("If-Match", "*"), Some(&current)));
+        assert!(!preconditions_hold(&put_with("If-Match", "\"old\""), Some(&current)));
+        assert!(!preconditions_hold(&put_with("If-Match", "W/\"abc-10\""), Some(&current)));
+        assert!(!preconditions_hold(&put_with("If-Match", "*"), None));
+    }
+
+    #[test]
+    fn if_unmodified_since_compares_whole_seconds() {
+        let current = validators();
+        let at = |secs| format_http_date(UNIX_EPOCH + Duration::from_secs(secs));
+        assert!(preconditions_hold(&put_with("If-Unmodified-Since", &at(784_111_777)), Some(&current)));
+        assert!(!preconditions_hold(&put_with("If-Unmodified-Since", &at(784_111_776)), Some(&current)));
+        // An unparseable date is ignored.
+        assert!(preconditions_hold(&put_with("If-Unmodified-Since", "yesterday"), Some(&current)));
+        assert!(preconditions_hold(&HttpRequest::build(HttpMethods::PUT, "/".to_string(), "HTTP/1.1".to_string()), None));
+    }
+}
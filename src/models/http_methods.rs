@@ -1,6 +1,6 @@
 use std::fmt;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum HttpMethods {
     GET,
     HEAD,
@@ -11,6 +11,9 @@ pub enum HttpMethods {
     OPTIONS,
     TRACE,
     PATCH,
+    /// A method outside the standard set (e.g. WebDAV's `PROPFIND`), stored
+    /// with its original casing.
+    Extension(String),
 }
 
 impl fmt::Display for HttpMethods {
@@ -25,6 +28,7 @@ impl fmt::Display for HttpMethods {
             HttpMethods::OPTIONS => "OPTIONS",
             HttpMethods::TRACE => "TRACE",
             HttpMethods::PATCH => "PATCH",
+            HttpMethods::Extension(method) => method,
         };
         write!(f, "{}", method)
     }
@@ -41,10 +45,20 @@ pub fn http_method_from_string(method: &str) -> Option<HttpMethods> {
         "OPTIONS" => Some(HttpMethods::OPTIONS),
         "TRACE" => Some(HttpMethods::TRACE),
         "PATCH" => Some(HttpMethods::PATCH),
+        _ if is_valid_method_token(method) => Some(HttpMethods::Extension(method.to_string())),
         _ => None,
     }
 }
 
+/// Whether `method` is a non-empty RFC 9110 token: letters, digits and
+/// ``!#$%&'*+-.^_`|~``.
+pub fn is_valid_method_token(method: &str) -> bool {
+    !method.is_empty()
+        && method
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -57,10 +71,44 @@ mod tests {
     }
 
     #[test]
-    fn rejects_non_uppercase_methods() {
-        assert_eq!(http_method_from_string("get"), None);
-        assert_eq!(http_method_from_string("Get"), None);
-        assert_eq!(http_method_from_string("post"), None);
-        assert_eq!(http_method_from_string("Post"), None);
+    fn non_uppercase_methods_are_not_standard_methods() {
+        assert_ne!(http_method_from_string("get"), Some(HttpMethods::GET));
+        assert_ne!(http_method_from_string("Get"), Some(HttpMethods::GET));
+        assert_ne!(http_method_from_string("post"), Some(HttpMethods::POST));
+        assert_ne!(http_method_from_string("Post"), Some(HttpMethods::POST));
+    }
+
+    #[test]
+    fn parses_unknown_methods_as_extension() {
+        assert_eq!(
+            http_method_from_string("PROPFIND"),
+            Some(HttpMethods::Extension("PROPFIND".to_string()))
+        );
+        assert_eq!(
+            http_method_from_string("get"),
+            Some(HttpMethods::Extension("get".to_string()))
+        );
+    }
+
+    #[test]
+    fn extension_method_round_trips_through_display() {
+        let method = http_method_from_string("MKCOL").unwrap();
+        assert_eq!(method.to_string(), "MKCOL");
+    }
+
+    #[test]
+    fn rejects_empty_method() {
+        assert_eq!(http_method_from_string(""), None);
+    }
+
+    #[test]
+    fn method_tokens_allow_only_tchars() {
+        for valid in ["PROPFIND", "M-SEARCH", "X_CUSTOM.1", "!#$%&'*+-.^_`|~"] {
+            assert!(is_valid_method_token(valid), "{valid}");
+        }
+        for invalid in ["", "GE T", "GET\x00", "GET:", "GET(1)", "MÉTHODE", "GET\r"] {
+            assert!(!is_valid_method_token(invalid), "{invalid:?}");
+            assert_eq!(http_method_from_string(invalid), None, "{invalid:?}");
+        }
     }
 }
@@ -0,0 +1,73 @@
+/// What a `Range` header asks for, resolved against a body of known length.
+#[derive(Debug, PartialEq)]
+pub enum ByteRange {
+    /// Serve bytes `start..=end`.
+    Satisfiable { start: usize, end: usize },
+    /// Nothing requested overlaps the body; answer `416`.
+    Unsatisfiable,
+}
+
+/// Parses a single-range `bytes=` header (`a-b`, `a-` or `-n`) against a
+/// body of `len` bytes. Headers the server doesn't honour (other units,
+/// multiple ranges, bad syntax) give `None`, meaning the whole body should
+/// be served as usual.
+pub fn parse_byte_range(header: &str, len: usize) -> Option<ByteRange> {
+    let spec = header.trim().strip_prefix("bytes=")?.trim();
+    if spec.contains(',') {
+        return None;
+    }
+    let (first, last) = spec.split_once('-')?;
+    let (first, last) = (first.trim(), last.trim());
+
+    if first.is_empty() {
+        let suffix: usize = last.parse().ok()?;
+        if suffix == 0 || len == 0 {
+            return Some(ByteRange::Unsatisfiable);
+        }
+        return Some(ByteRange::Satisfiable { start: len.saturating_sub(suffix), end: len - 1 });
+    }
+
+    let start: usize = first.parse().ok()?;
+    let end = match last {
+        "" => None,
+        last => Some(last.parse::<usize>().ok()?),
+    };
+    if end.is_some_and(|end| end < start) {
+        return None;
+    }
+    if start >= len {
+        return Some(ByteRange::Unsatisfiable);
+    }
+    let end = end.map_or(len - 1, |end| end.min(len - 1));
+    Some(ByteRange::Satisfiable { start, end })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_bounded_open_and_suffix_ranges() {
+        assert_eq!(parse_byte_range("bytes=0-4", 10), Some(ByteRange::Satisfiable { start: 0, end: 4 }));
+        assert_eq!(parse_byte_range("bytes=6-", 10), Some(ByteRange::Satisfiable { start: 6, end: 9 }));
+        assert_eq!(parse_byte_range("bytes=-3", 10), Some(ByteRange::Satisfiable { start: 7, end: 9 }));
+        assert_eq!(parse_byte_range("bytes=-30", 10), Some(ByteRange::Satisfiable { start: 0, end: 9 }));
+        assert_eq!(parse_byte_range("bytes=5-100", 10), Some(ByteRange::Satisfiable { start: 5, end: 9 }));
+    }
+
+    #[test]
+    fn out_of_bounds_ranges_are_unsatisfiable() {
+        assert_eq!(parse_byte_range("bytes=10-", 10), Some(ByteRange::Unsatisfiable));
+        assert_eq!(parse_byte_range("bytes=-0", 10), Some(ByteRange::Unsatisfiable));
+        assert_eq!(parse_byte_range("bytes=0-", 0), Some(ByteRange::Unsatisfiable));
+    }
+
+    #[test]
+    fn unsupported_headers_are_ignored() {
+        assert_eq!(parse_byte_range("items=0-4", 10), None);
+        assert_eq!(parse_byte_range("bytes=0-1,4-5", 10), None);
+        assert_eq!(parse_byte_range("bytes=4-1", 10), None);
+        assert_eq!(parse_byte_range("bytes=x-", 10), None);
+        assert_eq!(parse_byte_range("bytes=", 10), None);
+    }
+}
@@ -1,39 +1,141 @@
 use std::{
     collections::HashMap,
     fmt,
-    io::{BufReader, prelude::*},
-    net::TcpStream,
+    fs,
+    io::{self, BufReader, prelude::*},
+    net::{IpAddr, TcpStream},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
 };
 use super::http_methods::*;
+use super::http_response::HttpResponse;
+use crate::gzip::{self, GzipError};
+use crate::router::clean_route;
 
-const MAX_HEADER_LINE_LEN: usize = 8192;
+/// Default limit on the request line, which carries the URL.
+pub const MAX_REQUEST_LINE_LEN: usize = 16 * 1024;
+/// Default limit on each header line.
+pub const MAX_HEADER_LINE_LEN: usize = 8192;
+pub(crate) const MAX_HEADERS: usize = 100;
+pub const MAX_BODY_LEN: usize = 10 * 1024 * 1024;
+/// Default time allowed from the start of a request to the end of its headers.
+pub const DEFAULT_HEADER_DEADLINE: Duration = Duration::from_secs(10);
 
 #[derive(Debug)]
 pub enum HttpParseError {
+    UriTooLong,
     HeaderTooLong,
+    TooManyHeaders,
     MissingHostHeader,
     MalformedRequestLine,
+    MalformedHeader,
+    MalformedStatusLine,
+    BodyTooLarge,
+    BadContentEncoding,
+    HeaderTimeout,
+    /// An `Expect` header other than `100-continue`.
+    ExpectationFailed,
+    /// A `Content-Length` that isn't a single plain decimal number.
+    InvalidContentLength,
+    /// The peer closed the connection without sending a request line. Not
+    /// an error to answer: there is no request.
+    ConnectionClosed,
     IoError(std::io::Error),
 }
 
 impl fmt::Display for HttpParseError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
+            HttpParseError::UriTooLong => write!(f, "Request line exceeds maximum length"),
             HttpParseError::HeaderTooLong => write!(f, "Header line exceeds maximum length"),
+            HttpParseError::TooManyHeaders => write!(f, "Too many header fields"),
             HttpParseError::MissingHostHeader => write!(f, "Missing required Host header"),
             HttpParseError::MalformedRequestLine => write!(f, "Malformed request line"),
+            HttpParseError::MalformedHeader => write!(f, "Malformed header field"),
+            HttpParseError::MalformedStatusLine => write!(f, "Malformed status line"),
+            HttpParseError::BodyTooLarge => write!(f, "Request body exceeds maximum length"),
+            HttpParseError::BadContentEncoding => write!(f, "Request body could not be decoded"),
+            HttpParseError::HeaderTimeout => write!(f, "Request headers not received in time"),
+            HttpParseError::ExpectationFailed => write!(f, "Unsupported expectation"),
+            HttpParseError::InvalidContentLength => write!(f, "Invalid Content-Length header"),
+            HttpParseError::ConnectionClosed => write!(f, "Connection closed before a request was sent"),
             HttpParseError::IoError(e) => write!(f, "IO error: {e}"),
         }
     }
 }
 
+impl HttpParseError {
+    /// The status code to answer a request that failed to parse with.
+    pub fn status_code(&self) -> u16 {
+        match self {
+            HttpParseError::UriTooLong => 414,
+            HttpParseError::HeaderTooLong | HttpParseError::TooManyHeaders => 431,
+            HttpParseError::BodyTooLarge => 413,
+            HttpParseError::HeaderTimeout => 408,
+            HttpParseError::ExpectationFailed => 417,
+            HttpParseError::IoError(e)
+                if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) =>
+            {
+                408
+            }
+            _ => 400,
+        }
+    }
+}
+
+/// Knobs for `HttpRequest::build_from_reader_with`.
+#[derive(Debug, Clone)]
+pub struct ParseOptions {
+    /// Request targets whose bodies are streamed to a file in `upload_dir`
+    /// instead of being buffered in memory.
+    pub upload_routes: Vec<String>,
+    pub upload_dir: PathBuf,
+    /// Size cap for streamed uploads, which may exceed `MAX_BODY_LEN`.
+    pub max_upload_len: usize,
+    /// Size cap for buffered bodies, before and after gzip decoding.
+    pub max_body_len: usize,
+    /// Overall limit on receiving the request line and headers, however
+    /// steadily the bytes trickle in. `None` disables it.
+    pub header_deadline: Option<Duration>,
+    /// Longest request line accepted; longer ones get `UriTooLong`.
+    pub max_request_line_len: usize,
+    /// Longest header line accepted; longer ones get `HeaderTooLong`.
+    pub max_header_line_len: usize,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions {
+            upload_routes: Vec::new(),
+            upload_dir: std::env::temp_dir(),
+            max_upload_len: MAX_BODY_LEN,
+            max_body_len: MAX_BODY_LEN,
+            header_deadline: Some(DEFAULT_HEADER_DEADLINE),
+            max_request_line_len: MAX_REQUEST_LINE_LEN,
+            max_header_line_len: MAX_HEADER_LINE_LEN,
+        }
+    }
+}
+
+impl ParseOptions {
+    fn is_upload_route(&self, target: &str) -> bool {
+        let target = target.trim_end_matches('/');
+        self.upload_routes.iter().any(|route| route.trim_end_matches('/') == target)
+    }
+}
+
+static UPLOAD_COUNTER: AtomicU64 = AtomicU64::new(0);
+
 #[derive(Debug)]
 pub struct HttpRequest {
     pub method: HttpMethods,
     pub target: String,
     pub version: String,
     headers: HashMap<String, String>,
-    body: Option<Vec<u8>>
+    body: Option<Vec<u8>>,
+    body_file: Option<PathBuf>,
+    remote_addr: Option<IpAddr>,
 }
 
 impl HttpRequest {
@@ -44,47 +146,105 @@ impl HttpRequest {
             target,
             version,
             headers,
-            body: None
+            body: None,
+            body_file: None,
+            remote_addr: None,
         }
     }
 
+    /// Parses one request from `stream`. Anything read past its end is
+    /// discarded with the internal buffer; use `build_from_reader` with a
+    /// long-lived reader to parse pipelined requests.
     pub fn build_from_stream(stream: &TcpStream) -> Result<HttpRequest, HttpParseError> {
         let mut buf_reader = BufReader::new(stream);
+        HttpRequest::build_from_reader(&mut buf_reader)
+    }
+
+    /// Parses one request from `buf_reader`. Bytes past the end of the
+    /// request stay buffered in the reader, so calling this repeatedly on the
+    /// same reader parses consecutive requests on a persistent connection.
+    pub fn build_from_reader<R: BufRead>(buf_reader: &mut R) -> Result<HttpRequest, HttpParseError> {
+        HttpRequest::build_from_reader_with(buf_reader, &ParseOptions::default())
+    }
 
-        // Parse request line
-        let mut line = String::new();
-        buf_reader.read_line(&mut line).map_err(HttpParseError::IoError)?;
-        let line = line.trim_end_matches(|c| c == '\r' || c == '\n');
+    /// Like `build_from_reader`, but bodies sent to one of
+    /// `options.upload_routes` are streamed to a temp file (see `body_file`)
+    /// rather than buffered. Such bodies are stored as sent, without
+    /// `Content-Encoding` decoding.
+    pub fn build_from_reader_with<R: BufRead>(
+        buf_reader: &mut R,
+        options: &ParseOptions,
+    ) -> Result<HttpRequest, HttpParseError> {
+        HttpRequest::parse(buf_reader, options, None)
+    }
 
-        if line.len() > MAX_HEADER_LINE_LEN {
-            return Err(HttpParseError::HeaderTooLong);
+    /// Like `build_from_reader_with`, but answers `Expect: 100-continue`
+    /// on `writer`: `100 Continue` once the headers are accepted, so the
+    /// client goes on to send its body. A body that is too large fails
+    /// before that, for the caller to answer with `413`.
+    pub fn build_from_connection<R: BufRead, W: Write>(
+        buf_reader: &mut R,
+        writer: &mut W,
+        options: &ParseOptions,
+    ) -> Result<HttpRequest, HttpParseError> {
+        HttpRequest::parse(buf_reader, options, Some(writer))
+    }
+
+    fn parse<R: BufRead>(
+        buf_reader: &mut R,
+        options: &ParseOptions,
+        interim: Option<&mut dyn Write>,
+    ) -> Result<HttpRequest, HttpParseError> {
+        let deadline = options.header_deadline.map(|limit| Instant::now() + limit);
+
+        // Parse request line, skipping one empty line before it (RFC 9112 §2.2)
+        let read_request_line = |buf_reader: &mut R| {
+            read_line_before(buf_reader, options.max_request_line_len, deadline).map_err(|e| match e {
+                HttpParseError::HeaderTooLong => HttpParseError::UriTooLong,
+                e => e,
+            })
+        };
+        let mut line = read_request_line(buf_reader)?;
+        if line == "\r\n" || line == "\n" {
+            line = read_request_line(buf_reader)?;
+        }
+        // Nothing read at all is a closed connection; a line of only
+        // whitespace is a malformed request.
+        if line.is_empty() {
+            return Err(HttpParseError::ConnectionClosed);
         }
+        let line = line.trim_end_matches(['\r', '\n']);
 
         let mut iter = line.split_whitespace();
         let method_str = iter.next().ok_or(HttpParseError::MalformedRequestLine)?;
         let method = http_method_from_string(method_str).ok_or(HttpParseError::MalformedRequestLine)?;
-        let target = iter.next().ok_or(HttpParseError::MalformedRequestLine)?.to_string();
+        let target = iter.next().ok_or(HttpParseError::MalformedRequestLine)?;
+        // Fragments are client-side only; drop one a misbehaving client sent.
+        let target = target.split('#').next().unwrap_or_default().to_string();
         let version = iter.next().ok_or(HttpParseError::MalformedRequestLine)?.to_string();
+        if parse_http_version(&version).is_none() {
+            return Err(HttpParseError::MalformedRequestLine);
+        }
         let mut request = HttpRequest::build(method, target, version);
 
         // Parse headers
+        let mut header_count = 0;
         loop {
-            let mut header_line = String::new();
-            let len = buf_reader.read_line(&mut header_line).map_err(HttpParseError::IoError)?;
-            if len == 0 {
+            let header_line = read_line_before(buf_reader, options.max_header_line_len, deadline)?;
+            if header_line.is_empty() {
                 break;
             }
-            let header_line = header_line.trim_end_matches(|c| c == '\r' || c == '\n');
-
-            if header_line.len() > MAX_HEADER_LINE_LEN {
-                return Err(HttpParseError::HeaderTooLong);
-            }
+            let header_line = header_line.trim_end_matches(['\r', '\n']);
 
             if header_line.is_empty() {
                 break;
             }
-            let Some((title, value)) = header_line.split_once(":") else { break; };
-            request.add_header(title.to_string(), value.trim().to_string());
+            header_count += 1;
+            if header_count > MAX_HEADERS {
+                return Err(HttpParseError::TooManyHeaders);
+            }
+            let (title, value) = parse_header_line(header_line)?;
+            request.add_header(title.to_string(), value.to_string());
         }
 
         // Validate Host header for HTTP/1.1
@@ -92,20 +252,88 @@ impl HttpRequest {
             return Err(HttpParseError::MissingHostHeader);
         }
 
+        let expects_continue = match request.headers.get("expect") {
+            Some(expect) if expect.trim().eq_ignore_ascii_case("100-continue") => request.version != "HTTP/1.0",
+            Some(_) => return Err(HttpParseError::ExpectationFailed),
+            None => false,
+        };
+
         // Parse body if Content-Length is present
-        if let Some(content_length) = request.headers.get("content-length") {
-            if let Ok(len) = content_length.parse::<usize>() {
-                if len > 0 {
-                    let mut body_buf = vec![0u8; len];
-                    buf_reader.read_exact(&mut body_buf).map_err(HttpParseError::IoError)?;
-                    request.add_body(body_buf);
-                }
+        let len = match request.headers.get("content-length") {
+            Some(content_length) => parse_content_length(content_length)?,
+            None => 0,
+        };
+        if len > 0 {
+            let upload = options.is_upload_route(&request.normalized_path());
+            let limit = if upload { options.max_upload_len } else { options.max_body_len };
+            if len > limit {
+                return Err(HttpParseError::BodyTooLarge);
+            }
+            if let Some(writer) = interim
+                && expects_continue
+            {
+                HttpResponse::write_informational(writer, 100, &[])
+                    .and_then(|()| writer.flush())
+                    .map_err(HttpParseError::IoError)?;
             }
+            if upload {
+                request.body_file = Some(spool_body(buf_reader, len, &options.upload_dir)?);
+                return Ok(request);
+            }
+            let mut body_buf = vec![0u8; len];
+            buf_reader.read_exact(&mut body_buf).map_err(HttpParseError::IoError)?;
+            request.add_body(body_buf);
         }
 
+        request.decode_body(options.max_body_len)?;
+
         Ok(request)
     }
 
+    /// Replaces a gzip-encoded body with its decoded bytes, so handlers only
+    /// ever see plaintext. Decoding is capped at `max_len` to defuse
+    /// decompression bombs.
+    fn decode_body(&mut self, max_len: usize) -> Result<(), HttpParseError> {
+        let Some(encoding) = self.headers.get("content-encoding") else { return Ok(()); };
+        if !encoding.trim().eq_ignore_ascii_case("gzip") {
+            return Ok(());
+        }
+        let Some(body) = &self.body else { return Ok(()); };
+
+        let decoded = gzip::decompress(body, max_len).map_err(|e| match e {
+            GzipError::TooLarge => HttpParseError::BodyTooLarge,
+            GzipError::Invalid(_) => HttpParseError::BadContentEncoding,
+        })?;
+        self.headers.remove("content-encoding");
+        self.add_body(decoded);
+        Ok(())
+    }
+
+    /// The target with any `?query` and `#fragment` removed.
+    pub fn target_without_query(&self) -> &str {
+        let end = self.target.find(['?', '#']).unwrap_or(self.target.len());
+        &self.target[..end]
+    }
+
+    /// The query string, without its `?` or any `#fragment`.
+    pub fn query(&self) -> Option<&str> {
+        let (_, rest) = self.target.split_once('?')?;
+        Some(rest.split('#').next().unwrap_or_default())
+    }
+
+    /// The path routing, logging and handlers should agree on: query and
+    /// fragment removed, then cleaned like `clean_route` (empty, `.` and
+    /// `..` segments dropped, so `/a//b/` becomes `/a/b`).
+    pub fn normalized_path(&self) -> String {
+        clean_route(self.target_without_query())
+    }
+
+    /// The `Host` header normalized by `normalize_host`, for matching
+    /// against configured hostnames.
+    pub fn host(&self) -> Option<String> {
+        self.headers.get("host").map(|host| normalize_host(host)).filter(|host| !host.is_empty())
+    }
+
     pub fn add_header(&mut self, title: String, value: String) -> &mut HttpRequest {
         self.headers.insert(title.to_lowercase(), value);
         self
@@ -115,8 +343,57 @@ impl HttpRequest {
         self.headers.get(&title.to_lowercase()).cloned()
     }
 
+    /// Iterates over every header as `(name, value)`, names lowercased.
+    pub fn headers(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.headers.iter().map(|(title, value)| (title.as_str(), value.as_str()))
+    }
+
+    /// The elements of a comma-separated header such as `Accept`, trimmed,
+    /// with empty ones dropped. Commas inside quoted strings don't split.
+    /// Empty if the header is absent.
+    pub fn header_list(&self, title: String) -> Vec<String> {
+        let Some(value) = self.headers.get(&title.to_lowercase()) else { return Vec::new(); };
+        let mut elements = Vec::new();
+        let mut current = String::new();
+        let (mut quoted, mut escaped) = (false, false);
+        for c in value.chars() {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' if quoted => escaped = true,
+                '"' => quoted = !quoted,
+                ',' if !quoted => {
+                    elements.push(std::mem::take(&mut current));
+                    continue;
+                }
+                _ => {}
+            }
+            current.push(c);
+        }
+        elements.push(current);
+        elements
+            .into_iter()
+            .map(|element| element.trim().to_string())
+            .filter(|element| !element.is_empty())
+            .collect()
+    }
+
+    /// Consuming variant of `add_header`, for building outbound requests in
+    /// a single expression.
+    pub fn with_header(mut self, title: String, value: String) -> HttpRequest {
+        self.add_header(title, value);
+        self
+    }
+
+    /// Consuming variant of `add_body`.
+    pub fn with_body(mut self, body: Vec<u8>) -> HttpRequest {
+        self.add_body(body);
+        self
+    }
+
     pub fn add_body(&mut self, body: Vec<u8>) -> &mut HttpRequest {
+        let len = body.len();
         self.body = Some(body);
+        self.headers.insert("content-length".to_string(), len.to_string());
         self
     }
 
@@ -124,34 +401,251 @@ impl HttpRequest {
         self.body.clone()
     }
 
+    /// Records the client's address, after any trusted-proxy resolution.
+    pub fn set_remote_addr(&mut self, addr: IpAddr) -> &mut HttpRequest {
+        self.remote_addr = Some(addr);
+        self
+    }
+
+    pub fn remote_addr(&self) -> Option<IpAddr> {
+        self.remote_addr
+    }
+
+    /// The temp file holding a streamed upload body. It is deleted when the
+    /// request is dropped, so move it elsewhere to keep it.
+    pub fn body_file(&self) -> Option<&Path> {
+        self.body_file.as_deref()
+    }
+
+    /// Whether the request frames a body: a non-zero (or unparseable)
+    /// `Content-Length`, or any `Transfer-Encoding`.
+    pub fn declares_body(&self) -> bool {
+        if self.headers.contains_key("transfer-encoding") {
+            return true;
+        }
+        match self.headers.get("content-length") {
+            Some(len) => len.trim().parse::<usize>().map_or(true, |len| len > 0),
+            None => false,
+        }
+    }
+
     pub fn as_bytes(&self) -> Vec<u8> {
         if let Some(body) = &self.body {
             let mut bytes = format!("{self}").as_bytes().to_vec();
             bytes.append(&mut body.clone());
-            return bytes
+            bytes
+        } else {
+            format!("{self}").as_bytes().to_vec()
+        }
+    }
+}
+
+/// A clone gets its own copy of a spooled upload body, since each request
+/// deletes its `body_file` when dropped.
+///
+/// # Panics
+///
+/// If the body file can't be copied, rather than hand back a request that
+/// silently lost its body.
+impl Clone for HttpRequest {
+    fn clone(&self) -> HttpRequest {
+        let body_file = self.body_file.as_ref().map(|path| {
+            copy_upload_file(path).unwrap_or_else(|e| panic!("failed to copy upload body {}: {e}", path.display()))
+        });
+        HttpRequest {
+            method: self.method.clone(),
+            target: self.target.clone(),
+            version: self.version.clone(),
+            headers: self.headers.clone(),
+            body: self.body.clone(),
+            body_file,
+            remote_addr: self.remote_addr,
+        }
+    }
+}
+
+/// Compares method, target, version, headers (in any order) and body. A
+/// spooled body is compared by content, and the peer address is ignored.
+impl PartialEq for HttpRequest {
+    fn eq(&self, other: &HttpRequest) -> bool {
+        let file_contents = |request: &HttpRequest| request.body_file.as_ref().map(|path| fs::read(path).ok());
+        self.method == other.method
+            && self.target == other.target
+            && self.version == other.version
+            && self.headers == other.headers
+            && self.body == other.body
+            && file_contents(self) == file_contents(other)
+    }
+}
+
+/// Lowercases a `Host` value and strips its port and any trailing dot, so
+/// `Example.COM.:8080` becomes `example.com`. Bracketed IPv6 literals keep
+/// their brackets.
+pub fn normalize_host(host: &str) -> String {
+    let host = host.trim();
+    let host = match host.strip_prefix('[') {
+        Some(rest) => rest.split_once(']').map_or(host, |(literal, _)| &host[..literal.len() + 2]),
+        None => host.rsplit_once(':').map_or(host, |(name, _)| name),
+    };
+    host.trim_end_matches('.').to_ascii_lowercase()
+}
+
+impl Drop for HttpRequest {
+    fn drop(&mut self) {
+        if let Some(path) = &self.body_file {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// Parses a `Content-Length` value. Only plain digits are accepted: a sign,
+/// a comma-separated list or trailing junk would let this server and a
+/// proxy in front of it disagree on where the body ends.
+fn parse_content_length(value: &str) -> Result<usize, HttpParseError> {
+    let value = value.trim();
+    if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(HttpParseError::InvalidContentLength);
+    }
+    value.parse().map_err(|_| HttpParseError::BodyTooLarge)
+}
+
+/// Copies a spooled upload body into a fresh upload file beside it.
+fn copy_upload_file(path: &Path) -> io::Result<PathBuf> {
+    let (copy, mut file) = create_upload_file(path.parent().unwrap_or(Path::new(".")))?;
+    let copied = fs::File::open(path).and_then(|mut original| io::copy(&mut original, &mut file));
+    match copied {
+        Ok(_) => Ok(copy),
+        Err(e) => {
+            let _ = fs::remove_file(&copy);
+            Err(e)
+        }
+    }
+}
+
+/// Creates a fresh upload file under `dir`, returning its path.
+fn create_upload_file(dir: &Path) -> io::Result<(PathBuf, fs::File)> {
+    let id = UPLOAD_COUNTER.fetch_add(1, Ordering::Relaxed);
+    let path = dir.join(format!("rcomm-upload-{}-{id}", std::process::id()));
+    let file = create_private_file(&path)?;
+    Ok((path, file))
+}
+
+/// Creates `path` readable only by the server's user. Anything already
+/// there, a symlink included, is refused rather than opened, so a
+/// predictable name in a shared temp dir can't be hijacked.
+fn create_private_file(path: &Path) -> io::Result<fs::File> {
+    let mut options = fs::File::options();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)
+}
+
+/// Copies exactly `len` body bytes from `reader` into a fresh file under
+/// `dir`, removing the file again if the body is cut short.
+fn spool_body<R: BufRead>(reader: &mut R, len: usize, dir: &Path) -> Result<PathBuf, HttpParseError> {
+    let (path, mut file) = create_upload_file(dir).map_err(HttpParseError::IoError)?;
+
+    let copied = io::copy(&mut reader.take(len as u64), &mut file).and_then(|copied| {
+        if copied < len as u64 {
+            Err(io::ErrorKind::UnexpectedEof.into())
         } else {
-            return format!("{self}").as_bytes().to_vec();
+            Ok(copied)
         }
+    });
+    if let Err(e) = copied {
+        let _ = fs::remove_file(&path);
+        return Err(HttpParseError::IoError(e));
     }
+    Ok(path)
 }
 
 // Will not display body.
 impl fmt::Display for HttpRequest {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let _ = match write!(f, "{} {} {}\r\n", self.method, self.target, self.version) {
-            Ok(result) => result,
-            Err(e) => return Err(e),
-        };
+        write!(f, "{} {} {}\r\n", self.method, self.target, self.version)?;
         for (title, value) in &self.headers {
-            let _ = match write!(f, "{}: {}\r\n", title, value) {
-                Ok(result) => result,
-                Err(e) => return Err(e),
-            };
+            write!(f, "{}: {}\r\n", title, value)?;
         }
         write!(f, "\r\n")
     }
 }
 
+/// Splits a `Name: value` field line at its first colon. The name must be
+/// non-empty; the value may be empty and loses only its surrounding optional
+/// whitespace (spaces and tabs), keeping anything inside it.
+fn parse_header_line(line: &str) -> Result<(&str, &str), HttpParseError> {
+    let (title, value) = line.split_once(':').ok_or(HttpParseError::MalformedHeader)?;
+    if title.is_empty() {
+        return Err(HttpParseError::MalformedHeader);
+    }
+    Ok((title, value.trim_matches([' ', '\t'])))
+}
+
+/// Parses an `HTTP/<major>.<minor>` version token into its single-digit
+/// major and minor numbers.
+pub fn parse_http_version(version: &str) -> Option<(u8, u8)> {
+    let digits = version.strip_prefix("HTTP/")?.as_bytes();
+    match digits {
+        [major, b'.', minor] if major.is_ascii_digit() && minor.is_ascii_digit() => {
+            Some((major - b'0', minor - b'0'))
+        }
+        _ => None,
+    }
+}
+
+/// Reads one line (including its terminator) without buffering more than
+/// `limit` bytes of content, so oversized lines are rejected as soon as the
+/// limit is crossed rather than after the whole line has been read.
+/// Returns an empty string at EOF.
+pub(crate) fn read_bounded_line<R: BufRead>(reader: &mut R, limit: usize) -> Result<String, HttpParseError> {
+    read_line_before(reader, limit, None)
+}
+
+/// `read_bounded_line`, failing with `HeaderTimeout` once `deadline` passes,
+/// checked before every read so a line trickled a byte at a time can't
+/// outlast it.
+fn read_line_before<R: BufRead>(reader: &mut R, limit: usize, deadline: Option<Instant>) -> Result<String, HttpParseError> {
+    // Room for the line content plus a trailing "\r\n".
+    let max_raw_len = limit + 2;
+    let mut bytes = Vec::new();
+
+    loop {
+        if deadline.is_some_and(|deadline| Instant::now() > deadline) {
+            return Err(HttpParseError::HeaderTimeout);
+        }
+        let available = match reader.fill_buf() {
+            Ok(available) => available,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(HttpParseError::IoError(e)),
+        };
+        if available.is_empty() {
+            break;
+        }
+
+        let (chunk_len, done) = match available.iter().position(|&b| b == b'\n') {
+            Some(i) => (i + 1, true),
+            None => (available.len(), false),
+        };
+        if bytes.len() + chunk_len > max_raw_len {
+            return Err(HttpParseError::HeaderTooLong);
+        }
+        bytes.extend_from_slice(&available[..chunk_len]);
+        reader.consume(chunk_len);
+
+        if done {
+            break;
+        }
+    }
+
+    let line = String::from_utf8(bytes)
+        .map_err(|e| HttpParseError::IoError(io::Error::new(io::ErrorKind::InvalidData, e)))?;
+    if line.trim_end_matches(['\r', '\n']).len() > limit {
+        return Err(HttpParseError::HeaderTooLong);
+    }
+    Ok(line)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -404,4 +898,581 @@ mod tests {
         assert!(matches!(result.unwrap_err(), HttpParseError::MissingHostHeader));
         handle.join().unwrap();
     }
+
+    #[test]
+    fn build_from_stream_rejects_huge_line_early() {
+        use std::io::Write;
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let handle = std::thread::spawn(move || {
+            let mut client = std::net::TcpStream::connect(addr).unwrap();
+            let huge_value = "x".repeat(4 * 1024 * 1024);
+            let msg = format!("GET / HTTP/1.1\r\nHost: localhost\r\nX-Huge: {huge_value}\r\n\r\n");
+            // The server stops reading and closes early, so this write may fail.
+            let _ = client.write_all(msg.as_bytes());
+        });
+
+        let (stream, _) = listener.accept().unwrap();
+        let start = std::time::Instant::now();
+        let result = HttpRequest::build_from_stream(&stream);
+
+        assert!(matches!(result.unwrap_err(), HttpParseError::HeaderTooLong));
+        assert!(start.elapsed() < std::time::Duration::from_secs(2));
+        drop(stream);
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn read_bounded_line_accepts_line_at_limit() {
+        let data = format!("{}\r\nrest", "a".repeat(16));
+        let mut reader = BufReader::new(data.as_bytes());
+        let line = read_bounded_line(&mut reader, 16).unwrap();
+        assert_eq!(line, format!("{}\r\n", "a".repeat(16)));
+    }
+
+    #[test]
+    fn read_bounded_line_rejects_line_over_limit() {
+        let data = format!("{}\r\n", "a".repeat(17));
+        let mut reader = BufReader::new(data.as_bytes());
+        let result = read_bounded_line(&mut reader, 16);
+        assert!(matches!(result.unwrap_err(), HttpParseError::HeaderTooLong));
+    }
+
+    #[test]
+    fn build_from_stream_rejects_too_many_headers() {
+        use std::io::Write;
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let handle = std::thread::spawn(move || {
+            let mut client = std::net::TcpStream::connect(addr).unwrap();
+            let mut msg = String::from("GET / HTTP/1.1\r\nHost: localhost\r\n");
+            for i in 0..MAX_HEADERS {
+                msg.push_str(&format!("X-H{i}: v\r\n"));
+            }
+            msg.push_str("\r\n");
+            client.write_all(msg.as_bytes()).unwrap();
+            client.shutdown(std::net::Shutdown::Write).unwrap();
+        });
+
+        let (stream, _) = listener.accept().unwrap();
+        let result = HttpRequest::build_from_stream(&stream);
+
+        assert!(matches!(result.unwrap_err(), HttpParseError::TooManyHeaders));
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn build_from_reader_with_times_out_trickling_headers() {
+        use std::io::Write;
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let handle = std::thread::spawn(move || {
+            let mut client = std::net::TcpStream::connect(addr).unwrap();
+            client.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n").unwrap();
+            // Every read succeeds quickly, but the headers never finish in time.
+            for i in 0..6 {
+                std::thread::sleep(Duration::from_millis(100));
+                if client.write_all(format!("X-Slow-{i}: v\r\n").as_bytes()).is_err() {
+                    return;
+                }
+            }
+            let _ = client.write_all(b"\r\n");
+        });
+
+        let (stream, _) = listener.accept().unwrap();
+        let options = ParseOptions { header_deadline: Some(Duration::from_millis(250)), ..ParseOptions::default() };
+        let started = Instant::now();
+        let result = HttpRequest::build_from_reader_with(&mut BufReader::new(&stream), &options);
+
+        let err = result.unwrap_err();
+        assert!(matches!(err, HttpParseError::HeaderTimeout));
+        assert_eq!(err.status_code(), 408);
+        assert!(started.elapsed() < Duration::from_millis(550));
+        drop(stream);
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn header_deadline_applies_within_a_trickled_line() {
+        /// Hands out `head` in one read, then one byte of `tail` per read.
+        struct Trickle {
+            head: &'static [u8],
+            tail: &'static [u8],
+        }
+        impl Read for Trickle {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                if !self.head.is_empty() {
+                    let n = self.head.len().min(buf.len());
+                    buf[..n].copy_from_slice(&self.head[..n]);
+                    self.head = &self.head[n..];
+                    return Ok(n);
+                }
+                std::thread::sleep(Duration::from_millis(10));
+                let Some((&byte, rest)) = self.tail.split_first() else { return Ok(0) };
+                buf[0] = byte;
+                self.tail = rest;
+                Ok(1)
+            }
+        }
+
+        let mut reader = BufReader::new(Trickle {
+            head: b"GET / HTTP/1.1\r\nHost: localhost\r\nX-Slow: ",
+            tail: b"aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa\r\n\r\n",
+        });
+        let options = ParseOptions { header_deadline: Some(Duration::from_millis(100)), ..ParseOptions::default() };
+        let started = Instant::now();
+        let result = HttpRequest::build_from_reader_with(&mut reader, &options);
+
+        assert!(matches!(result, Err(HttpParseError::HeaderTimeout)));
+        assert!(started.elapsed() < Duration::from_millis(500));
+    }
+
+    #[test]
+    fn build_from_stream_accepts_max_headers() {
+        use std::io::Write;
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let handle = std::thread::spawn(move || {
+            let mut client = std::net::TcpStream::connect(addr).unwrap();
+            let mut msg = String::from("GET / HTTP/1.1\r\nHost: localhost\r\n");
+            for i in 1..MAX_HEADERS {
+                msg.push_str(&format!("X-H{i}: v\r\n"));
+            }
+            msg.push_str("\r\n");
+            client.write_all(msg.as_bytes()).unwrap();
+            client.shutdown(std::net::Shutdown::Write).unwrap();
+        });
+
+        let (stream, _) = listener.accept().unwrap();
+        let req = HttpRequest::build_from_stream(&stream).unwrap();
+
+        assert_eq!(req.try_get_header(format!("X-H{}", MAX_HEADERS - 1)), Some("v".to_string()));
+        handle.join().unwrap();
+    }
+
+    fn parse_raw(raw: &'static [u8]) -> Result<HttpRequest, HttpParseError> {
+        use std::io::Write;
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let handle = std::thread::spawn(move || {
+            let mut client = std::net::TcpStream::connect(addr).unwrap();
+            client.write_all(raw).unwrap();
+            client.shutdown(std::net::Shutdown::Write).unwrap();
+        });
+
+        let (stream, _) = listener.accept().unwrap();
+        let result = HttpRequest::build_from_stream(&stream);
+        handle.join().unwrap();
+        result
+    }
+
+    #[test]
+    fn parse_http_version_accepts_digit_pairs() {
+        assert_eq!(parse_http_version("HTTP/1.1"), Some((1, 1)));
+        assert_eq!(parse_http_version("HTTP/1.0"), Some((1, 0)));
+    }
+
+    #[test]
+    fn parse_http_version_rejects_malformed_tokens() {
+        assert_eq!(parse_http_version("HTTP/1"), None);
+        assert_eq!(parse_http_version("HTTP/x.y"), None);
+        assert_eq!(parse_http_version("HTTP/banana"), None);
+        assert_eq!(parse_http_version("HTTP/1.10"), None);
+        assert_eq!(parse_http_version("http/1.1"), None);
+    }
+
+    #[test]
+    fn build_from_stream_rejects_malformed_version() {
+        for raw in [
+            &b"GET / HTTP/1\r\nHost: localhost\r\n\r\n"[..],
+            &b"GET / HTTP/x.y\r\nHost: localhost\r\n\r\n"[..],
+        ] {
+            let result = parse_raw(raw);
+            assert!(matches!(result.unwrap_err(), HttpParseError::MalformedRequestLine));
+        }
+    }
+
+    #[test]
+    fn header_list_splits_outside_quotes() {
+        let req = HttpRequest::build(HttpMethods::GET, "/".to_string(), "HTTP/1.1".to_string())
+            .with_header("Accept".to_string(), "a, b,c ,, ".to_string())
+            .with_header("Foo".to_string(), r#""x,y", z, "q\",r""#.to_string());
+        assert_eq!(req.header_list("accept".to_string()), vec!["a", "b", "c"]);
+        assert_eq!(req.header_list("Foo".to_string()), vec![r#""x,y""#, "z", r#""q\",r""#]);
+        assert!(req.header_list("Missing".to_string()).is_empty());
+    }
+
+    #[test]
+    fn body_consumes_exactly_content_length() {
+        let raw = b"POST /a HTTP/1.1\r\nHost: x\r\nContent-Length: 5\r\n\r\nhelloGET /b HTTP/1.1\r\nHost: x\r\n\r\n";
+        let mut reader = BufReader::new(&raw[..]);
+        let first = HttpRequest::build_from_reader(&mut reader).unwrap();
+        assert_eq!(first.try_get_body(), Some(b"hello".to_vec()));
+        let second = HttpRequest::build_from_reader(&mut reader).unwrap();
+        assert_eq!(second.method, HttpMethods::GET);
+        assert_eq!(second.target, "/b");
+
+        let short = b"POST /a HTTP/1.1\r\nHost: x\r\nContent-Length: 5\r\n\r\nhel";
+        let result = HttpRequest::build_from_reader(&mut BufReader::new(&short[..]));
+        assert!(matches!(result.unwrap_err(), HttpParseError::IoError(_)));
+    }
+
+    #[test]
+    fn build_from_stream_skips_one_leading_empty_line() {
+        let req = parse_raw(b"\r\nGET / HTTP/1.1\r\nHost: x\r\n\r\n").unwrap();
+        assert_eq!(req.method, HttpMethods::GET);
+        assert_eq!(req.target, "/");
+        let result = parse_raw(b"\r\n\r\nGET / HTTP/1.1\r\nHost: x\r\n\r\n");
+        assert!(matches!(result.unwrap_err(), HttpParseError::MalformedRequestLine));
+    }
+
+    #[test]
+    fn blank_request_line_is_malformed_but_no_bytes_is_a_close() {
+        assert!(matches!(parse_raw(b"   \r\n").unwrap_err(), HttpParseError::MalformedRequestLine));
+        assert!(matches!(parse_raw(b"").unwrap_err(), HttpParseError::ConnectionClosed));
+        assert!(matches!(parse_raw(b"\r\n").unwrap_err(), HttpParseError::ConnectionClosed));
+    }
+
+    #[test]
+    fn build_from_stream_accepts_http10_and_http11() {
+        let req = parse_raw(b"GET / HTTP/1.0\r\n\r\n").unwrap();
+        assert_eq!(req.version, "HTTP/1.0");
+        let req = parse_raw(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        assert_eq!(req.version, "HTTP/1.1");
+    }
+
+    #[test]
+    fn with_body_sets_content_length() {
+        let req = HttpRequest::build(HttpMethods::POST, "/".to_string(), "HTTP/1.1".to_string())
+            .with_body(b"12345".to_vec());
+        assert_eq!(req.try_get_header("Content-Length".to_string()), Some("5".to_string()));
+    }
+
+    #[test]
+    fn built_request_round_trips_through_build_from_stream() {
+        use std::io::Write;
+        use std::net::TcpListener;
+
+        let outbound = HttpRequest::build(HttpMethods::PUT, "/items/7".to_string(), "HTTP/1.1".to_string())
+            .with_header("Host".to_string(), "localhost".to_string())
+            .with_header("X-Trace".to_string(), "abc".to_string())
+            .with_body(b"{\"name\":\"seven\"}".to_vec());
+        let bytes = outbound.as_bytes();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handle = std::thread::spawn(move || {
+            let mut client = std::net::TcpStream::connect(addr).unwrap();
+            client.write_all(&bytes).unwrap();
+            client.shutdown(std::net::Shutdown::Write).unwrap();
+        });
+
+        let (stream, _) = listener.accept().unwrap();
+        let parsed = HttpRequest::build_from_stream(&stream).unwrap();
+        handle.join().unwrap();
+
+        assert_eq!(parsed.method, HttpMethods::PUT);
+        assert_eq!(parsed.target, "/items/7");
+        assert_eq!(parsed.version, "HTTP/1.1");
+        assert_eq!(parsed.try_get_header("x-trace".to_string()), Some("abc".to_string()));
+        assert_eq!(parsed.try_get_body(), outbound.try_get_body());
+    }
+
+    #[test]
+    fn build_from_reader_parses_consecutive_requests() {
+        let raw = b"GET /a HTTP/1.1\r\nHost: x\r\n\r\nGET /b HTTP/1.1\r\nHost: x\r\n\r\n";
+        let mut reader = BufReader::new(&raw[..]);
+        let first = HttpRequest::build_from_reader(&mut reader).unwrap();
+        let second = HttpRequest::build_from_reader(&mut reader).unwrap();
+        assert_eq!(first.target, "/a");
+        assert_eq!(second.target, "/b");
+    }
+
+    // `gzip.compress(b"hello world", mtime=0)`
+    const HELLO_GZ: [u8; 31] = [
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0xcb, 0x48, 0xcd, 0xc9,
+        0xc9, 0x57, 0x28, 0xcf, 0x2f, 0xca, 0x49, 0x01, 0x00, 0x85, 0x11, 0x4a, 0x0d, 0x0b,
+        0x00, 0x00, 0x00,
+    ];
+
+    #[test]
+    fn build_from_reader_decodes_gzip_body() {
+        let mut raw = format!(
+            "POST /upload HTTP/1.1\r\nHost: x\r\nContent-Encoding: gzip\r\nContent-Length: {}\r\n\r\n",
+            HELLO_GZ.len()
+        ).into_bytes();
+        raw.extend_from_slice(&HELLO_GZ);
+
+        let req = HttpRequest::build_from_reader(&mut BufReader::new(&raw[..])).unwrap();
+        assert_eq!(req.try_get_body(), Some(b"hello world".to_vec()));
+        assert_eq!(req.try_get_header("content-length".to_string()), Some("11".to_string()));
+        assert_eq!(req.try_get_header("content-encoding".to_string()), None);
+    }
+
+    #[test]
+    fn build_from_reader_rejects_undecodable_gzip_body() {
+        let raw = b"POST / HTTP/1.1\r\nHost: x\r\nContent-Encoding: gzip\r\nContent-Length: 5\r\n\r\nhello";
+        let result = HttpRequest::build_from_reader(&mut BufReader::new(&raw[..]));
+        assert!(matches!(result.unwrap_err(), HttpParseError::BadContentEncoding));
+    }
+
+    #[test]
+    fn build_from_reader_rejects_oversized_content_length() {
+        let raw = format!("POST / HTTP/1.1\r\nHost: x\r\nContent-Length: {}\r\n\r\n", MAX_BODY_LEN + 1);
+        let result = HttpRequest::build_from_reader(&mut BufReader::new(raw.as_bytes()));
+        assert!(matches!(result.unwrap_err(), HttpParseError::BodyTooLarge));
+    }
+
+    #[test]
+    fn normalized_path_strips_query_fragment_and_duplicate_slashes() {
+        let req = HttpRequest::build(HttpMethods::GET, "/a//b/?x=1#frag".to_string(), "HTTP/1.1".to_string());
+        assert_eq!(req.normalized_path(), "/a/b");
+        assert_eq!(req.target_without_query(), "/a//b/");
+        assert_eq!(req.query(), Some("x=1"));
+
+        let plain = HttpRequest::build(HttpMethods::GET, "/#top".to_string(), "HTTP/1.1".to_string());
+        assert_eq!(plain.normalized_path(), "/");
+        assert_eq!(plain.query(), None);
+    }
+
+    #[test]
+    fn build_from_reader_drops_fragment_from_target() {
+        let raw = b"GET /a//b/?x=1#frag HTTP/1.1\r\nHost: x\r\n\r\n";
+        let request = HttpRequest::build_from_reader(&mut BufReader::new(&raw[..])).unwrap();
+        assert_eq!(request.target, "/a//b/?x=1");
+        assert_eq!(request.normalized_path(), "/a/b");
+        assert_eq!(request.query(), Some("x=1"));
+    }
+
+    #[test]
+    fn parse_header_line_handles_empty_and_padded_values() {
+        assert_eq!(parse_header_line("X-Empty:").unwrap(), ("X-Empty", ""));
+        assert_eq!(parse_header_line("Name:  a : b  ").unwrap(), ("Name", "a : b"));
+        assert_eq!(parse_header_line("Name:\t a\tb \t").unwrap(), ("Name", "a\tb"));
+        assert!(matches!(parse_header_line(": value"), Err(HttpParseError::MalformedHeader)));
+        assert!(matches!(parse_header_line(":"), Err(HttpParseError::MalformedHeader)));
+        assert!(matches!(parse_header_line("no colon"), Err(HttpParseError::MalformedHeader)));
+    }
+
+    #[test]
+    fn build_from_reader_rejects_empty_header_name() {
+        let raw = b"GET / HTTP/1.1\r\nHost: x\r\n: value\r\n\r\n";
+        let err = HttpRequest::build_from_reader(&mut BufReader::new(&raw[..])).unwrap_err();
+        assert!(matches!(err, HttpParseError::MalformedHeader));
+        assert_eq!(err.status_code(), 400);
+
+        let raw = b"GET / HTTP/1.1\r\nHost: x\r\nX-Empty:\r\n\r\n";
+        let request = HttpRequest::build_from_reader(&mut BufReader::new(&raw[..])).unwrap();
+        assert_eq!(request.try_get_header("x-empty".to_string()), Some(String::new()));
+    }
+
+    #[test]
+    fn build_from_reader_with_honors_max_body_len() {
+        let raw = b"POST / HTTP/1.1\r\nHost: x\r\nContent-Length: 5\r\n\r\nhello";
+        let options = ParseOptions { max_body_len: 4, ..ParseOptions::default() };
+        let result = HttpRequest::build_from_reader_with(&mut BufReader::new(&raw[..]), &options);
+        assert!(matches!(result.unwrap_err(), HttpParseError::BodyTooLarge));
+
+        let options = ParseOptions { max_body_len: 5, ..ParseOptions::default() };
+        let request = HttpRequest::build_from_reader_with(&mut BufReader::new(&raw[..]), &options).unwrap();
+        assert_eq!(request.try_get_body(), Some(b"hello".to_vec()));
+    }
+
+    #[test]
+    fn over_long_url_and_header_get_distinct_errors() {
+        let options = ParseOptions { max_request_line_len: 32, max_header_line_len: 16, ..ParseOptions::default() };
+        let parse = |raw: String| HttpRequest::build_from_reader_with(&mut BufReader::new(raw.as_bytes()), &options);
+
+        let long_url = format!("GET /{} HTTP/1.1\r\nHost: x\r\n\r\n", "a".repeat(20));
+        let err = parse(long_url).unwrap_err();
+        assert!(matches!(err, HttpParseError::UriTooLong));
+        assert_eq!(err.status_code(), 414);
+
+        // The same URL fits the request line limit even though it's longer than a header may be.
+        let url_fits = format!("GET /{} HTTP/1.1\r\nHost: x\r\n\r\n", "a".repeat(17));
+        assert!(parse(url_fits).is_ok());
+
+        let long_header = format!("GET / HTTP/1.1\r\nHost: x\r\nX-Big: {}\r\n\r\n", "b".repeat(16));
+        let err = parse(long_header).unwrap_err();
+        assert!(matches!(err, HttpParseError::HeaderTooLong));
+        assert_eq!(err.status_code(), 431);
+    }
+
+    #[test]
+    fn declares_body_detects_length_and_transfer_encoding() {
+        let req = |header: Option<(&str, &str)>| {
+            let req = HttpRequest::build(HttpMethods::GET, "/".to_string(), "HTTP/1.1".to_string());
+            match header {
+                Some((title, value)) => req.with_header(title.to_string(), value.to_string()),
+                None => req,
+            }
+        };
+        assert!(!req(None).declares_body());
+        assert!(!req(Some(("Content-Length", "0"))).declares_body());
+        assert!(req(Some(("Content-Length", "5"))).declares_body());
+        assert!(req(Some(("Content-Length", "abc"))).declares_body());
+        assert!(req(Some(("Transfer-Encoding", "chunked"))).declares_body());
+    }
+
+    #[test]
+    fn build_from_reader_with_streams_upload_to_disk() {
+        let body: Vec<u8> = (0..2 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+        let mut raw = format!("POST /upload HTTP/1.1\r\nHost: x\r\nContent-Length: {}\r\n\r\n", body.len()).into_bytes();
+        raw.extend_from_slice(&body);
+        let options = ParseOptions { upload_routes: vec![String::from("/upload")], ..ParseOptions::default() };
+
+        let req = HttpRequest::build_from_reader_with(&mut &raw[..], &options).unwrap();
+        let path = req.body_file().unwrap().to_path_buf();
+        assert_eq!(req.try_get_body(), None);
+        assert_eq!(fs::read(&path).unwrap(), body);
+
+        drop(req);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn build_from_reader_with_rejects_truncated_upload() {
+        let raw = b"POST /upload HTTP/1.1\r\nHost: x\r\nContent-Length: 10\r\n\r\nshort";
+        let options = ParseOptions { upload_routes: vec![String::from("/upload")], ..ParseOptions::default() };
+        let result = HttpRequest::build_from_reader_with(&mut &raw[..], &options);
+        assert!(matches!(result, Err(HttpParseError::IoError(_))));
+    }
+
+    #[test]
+    fn upload_file_is_private_and_never_reuses_an_existing_path() {
+        let dir = std::env::temp_dir().join(format!("rcomm_upload_private_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let taken = dir.join("taken");
+        fs::write(&taken, "keep me").unwrap();
+        let refused = create_private_file(&taken).map_err(|e| e.kind());
+        #[cfg(unix)]
+        let link_refused = {
+            let link = dir.join("link");
+            std::os::unix::fs::symlink(&taken, &link).unwrap();
+            create_private_file(&link).map_err(|e| e.kind())
+        };
+        let (fresh, _) = create_upload_file(&dir).unwrap();
+        #[cfg(unix)]
+        let mode = {
+            use std::os::unix::fs::PermissionsExt;
+            fs::metadata(&fresh).unwrap().permissions().mode() & 0o777
+        };
+        let kept = fs::read(&taken).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(refused.err(), Some(io::ErrorKind::AlreadyExists));
+        #[cfg(unix)]
+        {
+            assert_eq!(link_refused.err(), Some(io::ErrorKind::AlreadyExists));
+            assert_eq!(mode, 0o600);
+        }
+        assert_eq!(kept, b"keep me");
+    }
+
+    #[test]
+    fn build_from_connection_answers_expect_continue() {
+        let options = ParseOptions { upload_routes: vec![String::from("/upload")], ..ParseOptions::default() };
+        let parse = |raw: &[u8]| {
+            let mut interim = Vec::new();
+            let result = HttpRequest::build_from_connection(&mut &raw[..], &mut interim, &options);
+            (result, interim)
+        };
+
+        let (accepted, interim) = parse(b"POST /upload HTTP/1.1\r\nHost: x\r\nExpect: 100-continue\r\nContent-Length: 5\r\n\r\nhello");
+        assert_eq!(interim, b"HTTP/1.1 100 Continue\r\n\r\n");
+        assert_eq!(fs::read(accepted.unwrap().body_file().unwrap()).unwrap(), b"hello");
+
+        let too_large = format!("POST /upload HTTP/1.1\r\nHost: x\r\nExpect: 100-continue\r\nContent-Length: {}\r\n\r\n", MAX_BODY_LEN + 1);
+        let (rejected, interim) = parse(too_large.as_bytes());
+        assert!(matches!(rejected, Err(HttpParseError::BodyTooLarge)));
+        assert!(interim.is_empty());
+
+        let (unknown, interim) = parse(b"POST /upload HTTP/1.1\r\nHost: x\r\nExpect: teapot\r\nContent-Length: 5\r\n\r\nhello");
+        assert_eq!(unknown.err().map(|e| e.status_code()), Some(417));
+        assert!(interim.is_empty());
+
+        let (old, interim) = parse(b"POST /form HTTP/1.0\r\nExpect: 100-continue\r\nContent-Length: 2\r\n\r\nhi");
+        assert_eq!(old.unwrap().try_get_body(), Some(b"hi".to_vec()));
+        assert!(interim.is_empty());
+    }
+
+    #[test]
+    fn content_length_must_be_plain_digits() {
+        for value in ["5x", "+5", "-1", "5, 5", " ", "0x5"] {
+            let raw = format!("POST /form HTTP/1.1\r\nHost: x\r\nContent-Length: {value}\r\n\r\nhello");
+            let result = HttpRequest::build_from_reader(&mut raw.as_bytes());
+            assert!(matches!(result, Err(HttpParseError::InvalidContentLength)), "accepted {value:?}");
+        }
+
+        let overflow = "POST /form HTTP/1.1\r\nHost: x\r\nContent-Length: 99999999999999999999999\r\n\r\n";
+        let result = HttpRequest::build_from_reader(&mut overflow.as_bytes());
+        assert!(matches!(result, Err(HttpParseError::BodyTooLarge)));
+    }
+
+    #[test]
+    fn requests_compare_by_content_regardless_of_header_order() {
+        let build = |headers: &[(&str, &str)]| {
+            let mut req = HttpRequest::build(HttpMethods::POST, "/form".to_string(), "HTTP/1.1".to_string());
+            for (title, value) in headers {
+                req.add_header(title.to_string(), value.to_string());
+            }
+            req.add_body(b"a=1".to_vec());
+            req
+        };
+        let req = build(&[("Host", "x"), ("Accept", "*/*")]);
+        assert_eq!(req, build(&[("Accept", "*/*"), ("host", "x")]));
+        assert_eq!(req.clone(), req);
+        assert_ne!(req, build(&[("Host", "y"), ("Accept", "*/*")]));
+    }
+
+    #[test]
+    fn clone_of_upload_owns_its_own_body_file() {
+        let raw = b"POST /upload HTTP/1.1\r\nHost: x\r\nContent-Length: 5\r\n\r\nhello";
+        let options = ParseOptions { upload_routes: vec![String::from("/upload")], ..ParseOptions::default() };
+        let req = HttpRequest::build_from_reader_with(&mut &raw[..], &options).unwrap();
+        let copy = req.clone();
+        let copy_path = copy.body_file().unwrap().to_path_buf();
+
+        assert_ne!(req.body_file(), copy.body_file());
+        assert_eq!(copy, req);
+        drop(req);
+        assert_eq!(fs::read(&copy_path).unwrap(), b"hello");
+        drop(copy);
+        assert!(!copy_path.exists());
+    }
+
+    #[test]
+    #[should_panic(expected = "failed to copy upload body")]
+    fn clone_of_upload_panics_when_the_body_is_gone() {
+        let raw = b"POST /upload HTTP/1.1\r\nHost: x\r\nContent-Length: 5\r\n\r\nhello";
+        let options = ParseOptions { upload_routes: vec![String::from("/upload")], ..ParseOptions::default() };
+        let req = HttpRequest::build_from_reader_with(&mut &raw[..], &options).unwrap();
+        fs::remove_file(req.body_file().unwrap()).unwrap();
+        let _ = req.clone();
+    }
+
+    #[test]
+    fn host_is_normalized() {
+        let mut req = HttpRequest::build(HttpMethods::GET, "/".to_string(), "HTTP/1.1".to_string());
+        assert_eq!(req.host(), None);
+        req.add_header("Host".to_string(), "Example.COM.:8080".to_string());
+        assert_eq!(req.host(), Some("example.com".to_string()));
+        assert_eq!(normalize_host("[::1]:7878"), "[::1]");
+        assert_eq!(normalize_host(" site.test "), "site.test");
+    }
 }
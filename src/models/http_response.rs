@@ -1,20 +1,111 @@
 use std::{
     collections::HashMap,
     fmt,
+    fs,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::TcpStream,
+    path::{Path, PathBuf},
+    sync::{
+        Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+use super::http_conditional::Validators;
+use super::http_date::format_http_date;
+use super::http_request::{
+    HttpParseError, MAX_BODY_LEN, MAX_HEADERS, MAX_HEADER_LINE_LEN, parse_http_version, read_bounded_line,
 };
 use super::http_status_codes::get_status_phrase;
+use super::mime_types::get_mime_type;
 
 pub struct HttpResponse {
     version: String,
     status_code: u16,
     status_phrase: String,
-    headers: HashMap<String, String>,
-    body: Option<Vec<u8>>
+    headers: HashMap<String, Vec<String>>,
+    body: Option<Vec<u8>>,
+    /// A body of unknown length, sent chunked by `write_to`.
+    body_stream: Option<Mutex<Box<dyn Read + Send>>>,
+    /// A body moved to disk by `extend_body` once it outgrew `spill_threshold`.
+    spilled: Option<SpilledBody>,
+    /// `None` until `set_spill_threshold`; `DEFAULT_SPILL_THRESHOLD` applies.
+    spill_threshold: Option<usize>,
+    /// Whether `body` was grown by `extend_body`, the only kind that spills.
+    extended: bool,
+}
+
+/// A response taken apart by `HttpResponse::into_parts`, in the order
+/// `HttpResponse::from_parts` takes them back.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResponseParts {
+    pub version: String,
+    pub code: u16,
+    pub phrase: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<Vec<u8>>,
+}
+
+/// Size of the chunks a streamed body is read and sent in.
+const STREAM_CHUNK_LEN: usize = 8192;
+
+static SPILL_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Size past which a body built with `extend_body` moves to a temp file,
+/// unless changed with `HttpResponse::set_spill_threshold`.
+pub const DEFAULT_SPILL_THRESHOLD: usize = 8 * 1024 * 1024;
+
+/// A body held in a temp file, deleted when the response is dropped.
+struct SpilledBody {
+    path: PathBuf,
+    file: fs::File,
+    len: usize,
+    /// Set when an append failed, so the file no longer matches the body.
+    error: Option<io::ErrorKind>,
+}
+
+impl SpilledBody {
+    fn create(dir: &Path, contents: &[u8]) -> io::Result<SpilledBody> {
+        let id = SPILL_COUNTER.fetch_add(1, Ordering::Relaxed);
+        let path = dir.join(format!("rcomm-spill-{}-{id}", std::process::id()));
+        let mut options = fs::File::options();
+        options.write(true).create_new(true);
+        // The body may be private to the client; keep other users out.
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let file = options.open(&path)?;
+        let mut spilled = SpilledBody { path, file, len: 0, error: None };
+        spilled.append(contents);
+        Ok(spilled)
+    }
+
+    fn append(&mut self, more: &[u8]) {
+        if self.error.is_none() {
+            match self.file.write_all(more) {
+                Ok(()) => self.len += more.len(),
+                Err(e) => self.error = Some(e.kind()),
+            }
+        }
+    }
+
+    fn open(&self) -> io::Result<fs::File> {
+        match self.error {
+            Some(kind) => Err(io::Error::new(kind, "spilled body could not be written to disk")),
+            None => fs::File::open(&self.path),
+        }
+    }
+}
+
+impl Drop for SpilledBody {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
 }
 
 impl HttpResponse {
     pub fn build(version: String, code: u16) -> HttpResponse {
-        let headers = HashMap::<String, String>::new();
+        let headers = HashMap::<String, Vec<String>>::new();
         let phrase = get_status_phrase(code);
         HttpResponse {
             version,
@@ -22,36 +113,533 @@ impl HttpResponse {
             status_phrase: phrase,
             headers,
             body: None,
+            body_stream: None,
+            spilled: None,
+            spill_threshold: None,
+            extended: false,
+        }
+    }
+
+    /// Builds a 200 response serving the file at `path`, with `Content-Type`
+    /// derived from its extension and `Last-Modified` and `ETag` from its
+    /// metadata.
+    /// Read failures (e.g. a missing file) are returned to the caller.
+    pub fn from_file(version: String, path: &Path) -> io::Result<HttpResponse> {
+        let contents = fs::read(path)?;
+        let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
+
+        let mut response = HttpResponse::build(version, 200);
+        response.add_header("Content-Type".to_string(), get_mime_type(extension).to_string());
+        if let Some(validators) = fs::metadata(path).ok().as_ref().and_then(Validators::from_metadata) {
+            response.add_header("Last-Modified".to_string(), format_http_date(validators.last_modified));
+            response.add_header("ETag".to_string(), validators.etag);
+        }
+        response.add_body(contents);
+        Ok(response)
+    }
+
+    /// Builds the response to a request that failed to parse: the matching
+    /// status, a short plaintext reason and `Connection: close`. I/O error
+    /// details are left out of the body.
+    pub fn from_parse_error(version: &str, err: &HttpParseError) -> HttpResponse {
+        let mut response = HttpResponse::build(version.to_string(), err.status_code());
+        let body = match err {
+            HttpParseError::IoError(_) => format!("{} {}", response.status_code, response.status_phrase),
+            _ => format!("{} {}: {err}", response.status_code, response.status_phrase),
+        };
+        response.add_header("Content-Type".to_string(), get_mime_type("txt").to_string());
+        response.add_header("Connection".to_string(), "close".to_string());
+        response.add_body(body.into());
+        response
+    }
+
+    pub fn build_from_stream(stream: &TcpStream) -> Result<HttpResponse, HttpParseError> {
+        let mut buf_reader = BufReader::new(stream);
+        HttpResponse::build_from_reader(&mut buf_reader)
+    }
+
+    /// Parses one response from `reader`: the head, then a body framed by
+    /// chunked `Transfer-Encoding`, `Content-Length` or, failing both, by the
+    /// server closing the connection. Bodies are capped at the same limit as
+    /// request bodies.
+    pub fn build_from_reader<R: BufRead>(reader: &mut R) -> Result<HttpResponse, HttpParseError> {
+        let mut response = HttpResponse::build_head_from_reader(reader)?;
+
+        let bodiless = (100..200).contains(&response.status_code)
+            || response.status_code == 204
+            || response.status_code == 304;
+        if bodiless {
+            return Ok(response);
+        }
+
+        let chunked = response
+            .try_get_header("transfer-encoding".to_string())
+            .is_some_and(|coding| coding.to_lowercase().contains("chunked"));
+        if chunked {
+            let body = read_chunked_body(reader)?;
+            response.headers.remove("transfer-encoding");
+            response.add_body(body);
+            return Ok(response);
         }
+
+        match response.try_get_header("content-length".to_string()) {
+            Some(len) => {
+                let len = len.trim().parse::<usize>().map_err(|_| {
+                    HttpParseError::IoError(io::Error::new(io::ErrorKind::InvalidData, "invalid Content-Length"))
+                })?;
+                if len > MAX_BODY_LEN {
+                    return Err(HttpParseError::BodyTooLarge);
+                }
+                let mut body = vec![0u8; len];
+                reader.read_exact(&mut body).map_err(HttpParseError::IoError)?;
+                response.add_body(body);
+            }
+            None => {
+                let mut body = Vec::new();
+                reader
+                    .take(MAX_BODY_LEN as u64 + 1)
+                    .read_to_end(&mut body)
+                    .map_err(HttpParseError::IoError)?;
+                if body.len() > MAX_BODY_LEN {
+                    return Err(HttpParseError::BodyTooLarge);
+                }
+                response.add_body(body);
+            }
+        }
+        Ok(response)
+    }
+
+    /// Parses only the status line and headers, leaving any body unread.
+    /// Use this for responses to `HEAD`, which advertise a length they
+    /// don't send.
+    pub fn build_head_from_reader<R: BufRead>(reader: &mut R) -> Result<HttpResponse, HttpParseError> {
+        let line = read_bounded_line(reader, MAX_HEADER_LINE_LEN)?;
+        let line = line.trim_end_matches(['\r', '\n']);
+
+        let mut parts = line.splitn(3, ' ');
+        let version = parts.next().unwrap_or("");
+        if parse_http_version(version).is_none() {
+            return Err(HttpParseError::MalformedStatusLine);
+        }
+        let code = parts
+            .next()
+            .filter(|code| code.len() == 3)
+            .and_then(|code| code.parse::<u16>().ok())
+            .ok_or(HttpParseError::MalformedStatusLine)?;
+        let mut response = HttpResponse::build(version.to_string(), code);
+        response.status_phrase = parts.next().unwrap_or("").to_string();
+
+        let mut header_count = 0;
+        loop {
+            let header_line = read_bounded_line(reader, MAX_HEADER_LINE_LEN)?;
+            let header_line = header_line.trim_end_matches(['\r', '\n']);
+            if header_line.is_empty() {
+                break;
+            }
+            header_count += 1;
+            if header_count > MAX_HEADERS {
+                return Err(HttpParseError::TooManyHeaders);
+            }
+            let Some((title, value)) = header_line.split_once(':') else { continue; };
+            response.append_header(title.to_string(), value.trim().to_string());
+        }
+        Ok(response)
+    }
+
+    /// Reassembles a response from decoded parts, as returned by
+    /// `into_parts`, without re-parsing. Headers are taken as given,
+    /// repeats included; nothing is added, not even `Content-Length`.
+    pub fn from_parts(
+        version: String,
+        code: u16,
+        phrase: String,
+        headers: Vec<(String, String)>,
+        body: Option<Vec<u8>>,
+    ) -> HttpResponse {
+        let mut response = HttpResponse::build(version, code);
+        response.status_phrase = phrase;
+        for (title, value) in headers {
+            response.append_header(title, value);
+        }
+        response.body = body;
+        response
     }
 
+    /// Splits the response into the parts `from_parts` takes. A spilled body
+    /// is read back into memory, failing if it can't be; a streamed body
+    /// can't be split off and is an `InvalidInput` error.
+    pub fn into_parts(self) -> io::Result<ResponseParts> {
+        if self.body_stream.is_some() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "a streamed body can't be split into parts"));
+        }
+        let body = match &self.spilled {
+            Some(spilled) => {
+                let mut body = Vec::with_capacity(spilled.len);
+                spilled.open()?.read_to_end(&mut body)?;
+                Some(body)
+            }
+            None => self.body.clone(),
+        };
+        let headers = self.headers().map(|(title, value)| (title.to_string(), value.to_string())).collect();
+        Ok(ResponseParts { version: self.version, code: self.status_code, phrase: self.status_phrase, headers, body })
+    }
+
+    pub fn status_code(&self) -> u16 {
+        self.status_code
+    }
+
+    /// Changes the status code, resetting the phrase to match.
+    pub fn set_status_code(&mut self, code: u16) -> &mut HttpResponse {
+        self.status_code = code;
+        self.status_phrase = get_status_phrase(code);
+        self
+    }
+
+    pub fn status_phrase(&self) -> &str {
+        &self.status_phrase
+    }
+
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    /// Sets a header, replacing any values previously stored under `title`.
     pub fn add_header(&mut self, title: String, value: String) -> &mut HttpResponse {
-        self.headers.insert(title.to_lowercase(), value);
+        self.headers.insert(title.to_lowercase(), vec![value]);
+        self
+    }
+
+    /// Adds another value for `title` without clobbering existing ones. Each
+    /// value is serialized on its own header line (e.g. `Set-Cookie`).
+    pub fn append_header(&mut self, title: String, value: String) -> &mut HttpResponse {
+        self.headers.entry(title.to_lowercase()).or_default().push(value);
         self
     }
 
+    /// Returns the first value stored under `title`.
     pub fn try_get_header(&self, title: String) -> Option<String> {
-        self.headers.get(&title.to_lowercase()).cloned()
+        self.headers.get(&title.to_lowercase()).and_then(|values| values.first()).cloned()
+    }
+
+    /// Returns every value stored under `title`, in insertion order.
+    pub fn get_header_values(&self, title: String) -> Vec<String> {
+        self.headers.get(&title.to_lowercase()).cloned().unwrap_or_default()
+    }
+
+    /// Removes `title`, returning the values it had.
+    pub fn remove_header(&mut self, title: String) -> Vec<String> {
+        self.headers.remove(&title.to_lowercase()).unwrap_or_default()
+    }
+
+    /// Sets `Content-Type` to `mime`, trimmed and with the type/subtype
+    /// lowercased. Parameters such as a multipart `boundary` can be
+    /// case-sensitive, so they are kept as given.
+    pub fn set_content_type(&mut self, mime: &str) -> &mut HttpResponse {
+        let mime = mime.trim();
+        let value = match mime.split_once(';') {
+            Some((essence, params)) => format!("{};{params}", essence.trim_end().to_ascii_lowercase()),
+            None => mime.to_ascii_lowercase(),
+        };
+        self.add_header("Content-Type".to_string(), value)
+    }
+
+    /// Sets `Content-Type` to `mime` with a `charset` parameter, e.g.
+    /// `text/html; charset=utf-8`.
+    pub fn set_content_type_charset(&mut self, mime: &str, charset: &str) -> &mut HttpResponse {
+        let value = format!("{}; charset={}", mime.trim().to_ascii_lowercase(), charset.trim().to_ascii_lowercase());
+        self.add_header("Content-Type".to_string(), value)
+    }
+
+    /// Asks the client to download the body as `filename`. Names with
+    /// non-ASCII characters also get an RFC 5987 `filename*`, with `_`
+    /// standing in for those characters in the plain `filename`.
+    pub fn set_attachment(&mut self, filename: &str) -> &mut HttpResponse {
+        let fallback: String = filename
+            .chars()
+            .map(|c| if c.is_ascii() && !c.is_ascii_control() { c } else { '_' })
+            .collect();
+        let quoted = fallback.replace('\\', "\\\\").replace('"', "\\\"");
+        let mut value = format!("attachment; filename=\"{quoted}\"");
+        if !filename.is_ascii() {
+            let encoded: String = filename
+                .bytes()
+                .map(|b| match b {
+                    b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'!' | b'#' | b'$' | b'&' | b'+' | b'-' | b'.' | b'^'
+                    | b'_' | b'`' | b'|' | b'~' => (b as char).to_string(),
+                    _ => format!("%{b:02X}"),
+                })
+                .collect();
+            value.push_str(&format!("; filename*=UTF-8''{encoded}"));
+        }
+        self.add_header("Content-Disposition".to_string(), value)
+    }
+
+    /// Iterates over every header as `(name, value)`, names lowercased.
+    pub fn headers(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.headers
+            .iter()
+            .flat_map(|(title, values)| values.iter().map(move |value| (title.as_str(), value.as_str())))
     }
 
     pub fn add_body(&mut self, body: Vec<u8>) -> &mut HttpResponse {
         let len = body.len();
         self.body = Some(body);
-        self.headers.insert("content-length".to_string(), len.to_string());
+        self.spilled = None;
+        self.extended = false;
+        self.headers.insert("content-length".to_string(), vec![len.to_string()]);
+        self
+    }
+
+    /// Appends `more` to the body, starting one if there is none, and keeps
+    /// `Content-Length` in step. Past the spill threshold the
+    /// body moves to a temp file, staying in memory if the file can't be
+    /// created.
+    pub fn extend_body(&mut self, more: &[u8]) -> &mut HttpResponse {
+        let len = if let Some(spilled) = &mut self.spilled {
+            spilled.append(more);
+            spilled.len
+        } else {
+            let body = self.body.get_or_insert_with(Vec::new);
+            body.extend_from_slice(more);
+            let len = body.len();
+            self.extended = true;
+            self.spill_if_over_threshold();
+            len
+        };
+        self.headers.insert("content-length".to_string(), vec![len.to_string()]);
+        self
+    }
+
+    /// Bounds how much of a body built with `extend_body` is held in memory;
+    /// anything larger is kept in a temp file and streamed from it. A body
+    /// `extend_body` already grew past `threshold` moves to disk right away.
+    pub fn set_spill_threshold(&mut self, threshold: usize) -> &mut HttpResponse {
+        self.spill_threshold = Some(threshold);
+        self.spill_if_over_threshold();
         self
     }
 
+    /// The threshold given to `set_spill_threshold`, if any.
+    pub fn spill_threshold(&self) -> Option<usize> {
+        self.spill_threshold
+    }
+
+    fn spill_if_over_threshold(&mut self) {
+        let threshold = self.spill_threshold.unwrap_or(DEFAULT_SPILL_THRESHOLD);
+        if let Some(body) = &self.body
+            && self.extended
+            && body.len() > threshold
+            && let Ok(spilled) = SpilledBody::create(&std::env::temp_dir(), body)
+        {
+            self.spilled = Some(spilled);
+            self.body = None;
+        }
+    }
+
+    /// Whether the body has been moved to a temp file.
+    pub fn is_spilled(&self) -> bool {
+        self.spilled.is_some()
+    }
+
+    /// Sets a plain-text body, taking ownership of a `String` without copying.
+    pub fn add_text_body(&mut self, text: impl Into<String>) -> &mut HttpResponse {
+        self.set_content_type_charset("text/plain", "utf-8");
+        self.add_body(text.into().into_bytes())
+    }
+
+    /// A copy of the body, read back from disk if it was spilled.
     pub fn try_get_body(&self) -> Option<Vec<u8>> {
-        self.body.clone()
+        match &self.spilled {
+            Some(spilled) => {
+                let mut body = Vec::with_capacity(spilled.len);
+                spilled.open().and_then(|mut file| file.read_to_end(&mut body)).ok().map(|_| body)
+            }
+            None => self.body.clone(),
+        }
     }
 
-    pub fn as_bytes(&self) -> Vec<u8> {
+    /// Streams the body from `reader` with `Transfer-Encoding: chunked`
+    /// rather than buffering it to compute a `Content-Length`. Replaces any
+    /// body set with `add_body`. HTTP/1.0 clients get it close-delimited
+    /// instead (see `close_delimit`).
+    pub fn set_body_stream<R: Read + Send + 'static>(&mut self, reader: R) -> &mut HttpResponse {
+        self.body = None;
+        self.spilled = None;
+        self.headers.remove("content-length");
+        self.headers.insert("transfer-encoding".to_string(), vec!["chunked".to_string()]);
+        self.body_stream = Some(Mutex::new(Box::new(reader)));
+        self
+    }
+
+    /// Whether the body was set with `set_body_stream`.
+    pub fn is_streamed(&self) -> bool {
+        self.body_stream.is_some()
+    }
+
+    /// Sends a streamed body unframed, ended by closing the connection, for
+    /// HTTP/1.0 clients that can't decode chunked encoding. Drops
+    /// `Transfer-Encoding` and sets `Connection: close`.
+    pub fn close_delimit(&mut self) -> &mut HttpResponse {
+        self.headers.remove("transfer-encoding");
+        self.add_header("Connection".to_string(), "close".to_string())
+    }
+
+    /// Length of the body without copying it out.
+    pub fn body_len(&self) -> usize {
+        match &self.spilled {
+            Some(spilled) => spilled.len,
+            None => self.body.as_ref().map_or(0, Vec::len),
+        }
+    }
+
+    /// Writes the serialized response (headers + body) to `writer`. The head
+    /// is formatted piecewise, so wrap unbuffered writers in a `BufWriter`.
+    /// A streamed body is consumed as it is written, so it is only sent once,
+    /// chunked unless `close_delimit` removed the framing. Errors, including
+    /// `WouldBlock`, are passed on; wrap a non-blocking writer in a
+    /// `RetryWriter`.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        write!(writer, "{self}")?;
         if let Some(body) = &self.body {
-            let mut bytes = format!("{self}").as_bytes().to_vec();
-            bytes.append(&mut body.clone());
-            return bytes
+            writer.write_all(body)?;
+        }
+        if let Some(spilled) = &self.spilled {
+            io::copy(&mut spilled.open()?.take(spilled.len as u64), writer)?;
+        }
+        if let Some(stream) = &self.body_stream {
+            let mut stream = stream.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            let chunked = self.headers.contains_key("transfer-encoding");
+            write_stream(&mut **stream, writer, chunked)?;
+        }
+        Ok(())
+    }
+
+    /// Writes an interim 1xx response (e.g. `100 Continue`, `103 Early
+    /// Hints`) ahead of the final response. It carries no body and no
+    /// `Content-Length`, so the message isn't terminated.
+    pub fn write_informational<W: Write + ?Sized>(writer: &mut W, code: u16, headers: &[(&str, &str)]) -> io::Result<()> {
+        if !(100..200).contains(&code) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{code} is not an informational status code"),
+            ));
+        }
+        write!(writer, "HTTP/1.1 {} {}\r\n", code, get_status_phrase(code))?;
+        for (title, value) in headers {
+            write!(writer, "{}: {}\r\n", title.to_lowercase(), value)?;
+        }
+        write!(writer, "\r\n")
+    }
+
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = format!("{self}").as_bytes().to_vec();
+        if let Some(mut body) = self.try_get_body() {
+            bytes.append(&mut body);
+        }
+        bytes
+    }
+}
+
+/// How long a write may keep failing with `WouldBlock` before the error is
+/// passed on.
+const WOULD_BLOCK_PATIENCE: Duration = Duration::from_secs(30);
+
+/// Retries writes and flushes that fail with `WouldBlock`, as a
+/// non-blocking socket does while its send buffer is full, so `write_all`
+/// only sees progress or real errors. Gives up after `WOULD_BLOCK_PATIENCE`
+/// without progress.
+pub struct RetryWriter<W> {
+    inner: W,
+}
+
+impl<W: Write> RetryWriter<W> {
+    pub fn new(inner: W) -> RetryWriter<W> {
+        RetryWriter { inner }
+    }
+
+    fn retry<T>(&mut self, mut op: impl FnMut(&mut W) -> io::Result<T>) -> io::Result<T> {
+        let started = Instant::now();
+        let mut pause = Duration::from_micros(100);
+        loop {
+            match op(&mut self.inner) {
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock && started.elapsed() < WOULD_BLOCK_PATIENCE => {
+                    thread::sleep(pause);
+                    pause = (pause * 2).min(Duration::from_millis(10));
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+impl<W: Write> Write for RetryWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.retry(|inner| inner.write(buf))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.retry(|inner| inner.flush())
+    }
+}
+
+/// Copies `reader` to `writer`, in chunked framing ending with the zero
+/// chunk and an empty trailer section when `chunked`, else as-is. Each read
+/// is flushed as soon as it is written, so slow producers (e.g. event
+/// streams) reach the client live.
+fn write_stream<R: Read + ?Sized, W: Write>(reader: &mut R, writer: &mut W, chunked: bool) -> io::Result<()> {
+    let mut buf = vec![0u8; STREAM_CHUNK_LEN];
+    loop {
+        let n = match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        if chunked {
+            write!(writer, "{n:x}\r\n")?;
+            writer.write_all(&buf[..n])?;
+            writer.write_all(b"\r\n")?;
         } else {
-            return format!("{self}").as_bytes().to_vec();
+            writer.write_all(&buf[..n])?;
+        }
+        writer.flush()?;
+    }
+    if chunked {
+        writer.write_all(b"0\r\n\r\n")?;
+    }
+    Ok(())
+}
+
+/// Decodes a chunked body, discarding chunk extensions and trailers.
+fn read_chunked_body<R: BufRead>(reader: &mut R) -> Result<Vec<u8>, HttpParseError> {
+    let invalid = || HttpParseError::IoError(io::Error::new(io::ErrorKind::InvalidData, "invalid chunk size"));
+    let mut body = Vec::new();
+    loop {
+        let line = read_bounded_line(reader, MAX_HEADER_LINE_LEN)?;
+        if line.is_empty() {
+            return Err(HttpParseError::IoError(io::ErrorKind::UnexpectedEof.into()));
+        }
+        let size = line.trim_end_matches(['\r', '\n']).split(';').next().unwrap_or("").trim();
+        let size = usize::from_str_radix(size, 16).map_err(|_| invalid())?;
+        if size == 0 {
+            break;
+        }
+        if body.len() + size > MAX_BODY_LEN {
+            return Err(HttpParseError::BodyTooLarge);
+        }
+        let start = body.len();
+        body.resize(start + size, 0);
+        reader.read_exact(&mut body[start..]).map_err(HttpParseError::IoError)?;
+        let terminator = read_bounded_line(reader, 0)?;
+        if !terminator.trim_end_matches(['\r', '\n']).is_empty() {
+            return Err(invalid());
+        }
+    }
+    // Trailer section, ended by an empty line (or EOF).
+    loop {
+        let trailer = read_bounded_line(reader, MAX_HEADER_LINE_LEN)?;
+        if trailer.trim_end_matches(['\r', '\n']).is_empty() {
+            return Ok(body);
         }
     }
 }
@@ -59,15 +647,11 @@ impl HttpResponse {
 // Will not display body.
 impl fmt::Display for HttpResponse {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let _ = match write!(f, "{} {} {}\r\n", self.version, self.status_code, self.status_phrase) {
-            Ok(result) => result,
-            Err(e) => return Err(e),
-        };
-        for (title, value) in &self.headers {
-            let _ = match write!(f, "{}: {}\r\n", title, value) {
-                Ok(result) => result,
-                Err(e) => return Err(e),
-            };
+        write!(f, "{} {} {}\r\n", self.version, self.status_code, self.status_phrase)?;
+        for (title, values) in &self.headers {
+            for value in values {
+                write!(f, "{}: {}\r\n", title, value)?;
+            }
         }
         write!(f, "\r\n")
     }
@@ -77,6 +661,43 @@ impl fmt::Display for HttpResponse {
 mod tests {
     use super::*;
 
+    /// The status line, header lines (sorted, as storage order is
+    /// unspecified) and body of a serialized response.
+    fn normalized(bytes: &[u8]) -> (String, Vec<String>, Vec<u8>) {
+        let end = bytes.windows(4).position(|window| window == b"\r\n\r\n").unwrap();
+        let head = String::from_utf8(bytes[..end].to_vec()).unwrap();
+        let mut lines = head.split("\r\n").map(String::from);
+        let status = lines.next().unwrap();
+        let mut headers: Vec<String> = lines.collect();
+        headers.sort();
+        (status, headers, bytes[end + 4..].to_vec())
+    }
+
+    #[test]
+    fn from_parts_round_trips_a_parsed_response() {
+        let raw = b"HTTP/1.1 299 Custom Phrase\r\ncontent-type: text/plain\r\nset-cookie: a=1\r\nset-cookie: b=2\r\ncontent-length: 5\r\n\r\nhello";
+        let parsed = HttpResponse::build_from_reader(&mut &raw[..]).unwrap();
+        let parts = parsed.into_parts().unwrap();
+        assert_eq!(parts.phrase, "Custom Phrase");
+        let rebuilt = HttpResponse::from_parts(parts.version, parts.code, parts.phrase, parts.headers, parts.body);
+
+        assert_eq!(normalized(&rebuilt.as_bytes()), normalized(raw));
+        assert_eq!(rebuilt.get_header_values("Set-Cookie".to_string()), vec!["a=1", "b=2"]);
+    }
+
+    #[test]
+    fn into_parts_reads_back_spilled_bodies_and_refuses_streams() {
+        let mut spilled = HttpResponse::build("HTTP/1.1".to_string(), 200);
+        spilled.set_spill_threshold(4);
+        spilled.extend_body(b"spilled to disk");
+        assert!(spilled.is_spilled());
+        assert_eq!(spilled.into_parts().unwrap().body, Some(b"spilled to disk".to_vec()));
+
+        let mut streamed = HttpResponse::build("HTTP/1.1".to_string(), 200);
+        streamed.set_body_stream(&b"streamed"[..]);
+        assert_eq!(streamed.into_parts().map_err(|e| e.kind()), Err(io::ErrorKind::InvalidInput));
+    }
+
     #[test]
     fn build_creates_response_with_correct_fields() {
         let resp = HttpResponse::build("HTTP/1.1".to_string(), 200);
@@ -178,4 +799,354 @@ mod tests {
         resp.add_body(b"hello world".to_vec());
         assert_eq!(resp.try_get_header("content-length".to_string()), Some("11".to_string()));
     }
+
+    #[test]
+    fn extend_body_concatenates_and_tracks_length() {
+        let mut resp = HttpResponse::build("HTTP/1.1".to_string(), 200);
+        resp.extend_body(b"hello ").extend_body(b"world");
+        assert_eq!(resp.try_get_body(), Some(b"hello world".to_vec()));
+        assert_eq!(resp.try_get_header("content-length".to_string()), Some("11".to_string()));
+        assert!(format!("{resp}").contains("content-length: 11\r\n"));
+    }
+
+    #[test]
+    fn extend_body_spills_past_threshold_and_writes_from_disk() {
+        let chunk: Vec<u8> = (0..1000).map(|i| (i % 251) as u8).collect();
+        let mut resp = HttpResponse::build("HTTP/1.1".to_string(), 200);
+        resp.set_spill_threshold(2500);
+        let mut expected = Vec::new();
+        for _ in 0..5 {
+            resp.extend_body(&chunk);
+            expected.extend_from_slice(&chunk);
+        }
+        assert!(resp.is_spilled());
+        assert_eq!(resp.body_len(), 5000);
+
+        let mut written = Vec::new();
+        resp.write_to(&mut written).unwrap();
+        let path = resp.spilled.as_ref().unwrap().path.clone();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+        drop(resp);
+        assert!(!path.exists());
+
+        let decoded = HttpResponse::build_from_reader(&mut &written[..]).unwrap();
+        assert_eq!(decoded.try_get_header("content-length".to_string()), Some("5000".to_string()));
+        assert_eq!(decoded.try_get_body(), Some(expected));
+    }
+
+    #[test]
+    fn lowering_the_spill_threshold_spills_a_larger_body() {
+        let mut resp = HttpResponse::build("HTTP/1.1".to_string(), 200);
+        resp.extend_body(b"already in memory");
+        assert_eq!(resp.spill_threshold(), None);
+        assert!(!resp.is_spilled());
+
+        resp.set_spill_threshold(8);
+        assert!(resp.is_spilled());
+        assert_eq!(resp.into_parts().unwrap().body, Some(b"already in memory".to_vec()));
+
+        let mut added = HttpResponse::build("HTTP/1.1".to_string(), 200);
+        added.add_body(b"set in one go".to_vec());
+        added.set_spill_threshold(8);
+        assert!(!added.is_spilled());
+    }
+
+    #[test]
+    fn set_content_type_normalizes_value() {
+        let mut resp = HttpResponse::build("HTTP/1.1".to_string(), 200);
+        resp.set_content_type(" Application/JSON ");
+        assert_eq!(resp.try_get_header("content-type".to_string()), Some("application/json".to_string()));
+        resp.set_content_type("Multipart/Form-Data; boundary=AbC123xYz");
+        assert_eq!(
+            resp.try_get_header("content-type".to_string()),
+            Some("multipart/form-data; boundary=AbC123xYz".to_string())
+        );
+        resp.set_content_type_charset("Text/HTML", "UTF-8");
+        assert_eq!(resp.get_header_values("Content-Type".to_string()), vec!["text/html; charset=utf-8"]);
+    }
+
+    #[test]
+    fn set_attachment_quotes_ascii_filename() {
+        let mut resp = HttpResponse::build("HTTP/1.1".to_string(), 200);
+        resp.set_attachment(r#"report "final".pdf"#);
+        assert_eq!(
+            resp.try_get_header("content-disposition".to_string()),
+            Some(r#"attachment; filename="report \"final\".pdf""#.to_string())
+        );
+    }
+
+    #[test]
+    fn set_attachment_adds_encoded_filename_for_utf8() {
+        let mut resp = HttpResponse::build("HTTP/1.1".to_string(), 200);
+        resp.set_attachment("résumé 2024.pdf");
+        assert_eq!(
+            resp.try_get_header("content-disposition".to_string()),
+            Some("attachment; filename=\"r_sum_ 2024.pdf\"; filename*=UTF-8''r%C3%A9sum%C3%A9%202024.pdf".to_string())
+        );
+    }
+
+    #[test]
+    fn add_text_body_sets_type_and_length() {
+        let mut resp = HttpResponse::build("HTTP/1.1".to_string(), 200);
+        resp.add_text_body("héllo");
+        assert_eq!(resp.try_get_header("content-type".to_string()), Some("text/plain; charset=utf-8".to_string()));
+        assert_eq!(resp.try_get_header("content-length".to_string()), Some("6".to_string()));
+        assert_eq!(resp.try_get_body(), Some("héllo".as_bytes().to_vec()));
+    }
+
+    #[test]
+    fn append_header_emits_each_value_on_its_own_line() {
+        let mut resp = HttpResponse::build("HTTP/1.1".to_string(), 200);
+        resp.append_header("Set-Cookie".to_string(), "a=1".to_string())
+            .append_header("Set-Cookie".to_string(), "b=2".to_string());
+        let output = format!("{resp}");
+        assert!(output.contains("set-cookie: a=1\r\n"));
+        assert!(output.contains("set-cookie: b=2\r\n"));
+        assert_eq!(
+            resp.get_header_values("Set-Cookie".to_string()),
+            vec!["a=1".to_string(), "b=2".to_string()]
+        );
+    }
+
+    #[test]
+    fn add_header_overwrites_appended_values() {
+        let mut resp = HttpResponse::build("HTTP/1.1".to_string(), 200);
+        resp.append_header("Link".to_string(), "</a.css>".to_string())
+            .append_header("Link".to_string(), "</b.css>".to_string())
+            .add_header("Link".to_string(), "</c.css>".to_string());
+        assert_eq!(resp.get_header_values("link".to_string()), vec!["</c.css>".to_string()]);
+        assert_eq!(resp.try_get_header("link".to_string()), Some("</c.css>".to_string()));
+    }
+
+    #[test]
+    fn from_file_serves_existing_file() {
+        let path = std::env::temp_dir()
+            .join(format!("rcomm_from_file_{}.css", std::process::id()));
+        fs::write(&path, "body { color: red; }").unwrap();
+
+        let resp = HttpResponse::from_file("HTTP/1.1".to_string(), &path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(format!("{resp}").starts_with("HTTP/1.1 200 OK\r\n"));
+        assert_eq!(resp.try_get_header("content-type".to_string()), Some("text/css; charset=utf-8".to_string()));
+        assert_eq!(resp.try_get_header("content-length".to_string()), Some("20".to_string()));
+        assert!(resp.try_get_header("last-modified".to_string()).unwrap().ends_with(" GMT"));
+        assert_eq!(resp.try_get_body(), Some(b"body { color: red; }".to_vec()));
+    }
+
+    #[test]
+    fn from_file_returns_error_for_missing_file() {
+        let path = std::env::temp_dir()
+            .join(format!("rcomm_from_file_missing_{}.html", std::process::id()));
+        let result = HttpResponse::from_file("HTTP/1.1".to_string(), &path);
+        assert_eq!(result.err().map(|e| e.kind()), Some(io::ErrorKind::NotFound));
+    }
+
+    /// Takes at most three bytes per call and reports `WouldBlock` on every
+    /// other call, like a congested non-blocking socket.
+    struct TrickleWriter {
+        written: Vec<u8>,
+        calls: usize,
+    }
+
+    impl Write for TrickleWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.calls += 1;
+            if self.calls.is_multiple_of(2) {
+                return Err(io::ErrorKind::WouldBlock.into());
+            }
+            let n = buf.len().min(3);
+            self.written.extend_from_slice(&buf[..n]);
+            Ok(n)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn retry_writer_completes_through_short_writes_and_would_block() {
+        let mut resp = HttpResponse::build("HTTP/1.1".to_string(), 200);
+        resp.add_header("Content-Type".to_string(), "text/plain".to_string());
+        resp.add_body(b"a body longer than a few bytes".to_vec());
+
+        // Unwrapped, write_to leaves WouldBlock to the caller.
+        let mut writer = TrickleWriter { written: Vec::new(), calls: 0 };
+        let plain = resp.write_to(&mut writer);
+        assert_eq!(plain.map_err(|e| e.kind()), Err(io::ErrorKind::WouldBlock));
+
+        let mut writer = TrickleWriter { written: Vec::new(), calls: 0 };
+        resp.write_to(&mut RetryWriter::new(&mut writer)).unwrap();
+        assert_eq!(writer.written, resp.as_bytes());
+
+        let mut streamed = HttpResponse::build("HTTP/1.1".to_string(), 200);
+        streamed.set_body_stream(&b"streamed"[..]);
+        let mut writer = TrickleWriter { written: Vec::new(), calls: 0 };
+        streamed.write_to(&mut RetryWriter::new(&mut writer)).unwrap();
+        assert!(writer.written.ends_with(b"8\r\nstreamed\r\n0\r\n\r\n"));
+    }
+
+    #[test]
+    fn write_to_matches_as_bytes() {
+        let mut resp = HttpResponse::build("HTTP/1.1".to_string(), 200);
+        resp.add_header("Server".to_string(), "rcomm".to_string());
+        resp.add_body(b"payload".to_vec());
+        let mut written = Vec::new();
+        resp.write_to(&mut written).unwrap();
+        assert_eq!(written, resp.as_bytes());
+    }
+
+    #[test]
+    fn accessors_read_back_status_line() {
+        let resp = HttpResponse::build("HTTP/1.0".to_string(), 404);
+        assert_eq!(resp.status_code(), 404);
+        assert_eq!(resp.status_phrase(), "Not Found");
+        assert_eq!(resp.version(), "HTTP/1.0");
+    }
+
+    #[test]
+    fn write_informational_emits_early_hints() {
+        let mut written = Vec::new();
+        HttpResponse::write_informational(&mut written, 103, &[("Link", "</style.css>; rel=preload; as=style")]).unwrap();
+        let text = String::from_utf8(written).unwrap();
+        assert_eq!(text, "HTTP/1.1 103 Early Hints\r\nlink: </style.css>; rel=preload; as=style\r\n\r\n");
+        assert!(!text.contains("content-length"));
+    }
+
+    #[test]
+    fn write_informational_rejects_final_status_codes() {
+        let mut written = Vec::new();
+        let result = HttpResponse::write_informational(&mut written, 200, &[]);
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert!(written.is_empty());
+    }
+
+    #[test]
+    fn build_from_reader_parses_content_length_response() {
+        let raw = b"HTTP/1.1 404 Not Here\r\nContent-Type: text/plain\r\nSet-Cookie: a=1\r\nSet-Cookie: b=2\r\nContent-Length: 4\r\n\r\ngoneEXTRA";
+        let mut reader = io::BufReader::new(&raw[..]);
+        let resp = HttpResponse::build_from_reader(&mut reader).unwrap();
+
+        assert_eq!(resp.status_code(), 404);
+        assert_eq!(resp.status_phrase(), "Not Here");
+        assert_eq!(resp.try_get_header("content-type".to_string()), Some("text/plain".to_string()));
+        assert_eq!(resp.get_header_values("set-cookie".to_string()).len(), 2);
+        assert_eq!(resp.try_get_body(), Some(b"gone".to_vec()));
+    }
+
+    #[test]
+    fn build_from_reader_reads_unframed_body_to_eof() {
+        let raw = b"HTTP/1.0 200 OK\r\n\r\nuntil close";
+        let resp = HttpResponse::build_from_reader(&mut &raw[..]).unwrap();
+        assert_eq!(resp.try_get_body(), Some(b"until close".to_vec()));
+    }
+
+    #[test]
+    fn build_from_reader_rejects_malformed_status_line() {
+        let raw = b"HTTP/1.1 OK\r\n\r\n";
+        let result = HttpResponse::build_from_reader(&mut &raw[..]);
+        assert!(matches!(result, Err(HttpParseError::MalformedStatusLine)));
+    }
+
+    #[test]
+    fn build_from_stream_parses_canned_response() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: 14\r\n\r\n<h1>Hello</h1>")
+                .unwrap();
+        });
+
+        let stream = TcpStream::connect(addr).unwrap();
+        let resp = HttpResponse::build_from_stream(&stream).unwrap();
+        server.join().unwrap();
+
+        assert_eq!(resp.status_code(), 200);
+        assert_eq!(resp.version(), "HTTP/1.1");
+        assert_eq!(resp.try_get_header("content-type".to_string()), Some("text/html".to_string()));
+        assert_eq!(resp.try_get_body(), Some(b"<h1>Hello</h1>".to_vec()));
+    }
+
+    #[test]
+    fn build_from_reader_decodes_chunked_body() {
+        let raw = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5;ext=1\r\nHello\r\n7\r\n, world\r\n0\r\nX-Trailer: yes\r\n\r\nNEXT";
+        let mut reader = &raw[..];
+        let resp = HttpResponse::build_from_reader(&mut reader).unwrap();
+
+        assert_eq!(resp.try_get_body(), Some(b"Hello, world".to_vec()));
+        assert_eq!(resp.try_get_header("transfer-encoding".to_string()), None);
+        assert_eq!(resp.try_get_header("content-length".to_string()), Some("12".to_string()));
+        assert_eq!(reader, b"NEXT");
+    }
+
+    #[test]
+    fn build_from_reader_rejects_bad_chunk_size() {
+        let raw = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\n";
+        assert!(HttpResponse::build_from_reader(&mut &raw[..]).is_err());
+    }
+
+    #[test]
+    fn from_parse_error_maps_status_and_body() {
+        let resp = HttpResponse::from_parse_error("HTTP/1.1", &HttpParseError::HeaderTooLong);
+        assert_eq!(resp.status_code(), 431);
+        assert_eq!(resp.try_get_header("connection".to_string()), Some("close".to_string()));
+        assert_eq!(
+            resp.try_get_body(),
+            Some(b"431 Request Header Fields Too Large: Header line exceeds maximum length".to_vec())
+        );
+
+        let resp = HttpResponse::from_parse_error("HTTP/1.1", &HttpParseError::BodyTooLarge);
+        assert_eq!(resp.status_code(), 413);
+    }
+
+    #[test]
+    fn from_parse_error_hides_io_details() {
+        let err = HttpParseError::IoError(io::Error::new(io::ErrorKind::TimedOut, "secret internals"));
+        let resp = HttpResponse::from_parse_error("HTTP/1.1", &err);
+        assert_eq!(resp.status_code(), 408);
+        assert_eq!(resp.try_get_body(), Some(b"408 Request Timeout".to_vec()));
+
+        let err = HttpParseError::IoError(io::ErrorKind::InvalidData.into());
+        assert_eq!(HttpResponse::from_parse_error("HTTP/1.1", &err).status_code(), 400);
+    }
+
+    #[test]
+    fn write_to_streams_unknown_length_body_chunked() {
+        let source = io::Cursor::new(b"hello, ".to_vec()).chain(io::repeat(b'x').take(20_000));
+        let mut expected = b"hello, ".to_vec();
+        expected.extend(std::iter::repeat_n(b'x', 20_000));
+
+        let mut response = HttpResponse::build("HTTP/1.1".to_string(), 200);
+        response.add_body(b"replaced".to_vec());
+        response.set_body_stream(source);
+        assert_eq!(response.try_get_header("content-length".to_string()), None);
+
+        let mut wire = Vec::new();
+        response.write_to(&mut wire).unwrap();
+        assert!(wire.ends_with(b"\r\n0\r\n\r\n"));
+
+        let decoded = HttpResponse::build_from_reader(&mut BufReader::new(&wire[..])).unwrap();
+        assert_eq!(decoded.try_get_body(), Some(expected));
+    }
+
+    #[test]
+    fn close_delimited_stream_is_written_unframed() {
+        let mut response = HttpResponse::build("HTTP/1.0".to_string(), 200);
+        response.set_body_stream(&b"streamed"[..]);
+        response.close_delimit();
+        assert!(response.is_streamed());
+        assert_eq!(response.try_get_header("transfer-encoding".to_string()), None);
+        assert_eq!(response.try_get_header("connection".to_string()), Some("close".to_string()));
+
+        let mut wire = Vec::new();
+        response.write_to(&mut wire).unwrap();
+        assert!(wire.ends_with(b"\r\n\r\nstreamed"));
+    }
 }
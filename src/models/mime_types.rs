@@ -0,0 +1,73 @@
+pub fn get_mime_type(extension: &str) -> &'static str {
+    match extension.to_lowercase().as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" | "mjs" => "text/javascript; charset=utf-8",
+        "json" => "application/json",
+        "txt" => "text/plain; charset=utf-8",
+        "xml" => "application/xml",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "ico" => "image/x-icon",
+        "webp" => "image/webp",
+        "avif" => "image/avif",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "ttf" => "font/ttf",
+        "otf" => "font/otf",
+        "mp4" => "video/mp4",
+        "webm" => "video/webm",
+        "wasm" => "application/wasm",
+        "map" => "application/json",
+        "pdf" => "application/pdf",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Default `Cache-Control` for a response of `content_type`: assets are
+/// cached for a year as immutable (they're expected to be fingerprinted),
+/// while HTML is revalidated on every use.
+pub fn default_cache_control(content_type: &str) -> Option<&'static str> {
+    let essence = content_type.split(';').next().unwrap_or("").trim().to_lowercase();
+    match essence.as_str() {
+        "text/html" => Some("no-cache"),
+        "text/css" | "text/javascript" => Some("public, max-age=31536000, immutable"),
+        _ if essence.starts_with("image/") => Some("public, max-age=31536000, immutable"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_known_extensions() {
+        assert_eq!(get_mime_type("html"), "text/html; charset=utf-8");
+        assert_eq!(get_mime_type("css"), "text/css; charset=utf-8");
+        assert_eq!(get_mime_type("js"), "text/javascript; charset=utf-8");
+        assert_eq!(get_mime_type("webp"), "image/webp");
+        assert_eq!(get_mime_type("woff2"), "font/woff2");
+        assert_eq!(get_mime_type("wasm"), "application/wasm");
+    }
+
+    #[test]
+    fn extension_match_is_case_insensitive() {
+        assert_eq!(get_mime_type("PNG"), "image/png");
+    }
+
+    #[test]
+    fn unknown_extension_is_octet_stream() {
+        assert_eq!(get_mime_type("xyz"), "application/octet-stream");
+    }
+
+    #[test]
+    fn default_cache_control_by_category() {
+        assert_eq!(default_cache_control(get_mime_type("css")), Some("public, max-age=31536000, immutable"));
+        assert_eq!(default_cache_control(get_mime_type("png")), Some("public, max-age=31536000, immutable"));
+        assert_eq!(default_cache_control(get_mime_type("html")), Some("no-cache"));
+        assert_eq!(default_cache_control(get_mime_type("json")), None);
+    }
+}
@@ -0,0 +1,292 @@
+//! Relaying requests to an upstream server. `Proxy` forwards a request
+//! and turns upstream failures into gateway errors; `strip_hop_by_hop`
+//! cleans up what comes back.
+
+use std::{
+    fs,
+    io::{self, BufReader, BufWriter, Write},
+    net::{TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+use crate::models::{
+    http_methods::HttpMethods,
+    http_request::{HttpParseError, HttpRequest},
+    http_response::HttpResponse,
+};
+
+/// How long an upstream may go without connecting or sending data.
+pub const DEFAULT_UPSTREAM_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Headers that describe a single connection rather than the message, so
+/// they must not be relayed to the next hop (RFC 9110 §7.6.1).
+pub const HOP_BY_HOP_HEADERS: [&str; 8] = [
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "te",
+    "trailers",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// Removes hop-by-hop headers from an upstream response before it is
+/// relayed: the standard set plus any named in its `Connection` header.
+pub fn strip_hop_by_hop(response: &mut HttpResponse) {
+    let listed: Vec<String> = response
+        .get_header_values("Connection".to_string())
+        .iter()
+        .flat_map(|value| value.split(','))
+        .map(|token| token.trim().to_string())
+        .filter(|token| !token.is_empty())
+        .collect();
+
+    for title in listed.into_iter().chain(HOP_BY_HOP_HEADERS.iter().map(|title| title.to_string())) {
+        response.remove_header(title);
+    }
+}
+
+/// Forwards requests to one upstream `host:port`, opening a fresh
+/// connection for each.
+pub struct Proxy {
+    upstream: String,
+    timeout: Duration,
+}
+
+impl Proxy {
+    pub fn new(upstream: impl Into<String>) -> Proxy {
+        Proxy { upstream: upstream.into(), timeout: DEFAULT_UPSTREAM_TIMEOUT }
+    }
+
+    /// Sets how long connecting, sending, or waiting for each read from the
+    /// upstream may take before the client gets a `504`.
+    pub fn set_timeout(&mut self, timeout: Duration) -> &mut Proxy {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sends `request` upstream and relays the response without its
+    /// hop-by-hop headers. An upstream that times out gets the client a
+    /// `504 Gateway Timeout`; one that can't be reached or answers with
+    /// something unparseable, a `502 Bad Gateway`. The upstream connection
+    /// is closed either way.
+    pub fn forward(&self, request: &HttpRequest) -> HttpResponse {
+        match self.exchange(request) {
+            Ok(mut response) => {
+                strip_hop_by_hop(&mut response);
+                response
+            }
+            Err(e) if is_timeout(&e) => gateway_error(504),
+            Err(_) => gateway_error(502),
+        }
+    }
+
+    fn exchange(&self, request: &HttpRequest) -> Result<HttpResponse, HttpParseError> {
+        let address = self
+            .upstream
+            .to_socket_addrs()
+            .map_err(HttpParseError::IoError)?
+            .next()
+            .ok_or_else(|| HttpParseError::IoError(io::ErrorKind::NotFound.into()))?;
+        let stream = TcpStream::connect_timeout(&address, self.timeout).map_err(HttpParseError::IoError)?;
+        stream.set_read_timeout(Some(self.timeout)).map_err(HttpParseError::IoError)?;
+        stream.set_write_timeout(Some(self.timeout)).map_err(HttpParseError::IoError)?;
+
+        let mut writer = BufWriter::new(&stream);
+        write_upstream_request(request, &mut writer)
+            .and_then(|()| writer.flush())
+            .map_err(HttpParseError::IoError)?;
+        drop(writer);
+
+        let mut reader = BufReader::new(&stream);
+        if request.method == HttpMethods::HEAD {
+            HttpResponse::build_head_from_reader(&mut reader)
+        } else {
+            HttpResponse::build_from_reader(&mut reader)
+        }
+    }
+}
+
+/// Writes `request` for the upstream: its own hop-by-hop headers (the
+/// standard set, `Proxy-*` and anything its `Connection` names) dropped,
+/// `Connection: close` added, and the body, spooled or in memory, framed
+/// by a fresh `Content-Length`.
+fn write_upstream_request<W: Write>(request: &HttpRequest, writer: &mut W) -> io::Result<()> {
+    let listed: Vec<String> = request
+        .header_list("Connection".to_string())
+        .iter()
+        .map(|token| token.to_ascii_lowercase())
+        .collect();
+    let body_len = match request.body_file() {
+        Some(path) => Some(fs::metadata(path)?.len()),
+        None => request.try_get_body().map(|body| body.len() as u64),
+    };
+
+    write!(writer, "{} {} {}\r\n", request.method, request.target, request.version)?;
+    for (title, value) in request.headers() {
+        let hop_by_hop = HOP_BY_HOP_HEADERS.contains(&title)
+            || title.starts_with("proxy-")
+            || listed.iter().any(|token| token == title);
+        if !hop_by_hop && title != "content-length" {
+            write!(writer, "{title}: {value}\r\n")?;
+        }
+    }
+    if let Some(len) = body_len {
+        write!(writer, "content-length: {len}\r\n")?;
+    }
+    write!(writer, "connection: close\r\n\r\n")?;
+
+    match request.body_file() {
+        Some(path) => io::copy(&mut fs::File::open(path)?, writer).map(|_| ()),
+        None => writer.write_all(&request.try_get_body().unwrap_or_default()),
+    }
+}
+
+fn is_timeout(error: &HttpParseError) -> bool {
+    matches!(error, HttpParseError::IoError(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut))
+}
+
+fn gateway_error(code: u16) -> HttpResponse {
+    let mut response = HttpResponse::build(String::from("HTTP/1.1"), code);
+    let body = format!("{code} {}", response.status_phrase());
+    response.add_text_body(body);
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{io::Read, net::TcpListener, thread};
+
+    fn get(path: &str) -> HttpRequest {
+        HttpRequest::build(HttpMethods::GET, path.to_string(), "HTTP/1.1".to_string())
+            .with_header("Host".to_string(), "site.test".to_string())
+    }
+
+    /// Accepts one connection, reads the request head and hands the stream
+    /// to `reply`.
+    fn upstream<F>(reply: F) -> (String, thread::JoinHandle<()>)
+    where
+        F: FnOnce(TcpStream) + Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let handle = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            HttpRequest::build_from_stream(&stream).unwrap();
+            reply(stream);
+        });
+        (address, handle)
+    }
+
+    #[test]
+    fn forward_relays_upstream_response() {
+        let (address, upstream) = upstream(|mut stream| {
+            stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nKeep-Alive: timeout=5\r\n\r\nhi").unwrap();
+        });
+        let response = Proxy::new(address).forward(&get("/"));
+        upstream.join().unwrap();
+
+        assert_eq!(response.status_code(), 200);
+        assert_eq!(response.try_get_body(), Some(b"hi".to_vec()));
+        assert_eq!(response.try_get_header("keep-alive".to_string()), None);
+    }
+
+    #[test]
+    fn forward_streams_spooled_body_without_hop_by_hop_headers() {
+        use crate::models::http_request::ParseOptions;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let upstream = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let request = HttpRequest::build_from_stream(&stream).unwrap();
+            stream.write_all(b"HTTP/1.1 204 No Content\r\n\r\n").unwrap();
+            request
+        });
+
+        let raw = b"POST /upload HTTP/1.1\r\nHost: x\r\nContent-Length: 5\r\nConnection: keep-alive, X-Hop\r\nX-Hop: 1\r\nKeep-Alive: timeout=5\r\nProxy-Authorization: secret\r\nX-Kept: yes\r\n\r\nhello";
+        let options = ParseOptions { upload_routes: vec![String::from("/upload")], ..ParseOptions::default() };
+        let request = HttpRequest::build_from_reader_with(&mut &raw[..], &options).unwrap();
+        assert!(request.body_file().is_some());
+        let response = Proxy::new(address).forward(&request);
+        let received = upstream.join().unwrap();
+
+        assert_eq!(response.status_code(), 204);
+        assert_eq!(received.try_get_body(), Some(b"hello".to_vec()));
+        assert_eq!(received.try_get_header("connection".to_string()), Some("close".to_string()));
+        assert_eq!(received.try_get_header("x-kept".to_string()), Some("yes".to_string()));
+        for stripped in ["x-hop", "keep-alive", "proxy-authorization"] {
+            assert_eq!(received.try_get_header(stripped.to_string()), None, "{stripped} forwarded");
+        }
+    }
+
+    #[test]
+    fn slow_upstream_gets_504_and_its_connection_closed() {
+        let (address, upstream) = upstream(|mut stream| {
+            // Say nothing until the proxy gives up, then see it hang up.
+            let mut rest = Vec::new();
+            let _ = stream.read_to_end(&mut rest);
+        });
+        let mut proxy = Proxy::new(address);
+        proxy.set_timeout(Duration::from_millis(200));
+        let response = proxy.forward(&get("/slow"));
+        upstream.join().unwrap();
+
+        assert_eq!(response.status_code(), 504);
+    }
+
+    #[test]
+    fn broken_or_unreachable_upstream_gets_502() {
+        let (address, upstream) = upstream(|mut stream| {
+            stream.write_all(b"NOT HTTP AT ALL\r\n\r\n").unwrap();
+        });
+        let broken = Proxy::new(address).forward(&get("/"));
+        upstream.join().unwrap();
+        assert_eq!(broken.status_code(), 502);
+
+        let closed = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let unreachable = Proxy::new(closed.to_string()).forward(&get("/"));
+        assert_eq!(unreachable.status_code(), 502);
+    }
+
+    fn upstream_response() -> HttpResponse {
+        let mut response = HttpResponse::build(String::from("HTTP/1.1"), 200);
+        response.add_header("Content-Type".to_string(), "text/html".to_string());
+        response.add_header("Cache-Control".to_string(), "no-cache".to_string());
+        response.add_header("Keep-Alive".to_string(), "timeout=5".to_string());
+        response.add_header("Transfer-Encoding".to_string(), "chunked".to_string());
+        response.add_header("Upgrade".to_string(), "h2c".to_string());
+        response.add_body(b"hello".to_vec());
+        response
+    }
+
+    #[test]
+    fn strips_default_hop_by_hop_headers() {
+        let mut response = upstream_response();
+        response.add_header("Connection".to_string(), "keep-alive".to_string());
+        strip_hop_by_hop(&mut response);
+
+        for title in HOP_BY_HOP_HEADERS {
+            assert_eq!(response.try_get_header(title.to_string()), None, "{title} survived");
+        }
+        assert_eq!(response.try_get_header("content-type".to_string()), Some("text/html".to_string()));
+        assert_eq!(response.try_get_header("cache-control".to_string()), Some("no-cache".to_string()));
+        assert_eq!(response.try_get_header("content-length".to_string()), Some("5".to_string()));
+    }
+
+    #[test]
+    fn strips_headers_listed_in_connection() {
+        let mut response = upstream_response();
+        response.add_header("X-Upstream-Node".to_string(), "a1".to_string());
+        response.add_header("X-Trace".to_string(), "abc".to_string());
+        response.append_header("Connection".to_string(), "X-Upstream-Node, close".to_string());
+        response.append_header("Connection".to_string(), "x-trace".to_string());
+        strip_hop_by_hop(&mut response);
+
+        assert_eq!(response.try_get_header("x-upstream-node".to_string()), None);
+        assert_eq!(response.try_get_header("x-trace".to_string()), None);
+        assert_eq!(response.try_get_header("connection".to_string()), None);
+        assert_eq!(response.try_get_header("content-type".to_string()), Some("text/html".to_string()));
+    }
+}
@@ -0,0 +1,1422 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    fs,
+    io,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+use crate::compression::{Encoding, select_encoding};
+use crate::logger::{LogLevel, Logger, StderrLogger};
+use crate::middleware::Middleware;
+use crate::models::{
+    http_conditional::{Validators, preconditions_hold},
+    http_methods::HttpMethods,
+    http_request::HttpRequest,
+    http_response::HttpResponse,
+    http_status_codes::get_status_phrase,
+    mime_types::get_mime_type,
+};
+
+pub type Handler = Box<dyn Fn(&HttpRequest) -> HttpResponse + Send + Sync>;
+
+pub const DEFAULT_NOT_FOUND_PAGE: &str = "pages/not_found.html";
+
+/// Served when the configured 404 page can't be read.
+const BUILTIN_NOT_FOUND_HTML: &str = "<!doctype html>\n<html lang=\"en\">\n  <head><meta charset=\"utf-8\" /><title>404 Not Found</title></head>\n  <body><h1>404 Not Found</h1></body>\n</html>\n";
+
+/// How directory-style routes (those served from an `index.html` or
+/// `page.html`) treat a trailing slash.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TrailingSlash {
+    /// Serve `/howdy` and `/howdy/` alike.
+    Ignore,
+    /// 301-redirect `/howdy/` to `/howdy`.
+    Strip,
+    /// 301-redirect `/howdy` to `/howdy/`.
+    Append,
+}
+
+pub fn trailing_slash_from_string(policy: &str) -> Option<TrailingSlash> {
+    match policy.to_lowercase().as_str() {
+        "ignore" => Some(TrailingSlash::Ignore),
+        "strip" => Some(TrailingSlash::Strip),
+        "append" => Some(TrailingSlash::Append),
+        _ => None,
+    }
+}
+
+/// The route table: cleaned request paths mapped to the files serving them,
+/// plus any extra response headers read from `<file>.headers` sidecars.
+/// Routes built with `from_virtual_files` are served from memory instead.
+#[derive(Debug, Clone, Default)]
+pub struct Routes {
+    map: HashMap<String, PathBuf>,
+    headers: HashMap<String, Vec<(String, String)>>,
+    contents: HashMap<String, Vec<u8>>,
+}
+
+impl Routes {
+    /// Adds `route`, picking up headers from a `<path>.headers` sidecar
+    /// (one `Name: Value` per line) when one exists.
+    fn insert_file(&mut self, route: String, path: PathBuf) {
+        let mut sidecar = path.as_os_str().to_owned();
+        sidecar.push(".headers");
+        if let Ok(contents) = fs::read_to_string(PathBuf::from(sidecar)) {
+            self.headers.insert(route.clone(), parse_header_lines(&contents));
+        }
+        self.map.insert(route, path);
+    }
+
+    /// Builds routes from in-memory files, named by their path relative to
+    /// the document root (e.g. `foo/page.html`), following the same
+    /// conventions as `build_routes`. Nothing touches the filesystem.
+    pub fn from_virtual_files(files: Vec<(String, Vec<u8>)>) -> Routes {
+        let mut routes = Routes::default();
+        let names: HashSet<String> = files.iter().map(|(file, _)| file.trim_start_matches('/').to_string()).collect();
+        for (file, contents) in files {
+            let file = file.trim_start_matches('/');
+            if file.strip_suffix(".gz").is_some_and(|original| names.contains(original)) {
+                continue;
+            }
+            let (dir, name) = file.rsplit_once('/').unwrap_or(("", file));
+            if dir.split('/').any(|segment| segment.starts_with('.')) {
+                continue;
+            }
+            let dir_route = if dir.is_empty() { String::new() } else { format!("/{dir}") };
+            if let Some(route) = route_for_file(&dir_route, name) {
+                routes.map.insert(route.clone(), PathBuf::from(file));
+                routes.contents.insert(route, contents);
+            }
+        }
+        routes
+    }
+
+    /// Merges `other` in, its entries replacing ours route by route along
+    /// with their sidecar headers.
+    fn extend(&mut self, other: Routes) {
+        for route in other.map.keys() {
+            self.headers.remove(route);
+            self.contents.remove(route);
+        }
+        self.map.extend(other.map);
+        self.headers.extend(other.headers);
+        self.contents.extend(other.contents);
+    }
+
+    /// The in-memory body of a virtual route.
+    fn contents(&self, route: &str) -> Option<&[u8]> {
+        self.contents.get(route).map(Vec::as_slice)
+    }
+
+    /// Extra headers configured for `route`'s 200 responses.
+    pub fn headers_for(&self, route: &str) -> &[(String, String)] {
+        self.headers.get(route).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn get(&self, route: &str) -> Option<&PathBuf> {
+        self.map.get(route)
+    }
+
+    /// Whether `path` resolves to a route once cleaned the way requests are.
+    pub fn contains(&self, path: &str) -> bool {
+        self.map.contains_key(&clean_route(path))
+    }
+
+    /// Every route and the file behind it, sorted by route.
+    pub fn list(&self) -> Vec<(&str, &Path)> {
+        let mut entries: Vec<_> = self
+            .map
+            .iter()
+            .map(|(route, path)| (route.as_str(), path.as_path()))
+            .collect();
+        entries.sort();
+        entries
+    }
+}
+
+impl From<HashMap<String, PathBuf>> for Routes {
+    fn from(map: HashMap<String, PathBuf>) -> Routes {
+        Routes { map, headers: HashMap::new(), contents: HashMap::new() }
+    }
+}
+
+fn parse_header_lines(contents: &str) -> Vec<(String, String)> {
+    contents
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .map(|(title, value)| (title.trim().to_string(), value.trim().to_string()))
+        .filter(|(title, _)| !title.is_empty())
+        .collect()
+}
+
+/// Serves an in-memory route, typed by the extension of its virtual path.
+fn virtual_file_response(path: &Path, contents: &[u8]) -> HttpResponse {
+    let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
+    let mut response = HttpResponse::build(String::from("HTTP/1.1"), 200);
+    response.add_header("Content-Type".to_string(), get_mime_type(extension).to_string());
+    response.add_header("Accept-Ranges".to_string(), "bytes".to_string());
+    response.add_body(contents.to_vec());
+    response
+}
+
+/// Sets each header on `response`; repeated names are appended rather than
+/// replacing the earlier value.
+fn apply_headers(response: &mut HttpResponse, headers: &[(String, String)]) {
+    for (i, (title, value)) in headers.iter().enumerate() {
+        if headers[..i].iter().any(|(seen, _)| seen.eq_ignore_ascii_case(title)) {
+            response.append_header(title.clone(), value.clone());
+        } else {
+            response.add_header(title.clone(), value.clone());
+        }
+    }
+}
+
+pub struct Router {
+    /// Swapped whole by `replace_routes`. Each request clones the `Arc` and
+    /// routes against that snapshot, so the lock is only held for the clone
+    /// and no request ever sees a half-replaced table.
+    routes: RwLock<Arc<Routes>>,
+    /// Handlers registered with `add_handler`, by route.
+    handlers: HashMap<String, Vec<(HttpMethods, Handler)>>,
+    fallback: Handler,
+    middleware: Vec<Box<dyn Middleware>>,
+    logger: Arc<dyn Logger>,
+    trailing_slash: TrailingSlash,
+    expose_routes: bool,
+    writable: bool,
+    /// Mount point stripped before lookup, and whether requests must carry it.
+    path_prefix: Option<(String, bool)>,
+    /// Catch-all mounts as `(prefix segments, directory)`, longest first.
+    wildcards: Vec<(Vec<String>, PathBuf)>,
+}
+
+impl Router {
+    /// Creates a router over `routes` whose fallback serves
+    /// `pages/not_found.html` with a 404 status.
+    pub fn new<R: Into<Routes>>(routes: R) -> Router {
+        Router {
+            routes: RwLock::new(Arc::new(routes.into())),
+            handlers: HashMap::new(),
+            fallback: Box::new(|_request| not_found(Path::new(DEFAULT_NOT_FOUND_PAGE))),
+            middleware: Vec::new(),
+            logger: Arc::new(StderrLogger::new(LogLevel::Info)),
+            trailing_slash: TrailingSlash::Ignore,
+            expose_routes: false,
+            writable: false,
+            path_prefix: None,
+            wildcards: Vec::new(),
+        }
+    }
+
+    /// The current route table.
+    pub fn routes(&self) -> Arc<Routes> {
+        Arc::clone(&self.routes.read().unwrap())
+    }
+
+    /// Swaps in a new route table, e.g. one rebuilt after the document root
+    /// changed. Requests already routed finish against the old table.
+    pub fn replace_routes<R: Into<Routes>>(&self, routes: R) {
+        let routes = Arc::new(routes.into());
+        *self.routes.write().unwrap() = routes;
+    }
+
+    /// Serves the route table as plain text at `/__routes`. Meant for
+    /// debugging; leave it off in production.
+    pub fn set_expose_routes(&mut self, expose: bool) -> &mut Router {
+        self.expose_routes = expose;
+        self
+    }
+
+    /// Lets PUT replace and DELETE remove the files behind existing routes,
+    /// honouring `If-Match` and `If-Unmodified-Since`. New routes can't be
+    /// created this way, and embedded routes are never writable.
+    pub fn set_writable(&mut self, writable: bool) -> &mut Router {
+        self.writable = writable;
+        self
+    }
+
+    pub fn set_logger(&mut self, logger: Arc<dyn Logger>) -> &mut Router {
+        self.logger = logger;
+        self
+    }
+
+    /// Serves the site under `prefix` (e.g. `/app`): it is stripped from
+    /// request paths before lookup, so `/app/howdy` finds the `/howdy`
+    /// route, and added back to redirects. With `required`, paths outside
+    /// the prefix go to the fallback; otherwise they are looked up as-is.
+    pub fn set_path_prefix(&mut self, prefix: &str, required: bool) -> &mut Router {
+        let prefix = clean_route(prefix);
+        self.path_prefix = (!prefix.is_empty()).then_some((prefix, required));
+        self
+    }
+
+    pub fn set_trailing_slash(&mut self, policy: TrailingSlash) -> &mut Router {
+        self.trailing_slash = policy;
+        self
+    }
+
+    /// Makes the fallback serve the HTML at `path` with a 404 status. If it
+    /// can't be read, a built-in page is served instead.
+    pub fn set_not_found_page<P: Into<PathBuf>>(&mut self, path: P) -> &mut Router {
+        let path = path.into();
+        self.set_fallback(move |_request| not_found(&path))
+    }
+
+    /// Like `set_not_found_page`, but serves `contents` without touching the
+    /// filesystem.
+    pub fn set_not_found_contents(&mut self, contents: Vec<u8>) -> &mut Router {
+        self.set_fallback(move |_request| not_found_response(contents.clone()))
+    }
+
+    /// Registers a catch-all `pattern` such as `/docs/*`, serving any path
+    /// below the prefix from the matching file under `directory`. Exact
+    /// routes take precedence. Fails if `pattern` doesn't end in `/*`.
+    pub fn add_wildcard_route<P: Into<PathBuf>>(&mut self, pattern: &str, directory: P) -> Result<&mut Router, RouteError> {
+        let prefix = pattern
+            .strip_suffix("/*")
+            .ok_or_else(|| RouteError::InvalidWildcard(pattern.to_string()))?;
+        let segments: Vec<String> = prefix
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .map(String::from)
+            .collect();
+        self.wildcards.push((segments, directory.into()));
+        self.wildcards.sort_by_key(|(prefix, _)| std::cmp::Reverse(prefix.len()));
+        Ok(self)
+    }
+
+    /// Answers `method` requests to `route` with `handler` instead of a
+    /// file. `route` may also be served statically, in which case other
+    /// methods still get the file. A GET handler also answers HEAD.
+    pub fn add_handler<F>(&mut self, method: HttpMethods, route: &str, handler: F) -> &mut Router
+    where
+        F: Fn(&HttpRequest) -> HttpResponse + Send + Sync + 'static,
+    {
+        self.handlers.entry(clean_route(route)).or_default().push((method, Box::new(handler)));
+        self
+    }
+
+    /// The methods `route` answers, as listed in `Allow`: GET and HEAD for
+    /// files and wildcard paths (plus PUT and DELETE for writable files), the
+    /// methods of its handlers, and OPTIONS. `None` if nothing is served there.
+    pub fn allowed_methods(&self, route: &str) -> Option<Vec<HttpMethods>> {
+        let handlers = self.handlers.get(route);
+        let routes = self.routes();
+        let is_file = routes.get(route).is_some();
+        let segments: Vec<&str> = route.split('/').filter(|segment| !segment.is_empty()).collect();
+        let is_wildcard = self.wildcard_for(&segments).is_some();
+        if handlers.is_none() && !is_file && !is_wildcard {
+            return None;
+        }
+
+        let mut methods = Vec::new();
+        let mut allow = |method: HttpMethods| {
+            if !methods.contains(&method) {
+                methods.push(method);
+            }
+        };
+        if is_file || is_wildcard {
+            allow(HttpMethods::GET);
+            allow(HttpMethods::HEAD);
+        }
+        if is_file && self.writable && routes.contents(route).is_none() {
+            allow(HttpMethods::PUT);
+            allow(HttpMethods::DELETE);
+        }
+        for (method, _) in handlers.into_iter().flatten() {
+            allow(method.clone());
+            if *method == HttpMethods::GET {
+                allow(HttpMethods::HEAD);
+            }
+        }
+        allow(HttpMethods::OPTIONS);
+        Some(methods)
+    }
+
+    /// Replaces the handler invoked for requests that match no route, e.g. to
+    /// render a custom 404, redirect, or serve an SPA entry point.
+    pub fn set_fallback<F>(&mut self, fallback: F) -> &mut Router
+    where
+        F: Fn(&HttpRequest) -> HttpResponse + Send + Sync + 'static,
+    {
+        self.fallback = Box::new(fallback);
+        self
+    }
+
+    /// Appends `middleware` to the chain. The first registered middleware is
+    /// the outermost, seeing the request first and the response last.
+    pub fn add_middleware<M>(&mut self, middleware: M) -> &mut Router
+    where
+        M: Middleware + 'static,
+    {
+        self.middleware.push(Box::new(middleware));
+        self
+    }
+
+    pub fn handle(&self, request: &HttpRequest) -> HttpResponse {
+        self.run_chain(0, request)
+    }
+
+    fn run_chain(&self, index: usize, request: &HttpRequest) -> HttpResponse {
+        match self.middleware.get(index) {
+            Some(middleware) => middleware.handle(request, &|request| self.run_chain(index + 1, request)),
+            None => self.route(request),
+        }
+    }
+
+    fn route(&self, request: &HttpRequest) -> HttpResponse {
+        let clean_target = request.normalized_path();
+        let (clean_target, mount) = match &self.path_prefix {
+            Some((prefix, required)) => match strip_path_prefix(&clean_target, prefix) {
+                Some(rest) => (rest.to_string(), prefix.as_str()),
+                None if *required => return (self.fallback)(request),
+                None => (clean_target, ""),
+            },
+            None => (clean_target, ""),
+        };
+        let raw_target = strip_path_prefix(request.target_without_query(), mount).unwrap_or(request.target_without_query());
+
+        if self.expose_routes && clean_target == "/__routes" {
+            return self.routes_response();
+        }
+
+        if let Some(handlers) = self.handlers.get(&clean_target) {
+            let find = |wanted: &HttpMethods| handlers.iter().find(|(method, _)| method == wanted);
+            let handler = match find(&request.method) {
+                None if request.method == HttpMethods::HEAD => find(&HttpMethods::GET),
+                found => found,
+            };
+            if let Some((_, handler)) = handler {
+                return handler(request);
+            }
+        }
+        if let Some(methods) = self.allowed_methods(&clean_target) {
+            if request.method == HttpMethods::OPTIONS {
+                return options_response(&methods);
+            }
+            if !methods.contains(&request.method) {
+                return method_not_allowed(&methods);
+            }
+        }
+
+        let routes = self.routes();
+        let Some(path) = routes.get(&clean_target) else {
+            if let Some(response) = self.route_wildcard(request, raw_target) {
+                return response;
+            }
+            return (self.fallback)(request);
+        };
+
+        if let Some(location) = self.canonical_location(raw_target, &clean_target, path) {
+            let location = match request.query() {
+                Some(query) => format!("{mount}{location}?{query}"),
+                None => format!("{mount}{location}"),
+            };
+            return redirect_response(&location);
+        }
+
+        let is_write = matches!(request.method, HttpMethods::PUT | HttpMethods::DELETE);
+        let mut response = match routes.contents(&clean_target) {
+            Some(contents) => virtual_file_response(path, contents),
+            None if self.writable && is_write => return self.write_file(request, path),
+            None => self.serve_file(request, path),
+        };
+        if response.status_code() == 200 {
+            apply_headers(&mut response, routes.headers_for(&clean_target));
+        }
+        response
+    }
+
+    fn serve_file(&self, request: &HttpRequest, path: &Path) -> HttpResponse {
+        let sidecar = gzip_sidecar(path);
+        if let Some(sidecar) = &sidecar
+            && select_encoding(request) == Encoding::Gzip
+            && let Ok(mut response) = HttpResponse::from_file(String::from("HTTP/1.1"), sidecar)
+        {
+            let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
+            response.add_header("Content-Type".to_string(), get_mime_type(extension).to_string());
+            response.add_header("Content-Encoding".to_string(), "gzip".to_string());
+            response.add_header("Accept-Ranges".to_string(), "bytes".to_string());
+            response.append_header("Vary".to_string(), "Accept-Encoding".to_string());
+            return response;
+        }
+
+        match HttpResponse::from_file(String::from("HTTP/1.1"), path) {
+            Ok(mut response) => {
+                response.add_header("Accept-Ranges".to_string(), "bytes".to_string());
+                if sidecar.is_some() {
+                    response.append_header("Vary".to_string(), "Accept-Encoding".to_string());
+                }
+                response
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (self.fallback)(request),
+            Err(e) => {
+                self.logger.error(&format!("Failed to read {}: {e}", path.display()));
+                error_response(status_for_io_error(&e))
+            }
+        }
+    }
+
+    /// Applies a PUT or DELETE to the file at `path`, unless the client's
+    /// preconditions say it has changed since they last saw it. A `.gz`
+    /// sidecar is removed too, so the stale compressed copy isn't served.
+    fn write_file(&self, request: &HttpRequest, path: &Path) -> HttpResponse {
+        let current = fs::metadata(path).ok().filter(|meta| meta.is_file());
+        let validators = current.as_ref().and_then(Validators::from_metadata);
+        if !preconditions_hold(request, validators.as_ref()) {
+            return error_response(412);
+        }
+
+        let result = match request.method {
+            HttpMethods::DELETE if current.is_none() => return (self.fallback)(request),
+            HttpMethods::DELETE => fs::remove_file(path),
+            // A body spooled to disk for an upload route is copied over whole.
+            _ => match request.body_file() {
+                Some(spooled) => fs::copy(spooled, path).map(|_| ()),
+                None => fs::write(path, request.try_get_body().unwrap_or_default()),
+            },
+        };
+        let result = result.and_then(|()| match gzip_sidecar(path) {
+            Some(sidecar) => fs::remove_file(sidecar),
+            None => Ok(()),
+        });
+        if let Err(e) = result {
+            self.logger.error(&format!("Failed to {} {}: {e}", request.method, path.display()));
+            return error_response(status_for_io_error(&e));
+        }
+
+        let mut response = HttpResponse::build(String::from("HTTP/1.1"), 204);
+        if let Some(validators) = fs::metadata(path).ok().as_ref().and_then(Validators::from_metadata) {
+            response.add_header("ETag".to_string(), validators.etag);
+        }
+        response
+    }
+
+    /// Serves `request` from the first catch-all mount whose prefix it falls
+    /// under. `..` segments are rejected outright rather than cleaned away,
+    /// and the resolved file must stay inside the mounted directory.
+    /// The longest wildcard whose prefix `segments` extends.
+    fn wildcard_for(&self, segments: &[&str]) -> Option<&(Vec<String>, PathBuf)> {
+        self.wildcards.iter().find(|(prefix, _)| {
+            segments.len() > prefix.len() && prefix.iter().zip(segments).all(|(a, b)| a == b)
+        })
+    }
+
+    fn route_wildcard(&self, request: &HttpRequest, target: &str) -> Option<HttpResponse> {
+        let segments: Vec<&str> = target
+            .split('/')
+            .filter(|segment| !segment.is_empty() && *segment != ".")
+            .collect();
+
+        let (prefix, directory) = self.wildcard_for(&segments)?;
+        let rest = &segments[prefix.len()..];
+        if rest.iter().any(|segment| *segment == ".." || segment.contains('\\')) {
+            self.logger.warn(&format!("Rejected traversal attempt: {}", request.target));
+            return Some(error_response(400));
+        }
+        // Hidden directories and the files `route_for_file` skips stay
+        // unrouted here too.
+        let (name, dirs) = rest.split_last()?;
+        if dirs.iter().any(|segment| segment.starts_with('.')) || route_for_file(target, name).is_none() {
+            return Some((self.fallback)(request));
+        }
+
+        let mut path = directory.join(rest.join("/"));
+        if path.is_dir() {
+            path.push("index.html");
+        }
+        let (Ok(root), Ok(resolved)) = (directory.canonicalize(), path.canonicalize()) else {
+            return Some((self.fallback)(request));
+        };
+        if !resolved.starts_with(&root) {
+            self.logger.warn(&format!("Rejected path outside {}: {}", root.display(), resolved.display()));
+            return Some(error_response(403));
+        }
+
+        Some(match HttpResponse::from_file(String::from("HTTP/1.1"), &resolved) {
+            Ok(response) => response,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (self.fallback)(request),
+            Err(e) => {
+                self.logger.error(&format!("Failed to read {}: {e}", resolved.display()));
+                error_response(status_for_io_error(&e))
+            }
+        })
+    }
+
+    fn routes_response(&self) -> HttpResponse {
+        let body: String = self
+            .routes()
+            .list()
+            .iter()
+            .map(|(route, path)| format!("{route} -> {}\n", path.display()))
+            .collect();
+        let mut response = HttpResponse::build(String::from("HTTP/1.1"), 200);
+        response.add_header("Content-Type".to_string(), get_mime_type("txt").to_string());
+        response.add_body(body.into());
+        response
+    }
+
+    /// Returns where to redirect `target` when it doesn't match the trailing
+    /// slash policy. Only directory-style routes are redirected, never files
+    /// or the root.
+    fn canonical_location(&self, target: &str, clean_target: &str, path: &Path) -> Option<String> {
+        if clean_target == "/" || !is_directory_route(path) {
+            return None;
+        }
+        let has_slash = target.ends_with('/');
+        match self.trailing_slash {
+            TrailingSlash::Strip if has_slash => Some(clean_target.to_string()),
+            TrailingSlash::Append if !has_slash => Some(format!("{clean_target}/")),
+            _ => None,
+        }
+    }
+}
+
+/// `path` with the mount point `prefix` removed (`/app/x` → `/x`, `/app` →
+/// `/`), or `None` if it lies outside it. An empty prefix matches anything.
+fn strip_path_prefix<'a>(path: &'a str, prefix: &str) -> Option<&'a str> {
+    let rest = path.strip_prefix(prefix)?;
+    match rest {
+        "" => Some("/"),
+        _ if rest.starts_with('/') => Some(rest),
+        _ => None,
+    }
+}
+
+fn is_directory_route(path: &Path) -> bool {
+    matches!(
+        path.file_name().and_then(|name| name.to_str()),
+        Some("index.html") | Some("page.html")
+    )
+}
+
+fn options_response(methods: &[HttpMethods]) -> HttpResponse {
+    let allow: Vec<String> = methods.iter().map(HttpMethods::to_string).collect();
+    let mut response = HttpResponse::build(String::from("HTTP/1.1"), 204);
+    response.add_header("Allow".to_string(), allow.join(", "));
+    response
+}
+
+/// The `405` for a method `route` doesn't answer, with the same `Allow`
+/// list as its `OPTIONS` response.
+fn method_not_allowed(methods: &[HttpMethods]) -> HttpResponse {
+    let allow: Vec<String> = methods.iter().map(HttpMethods::to_string).collect();
+    let mut response = error_response(405);
+    response.add_header("Allow".to_string(), allow.join(", "));
+    response
+}
+
+fn redirect_response(location: &str) -> HttpResponse {
+    let mut response = HttpResponse::build(String::from("HTTP/1.1"), 301);
+    response.add_header("Location".to_string(), location.to_string());
+    response.add_body(Vec::new());
+    response
+}
+
+/// Returns the pre-compressed `<path>.gz` sibling of `path`, if one exists.
+fn gzip_sidecar(path: &Path) -> Option<PathBuf> {
+    let mut sidecar = path.as_os_str().to_owned();
+    sidecar.push(".gz");
+    let sidecar = PathBuf::from(sidecar);
+    sidecar.is_file().then_some(sidecar)
+}
+
+/// Whether `path` is the pre-compressed copy of a file next to it, which is
+/// served in place of that file rather than under its own route.
+fn is_gzip_sidecar(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "gz") && path.with_extension("").is_file()
+}
+
+/// Maps a failure to read a routed file to the status code to respond with.
+pub fn status_for_io_error(error: &io::Error) -> u16 {
+    match error.kind() {
+        io::ErrorKind::NotFound => 404,
+        io::ErrorKind::PermissionDenied => 403,
+        _ => 500,
+    }
+}
+
+fn error_response(code: u16) -> HttpResponse {
+    let mut response = HttpResponse::build(String::from("HTTP/1.1"), code);
+    let body = format!("{code} {}", get_status_phrase(code));
+    response.add_header("Content-Type".to_string(), get_mime_type("txt").to_string());
+    response.add_body(body.into());
+    response
+}
+
+fn not_found(page: &Path) -> HttpResponse {
+    not_found_response(fs::read(page).unwrap_or_else(|_| BUILTIN_NOT_FOUND_HTML.into()))
+}
+
+fn not_found_response(contents: Vec<u8>) -> HttpResponse {
+    let mut response = HttpResponse::build(String::from("HTTP/1.1"), 404);
+    response.add_header("Content-Type".to_string(), get_mime_type("html").to_string());
+    response.add_body(contents);
+    response
+}
+
+pub fn clean_route(route: &str) -> String {
+    let mut clean_route = String::from("");
+    for part in route.split("/").collect::<Vec<_>>() {
+        if part.is_empty() || part == "." || part == ".." {
+            continue;
+        }
+        clean_route.push_str(format!("/{part}").as_str());
+    }
+    if clean_route.is_empty() {
+        clean_route = String::from("/");
+    }
+    clean_route
+}
+
+/// Routes every servable file under `directory`, which is routed at
+/// `route`. Fails if a directory can't be listed; entries whose names
+/// aren't UTF-8 can't be routed and are skipped.
+pub fn build_routes(route: String, directory: &Path) -> io::Result<Routes> {
+    let mut routes = Routes::default();
+
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(|name| name.to_str()) else { continue; };
+        if path.is_dir() {
+            // Hidden directories (`.git`, `.ssh`) are never served.
+            if name.starts_with('.') {
+                continue;
+            }
+            routes.extend(
+                build_routes(format!("{route}/{name}"), &path)?
+            );
+        } else if path.is_file()
+            && !is_gzip_sidecar(&path)
+            && let Some(file_route) = route_for_file(&route, name)
+        {
+            routes.insert_file(file_route, path);
+        }
+    }
+
+    Ok(routes)
+}
+
+/// The route a file named `name` in the directory routed at `route` is
+/// served under: `index.html`/`page.html` take the directory's own route,
+/// any other file its full path. `not_found.html`, `.headers` sidecars and
+/// hidden files (such as `.env`) aren't routed.
+fn route_for_file(route: &str, name: &str) -> Option<String> {
+    if name.starts_with('.') || name.ends_with(".headers") {
+        return None;
+    }
+    match name {
+        "index.html" | "page.html" if route.is_empty() => Some(String::from("/")),
+        "index.html" | "page.html" => Some(route.to_string()),
+        "not_found.html" => None,
+        crate::maintenance::MARKER_FILE if route.is_empty() => None,
+        _ => Some(format!("{route}/{name}")),
+    }
+}
+
+/// Builds one route table from several document roots. A route found in
+/// more than one root is served from the earliest root in `roots`, so list
+/// overrides (e.g. a tenant's directory) before the base site.
+pub fn build_routes_overlay(roots: &[PathBuf]) -> io::Result<Routes> {
+    let mut routes = Routes::default();
+    for root in roots.iter().rev() {
+        routes.extend(build_routes(String::from(""), root)?);
+    }
+    Ok(routes)
+}
+
+/// A route that can't be registered as given.
+#[derive(Debug, PartialEq)]
+pub enum RouteError {
+    /// A wildcard pattern that doesn't end in `/*`.
+    InvalidWildcard(String),
+}
+
+impl fmt::Display for RouteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RouteError::InvalidWildcard(pattern) => write!(f, "Wildcard route {pattern} must end with /*"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum RouteWarning {
+    Unreadable { route: String, path: PathBuf },
+    Empty { route: String, path: PathBuf },
+    MissingNotFoundPage(PathBuf),
+}
+
+impl RouteWarning {
+    /// Whether this is a deployment error rather than a suspicious entry.
+    pub fn is_error(&self) -> bool {
+        matches!(self, RouteWarning::MissingNotFoundPage(_))
+    }
+}
+
+impl fmt::Display for RouteWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RouteWarning::Unreadable { route, path } => {
+                write!(f, "Route {route} maps to unreadable file {}", path.display())
+            }
+            RouteWarning::Empty { route, path } => {
+                write!(f, "Route {route} maps to empty file {}", path.display())
+            }
+            RouteWarning::MissingNotFoundPage(path) => {
+                write!(f, "404 page {} is missing", path.display())
+            }
+        }
+    }
+}
+
+/// Checks that every route resolves to a readable, non-empty file and that
+/// the 404 page exists, returning problems sorted by route.
+pub fn validate_routes(routes: &Routes, not_found_page: &Path) -> Vec<RouteWarning> {
+    let mut warnings = Vec::new();
+    for (route, path) in routes.list() {
+        if routes.contents(route).is_some() {
+            continue;
+        }
+        let route = route.to_string();
+        let path = path.to_path_buf();
+        match fs::File::open(&path).and_then(|file| file.metadata()) {
+            Ok(meta) if !meta.is_file() => warnings.push(RouteWarning::Unreadable { route, path }),
+            Ok(meta) if meta.len() == 0 => warnings.push(RouteWarning::Empty { route, path }),
+            Ok(_) => {}
+            Err(_) => warnings.push(RouteWarning::Unreadable { route, path }),
+        }
+    }
+
+    if !not_found_page.is_file() {
+        warnings.push(RouteWarning::MissingNotFoundPage(not_found_page.to_path_buf()));
+    }
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::http_methods::HttpMethods;
+
+    fn get(target: &str) -> HttpRequest {
+        HttpRequest::build(HttpMethods::GET, target.to_string(), "HTTP/1.1".to_string())
+    }
+
+    #[test]
+    fn clean_route_strips_empty_and_dot_segments() {
+        assert_eq!(clean_route("//a/./b/../c/"), "/a/b/c");
+        assert_eq!(clean_route(""), "/");
+        assert_eq!(clean_route("/.."), "/");
+    }
+
+    #[test]
+    fn handle_serves_matched_route() {
+        let path = std::env::temp_dir()
+            .join(format!("rcomm_router_match_{}.html", std::process::id()));
+        fs::write(&path, "<h1>hi</h1>").unwrap();
+        let mut routes = HashMap::new();
+        routes.insert(String::from("/hi"), path.clone());
+
+        let router = Router::new(routes);
+        let resp = router.handle(&get("/hi/"));
+        fs::remove_file(&path).unwrap();
+
+        assert!(format!("{resp}").starts_with("HTTP/1.1 200 OK\r\n"));
+        assert_eq!(resp.try_get_body(), Some(b"<h1>hi</h1>".to_vec()));
+    }
+
+    #[test]
+    fn handle_serves_virtual_routes_from_memory() {
+        let routes = Routes::from_virtual_files(vec![
+            (String::from("index.html"), b"<h1>home</h1>".to_vec()),
+            (String::from("foo/page.html"), b"<h1>foo</h1>".to_vec()),
+            (String::from("foo/style.css"), b"h1 {}".to_vec()),
+            (String::from("not_found.html"), b"missing".to_vec()),
+            (String::from("foo/style.css.gz"), b"skipped".to_vec()),
+            (String::from("foo/style.css.headers"), b"skipped".to_vec()),
+            (String::from(".env"), b"skipped".to_vec()),
+            (String::from(".git/HEAD"), b"skipped".to_vec()),
+            (String::from("foo/.ssh/id_rsa"), b"skipped".to_vec()),
+            (String::from("notes.txt"), b"notes".to_vec()),
+        ]);
+        assert_eq!(
+            routes.list().iter().map(|(route, _)| *route).collect::<Vec<_>>(),
+            vec!["/", "/foo", "/foo/style.css", "/notes.txt"]
+        );
+        let warnings = validate_routes(&routes, Path::new("/nonexistent/not_found.html"));
+        assert!(matches!(warnings.as_slice(), [RouteWarning::MissingNotFoundPage(_)]));
+
+        let router = Router::new(routes);
+        let resp = router.handle(&get("/foo"));
+        assert_eq!(resp.status_code(), 200);
+        assert_eq!(resp.try_get_header("content-type".to_string()), Some(get_mime_type("html").to_string()));
+        assert_eq!(resp.try_get_body(), Some(b"<h1>foo</h1>".to_vec()));
+
+        let resp = router.handle(&get("/foo//?v=2#top"));
+        assert_eq!(resp.try_get_body(), Some(b"<h1>foo</h1>".to_vec()));
+
+        let resp = router.handle(&get("/foo/style.css"));
+        assert_eq!(resp.try_get_header("content-type".to_string()), Some(get_mime_type("css").to_string()));
+    }
+
+    #[test]
+    fn build_routes_serves_fonts_with_their_type() {
+        let dir = std::env::temp_dir().join(format!("rcomm_router_fonts_{}", std::process::id()));
+        fs::create_dir_all(dir.join("fonts")).unwrap();
+        fs::write(dir.join("fonts/body.woff2"), b"wOF2").unwrap();
+        let routes = build_routes(String::new(), &dir).unwrap();
+        let router = Router::new(routes);
+        let resp = router.handle(&get("/fonts/body.woff2"));
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(resp.status_code(), 200);
+        assert_eq!(resp.try_get_header("content-type".to_string()), Some("font/woff2".to_string()));
+        assert_eq!(resp.try_get_body(), Some(b"wOF2".to_vec()));
+    }
+
+    #[test]
+    fn build_routes_serves_any_file_type() {
+        let dir = std::env::temp_dir().join(format!("rcomm_router_assets_{}", std::process::id()));
+        fs::create_dir_all(dir.join("img")).unwrap();
+        fs::write(dir.join("img/logo.png"), b"\x89PNG").unwrap();
+        fs::write(dir.join("LICENSE"), b"MIT").unwrap();
+        fs::write(dir.join("MAINTENANCE"), b"").unwrap();
+        fs::create_dir_all(dir.join(".git")).unwrap();
+        fs::write(dir.join(".git/HEAD"), b"ref: refs/heads/main").unwrap();
+        let routes = build_routes(String::new(), &dir).unwrap();
+        assert!(!routes.contains("/.git/HEAD"));
+        let router = Router::new(routes);
+        let png = router.handle(&get("/img/logo.png"));
+        let license = router.handle(&get("/LICENSE"));
+        let marker = router.handle(&get("/MAINTENANCE"));
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(marker.status_code(), 404);
+        assert_eq!(png.status_code(), 200);
+        assert_eq!(png.try_get_header("content-type".to_string()), Some("image/png".to_string()));
+        assert_eq!(license.try_get_header("content-type".to_string()), Some("application/octet-stream".to_string()));
+    }
+
+    #[test]
+    fn fallback_handles_unmatched_routes() {
+        let mut router = Router::new(HashMap::new());
+        router.set_fallback(|_request| {
+            let mut response = HttpResponse::build(String::from("HTTP/1.1"), 302);
+            response.add_header("Location".to_string(), "/".to_string());
+            response
+        });
+
+        let resp = router.handle(&get("/missing"));
+        assert!(format!("{resp}").starts_with("HTTP/1.1 302 Found\r\n"));
+        assert_eq!(resp.try_get_header("location".to_string()), Some("/".to_string()));
+    }
+
+    #[test]
+    fn status_for_io_error_maps_kinds() {
+        assert_eq!(status_for_io_error(&io::Error::from(io::ErrorKind::NotFound)), 404);
+        assert_eq!(status_for_io_error(&io::Error::from(io::ErrorKind::PermissionDenied)), 403);
+        assert_eq!(status_for_io_error(&io::Error::from(io::ErrorKind::InvalidData)), 500);
+    }
+
+    #[test]
+    fn handle_serves_zero_length_file() {
+        let path = std::env::temp_dir()
+            .join(format!("rcomm_router_empty_{}.html", std::process::id()));
+        fs::write(&path, "").unwrap();
+        let mut routes = HashMap::new();
+        routes.insert(String::from("/empty"), path.clone());
+
+        let resp = Router::new(routes).handle(&get("/empty"));
+        fs::remove_file(&path).unwrap();
+
+        assert!(format!("{resp}").starts_with("HTTP/1.1 200 OK\r\n"));
+        assert_eq!(resp.try_get_header("content-length".to_string()), Some("0".to_string()));
+    }
+
+    #[test]
+    fn put_honours_if_match() {
+        let path = std::env::temp_dir()
+            .join(format!("rcomm_router_writable_{}.html", std::process::id()));
+        fs::write(&path, "v1").unwrap();
+        let mut routes = HashMap::new();
+        routes.insert(String::from("/doc"), path.clone());
+        let mut router = Router::new(routes);
+        router.set_writable(true);
+
+        let etag = router.handle(&get("/doc")).try_get_header("etag".to_string()).unwrap();
+        let put = |if_match: &str, body: &str| {
+            let request = HttpRequest::build(HttpMethods::PUT, "/doc".to_string(), "HTTP/1.1".to_string())
+                .with_header("If-Match".to_string(), if_match.to_string())
+                .with_body(body.as_bytes().to_vec());
+            router.handle(&request)
+        };
+
+        let stale = put("\"stale\"", "v2");
+        assert_eq!(stale.status_code(), 412);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "v1");
+
+        let fresh = put(&etag, "v2");
+        let contents = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(fresh.status_code(), 204);
+        assert_eq!(contents, "v2");
+    }
+
+    #[test]
+    fn put_copies_spooled_body_and_drops_gzip_sidecar() {
+        use crate::models::http_request::ParseOptions;
+
+        let dir = std::env::temp_dir().join(format!("rcomm_router_put_spooled_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("doc.txt");
+        fs::write(&path, "old").unwrap();
+        fs::write(dir.join("doc.txt.gz"), "old, compressed").unwrap();
+        let mut router = Router::new(HashMap::from([(String::from("/doc"), path.clone())]));
+        router.set_writable(true);
+
+        let raw = b"PUT /doc HTTP/1.1\r\nHost: x\r\nContent-Length: 3\r\n\r\nnew";
+        let options = ParseOptions { upload_routes: vec![String::from("/doc")], ..ParseOptions::default() };
+        let request = HttpRequest::build_from_reader_with(&mut &raw[..], &options).unwrap();
+        assert!(request.body_file().is_some());
+        let response = router.handle(&request);
+        let contents = fs::read_to_string(&path).unwrap();
+        let sidecar_left = dir.join("doc.txt.gz").exists();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(response.status_code(), 204);
+        assert_eq!(contents, "new");
+        assert!(!sidecar_left);
+    }
+
+    #[test]
+    fn writes_get_405_unless_writable() {
+        let path = std::env::temp_dir()
+            .join(format!("rcomm_router_read_only_{}.html", std::process::id()));
+        fs::write(&path, "v1").unwrap();
+        let mut routes = HashMap::new();
+        routes.insert(String::from("/doc"), path.clone());
+        let router = Router::new(routes);
+
+        let mut responses = Vec::new();
+        for method in [HttpMethods::DELETE, HttpMethods::PUT, HttpMethods::POST, HttpMethods::PATCH] {
+            responses.push(router.handle(&HttpRequest::build(method, "/doc".to_string(), "HTTP/1.1".to_string())));
+        }
+        let still_there = path.exists();
+        fs::remove_file(&path).unwrap();
+        for resp in responses {
+            assert_eq!(resp.status_code(), 405);
+            assert_eq!(resp.try_get_header("allow".to_string()), Some("GET, HEAD, OPTIONS".to_string()));
+        }
+        assert!(still_there);
+    }
+
+    #[test]
+    fn handler_route_answers_unregistered_methods_with_405() {
+        let mut router = Router::new(HashMap::new());
+        router.add_handler(HttpMethods::POST, "/submit", |_request| HttpResponse::build(String::from("HTTP/1.1"), 201));
+
+        let resp = router.handle(&get("/submit"));
+        assert_eq!(resp.status_code(), 405);
+        assert_eq!(resp.try_get_header("allow".to_string()), Some("POST, OPTIONS".to_string()));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn handle_maps_unreadable_file_to_403() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = std::env::temp_dir()
+            .join(format!("rcomm_router_unreadable_{}.html", std::process::id()));
+        fs::write(&path, "secret").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o000)).unwrap();
+        let mut routes = HashMap::new();
+        routes.insert(String::from("/secret"), path.clone());
+
+        // Privileged users (e.g. root in CI containers) bypass permission bits.
+        if fs::read(&path).is_ok() {
+            fs::remove_file(&path).unwrap();
+            return;
+        }
+
+        let resp = Router::new(routes).handle(&get("/secret"));
+        fs::remove_file(&path).unwrap();
+
+        assert!(format!("{resp}").starts_with("HTTP/1.1 403 Forbidden\r\n"));
+    }
+
+    #[test]
+    fn handle_maps_other_read_errors_to_500() {
+        // Reading a directory as a file fails with an error other than
+        // NotFound or PermissionDenied.
+        let mut routes = HashMap::new();
+        routes.insert(String::from("/dir"), std::env::temp_dir());
+
+        let resp = Router::new(routes).handle(&get("/dir"));
+        assert!(format!("{resp}").starts_with("HTTP/1.1 500 Internal Server Error\r\n"));
+    }
+
+    #[test]
+    fn validate_routes_flags_missing_empty_files_and_404_page() {
+        let root = std::env::temp_dir()
+            .join(format!("rcomm_validate_routes_{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("ok.html"), "<p>ok</p>").unwrap();
+        fs::write(root.join("empty.html"), "").unwrap();
+
+        let mut routes = HashMap::new();
+        routes.insert(String::from("/ok"), root.join("ok.html"));
+        routes.insert(String::from("/empty"), root.join("empty.html"));
+        routes.insert(String::from("/gone"), root.join("gone.html"));
+
+        let warnings = validate_routes(&Routes::from(routes), &root.join("not_found.html"));
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(warnings, vec![
+            RouteWarning::Empty { route: String::from("/empty"), path: root.join("empty.html") },
+            RouteWarning::Unreadable { route: String::from("/gone"), path: root.join("gone.html") },
+            RouteWarning::MissingNotFoundPage(root.join("not_found.html")),
+        ]);
+        assert!(warnings[2].is_error());
+        assert!(!warnings[0].is_error());
+    }
+
+    #[test]
+    fn validate_routes_accepts_pages_directory() {
+        let root = Path::new("./pages");
+        let routes = build_routes(String::from(""), root).unwrap();
+        assert_eq!(validate_routes(&routes, Path::new(DEFAULT_NOT_FOUND_PAGE)), Vec::new());
+    }
+
+    #[test]
+    fn handle_serves_gzip_sidecar_when_accepted() {
+        let root = std::env::temp_dir()
+            .join(format!("rcomm_router_sidecar_{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("style.css"), "body { color: red; }").unwrap();
+        fs::write(root.join("style.css.gz"), b"\x1f\x8bpretend-gzip").unwrap();
+        let mut routes = HashMap::new();
+        routes.insert(String::from("/style.css"), root.join("style.css"));
+        let router = Router::new(routes);
+
+        let mut gzip_req = get("/style.css");
+        gzip_req.add_header("Accept-Encoding".to_string(), "br, gzip".to_string());
+        let gzipped = router.handle(&gzip_req);
+        let plain = router.handle(&get("/style.css"));
+        let mut refused_req = get("/style.css");
+        refused_req.add_header("Accept-Encoding".to_string(), "gzip;q=0".to_string());
+        let refused = router.handle(&refused_req);
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(gzipped.try_get_body(), Some(b"\x1f\x8bpretend-gzip".to_vec()));
+        assert_eq!(gzipped.try_get_header("content-encoding".to_string()), Some("gzip".to_string()));
+        assert_eq!(gzipped.try_get_header("content-type".to_string()), Some("text/css; charset=utf-8".to_string()));
+        assert_eq!(gzipped.try_get_header("vary".to_string()), Some("Accept-Encoding".to_string()));
+
+        assert_eq!(plain.try_get_body(), Some(b"body { color: red; }".to_vec()));
+        assert_eq!(plain.try_get_header("content-encoding".to_string()), None);
+        assert_eq!(plain.try_get_header("vary".to_string()), Some("Accept-Encoding".to_string()));
+
+        assert_eq!(refused.try_get_header("content-encoding".to_string()), None);
+    }
+
+    #[test]
+    fn options_lists_methods_supported_by_route() {
+        let mut routes = HashMap::new();
+        routes.insert(String::from("/howdy"), PathBuf::from("pages/howdy/page.html"));
+        routes.insert(String::from("/"), PathBuf::from("pages/index.html"));
+        let mut router = Router::new(routes);
+        router.add_handler(HttpMethods::POST, "/", |_request| HttpResponse::build(String::from("HTTP/1.1"), 201));
+        router.add_handler(HttpMethods::GET, "/api", |_request| HttpResponse::build(String::from("HTTP/1.1"), 200));
+
+        let options = |target: &str| {
+            router.handle(&HttpRequest::build(HttpMethods::OPTIONS, target.to_string(), "HTTP/1.1".to_string()))
+        };
+        let resp = options("/howdy");
+        assert_eq!(resp.status_code(), 204);
+        assert_eq!(resp.try_get_header("allow".to_string()), Some("GET, HEAD, OPTIONS".to_string()));
+        let allow = options("/").try_get_header("allow".to_string());
+        assert_eq!(allow, Some("GET, HEAD, POST, OPTIONS".to_string()));
+        let allow = options("/api").try_get_header("allow".to_string());
+        assert_eq!(allow, Some("GET, HEAD, OPTIONS".to_string()));
+        assert_eq!(options("/nope").status_code(), 404);
+
+        let post = HttpRequest::build(HttpMethods::POST, "/".to_string(), "HTTP/1.1".to_string());
+        assert_eq!(router.handle(&post).status_code(), 201);
+        assert_eq!(router.handle(&get("/")).status_code(), 200);
+        let head = HttpRequest::build(HttpMethods::HEAD, "/api".to_string(), "HTTP/1.1".to_string());
+        assert_eq!(router.handle(&head).status_code(), 200);
+    }
+
+    #[test]
+    fn path_prefix_is_stripped_before_lookup_and_restored_in_redirects() {
+        let mut routes = HashMap::new();
+        routes.insert(String::from("/"), PathBuf::from("pages/index.html"));
+        routes.insert(String::from("/howdy"), PathBuf::from("pages/howdy/page.html"));
+        let mut router = Router::new(routes);
+        router.set_fallback(|_request| HttpResponse::build(String::from("HTTP/1.1"), 404));
+        router.set_trailing_slash(TrailingSlash::Strip);
+
+        router.set_path_prefix("/app/", true);
+        assert_eq!(router.handle(&get("/app/howdy")).status_code(), 200);
+        assert_eq!(router.handle(&get("/app")).status_code(), 200);
+        assert_eq!(router.handle(&get("/howdy")).status_code(), 404);
+        assert_eq!(router.handle(&get("/application/howdy")).status_code(), 404);
+        let resp = router.handle(&get("/app/howdy/?x=1"));
+        assert_eq!(resp.try_get_header("location".to_string()), Some("/app/howdy?x=1".to_string()));
+
+        router.set_path_prefix("/app", false);
+        assert_eq!(router.handle(&get("/howdy")).status_code(), 200);
+    }
+
+    #[test]
+    fn trailing_slash_policy_only_redirects_directory_routes() {
+        let mut routes = HashMap::new();
+        routes.insert(String::from("/howdy"), PathBuf::from("pages/howdy/page.html"));
+        routes.insert(String::from("/howdy/page.css"), PathBuf::from("pages/howdy/page.css"));
+        let mut router = Router::new(routes);
+
+        router.set_trailing_slash(TrailingSlash::Strip);
+        let resp = router.handle(&get("/howdy/"));
+        assert_eq!(resp.status_code(), 301);
+        assert_eq!(resp.try_get_header("location".to_string()), Some("/howdy".to_string()));
+        assert_eq!(router.handle(&get("/howdy/page.css/")).status_code(), 200);
+
+        router.set_trailing_slash(TrailingSlash::Append);
+        let resp = router.handle(&get("/howdy"));
+        assert_eq!(resp.status_code(), 301);
+        assert_eq!(resp.try_get_header("location".to_string()), Some("/howdy/".to_string()));
+        assert_eq!(router.handle(&get("/howdy/")).status_code(), 200);
+    }
+
+    #[test]
+    fn routes_list_reflects_built_directory() {
+        let root = std::env::temp_dir().join(format!("rcomm_routes_list_{}", std::process::id()));
+        fs::create_dir_all(root.join("about")).unwrap();
+        fs::write(root.join("index.html"), "home").unwrap();
+        fs::write(root.join("index.css"), "body {}").unwrap();
+        fs::write(root.join("about/page.html"), "about").unwrap();
+        fs::write(root.join("not_found.html"), "404").unwrap();
+
+        let routes = build_routes(String::from(""), &root).unwrap();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(
+            routes.list(),
+            vec![
+                ("/", root.join("index.html").as_path()),
+                ("/about", root.join("about/page.html").as_path()),
+                ("/index.css", root.join("index.css").as_path()),
+            ]
+        );
+        assert!(routes.contains("/about/"));
+        assert!(!routes.contains("/not_found.html"));
+    }
+
+    #[test]
+    fn routes_endpoint_is_opt_in() {
+        let mut routes = HashMap::new();
+        routes.insert(String::from("/howdy"), PathBuf::from("pages/howdy/page.html"));
+        let mut router = Router::new(routes);
+        router.set_fallback(|_request| HttpResponse::build(String::from("HTTP/1.1"), 404));
+        assert_eq!(router.handle(&get("/__routes")).status_code(), 404);
+
+        router.set_expose_routes(true);
+        let resp = router.handle(&get("/__routes"));
+        assert_eq!(resp.status_code(), 200);
+        assert_eq!(resp.try_get_body(), Some(b"/howdy -> pages/howdy/page.html\n".to_vec()));
+    }
+
+    #[test]
+    fn wildcard_route_serves_nested_files() {
+        let root = std::env::temp_dir().join(format!("rcomm_wildcard_{}", std::process::id()));
+        fs::create_dir_all(root.join("docs/a/b")).unwrap();
+        fs::write(root.join("docs/a/b/c.html"), "deep").unwrap();
+        fs::write(root.join("secret"), "hidden").unwrap();
+
+        let mut router = Router::new(HashMap::new());
+        router.set_fallback(|_request| HttpResponse::build(String::from("HTTP/1.1"), 404));
+        router.add_wildcard_route("/docs/*", root.join("docs")).unwrap();
+
+        let deep = router.handle(&get("/docs/a/b/c.html"));
+        let traversal = router.handle(&get("/docs/../secret"));
+        let missing = router.handle(&get("/docs/a/missing.html"));
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(deep.status_code(), 200);
+        assert_eq!(deep.try_get_body(), Some(b"deep".to_vec()));
+        assert_eq!(traversal.status_code(), 400);
+        assert_eq!(missing.status_code(), 404);
+    }
+
+    #[test]
+    fn wildcard_route_without_trailing_star_is_an_error() {
+        let mut router = Router::new(HashMap::new());
+        let err = router.add_wildcard_route("/docs", "docs").err();
+        assert_eq!(err, Some(RouteError::InvalidWildcard(String::from("/docs"))));
+        assert_eq!(err.unwrap().to_string(), "Wildcard route /docs must end with /*");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn wildcard_route_rejects_symlinks_out_of_directory() {
+        let root = std::env::temp_dir().join(format!("rcomm_wildcard_link_{}", std::process::id()));
+        fs::create_dir_all(root.join("docs")).unwrap();
+        fs::write(root.join("secret"), "hidden").unwrap();
+        std::os::unix::fs::symlink(root.join("secret"), root.join("docs/leak")).unwrap();
+
+        let mut router = Router::new(HashMap::new());
+        router.add_wildcard_route("/docs/*", root.join("docs")).unwrap();
+        let resp = router.handle(&get("/docs/leak"));
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(resp.status_code(), 403);
+    }
+
+    #[test]
+    fn wildcard_route_answers_only_get_and_head() {
+        let root = std::env::temp_dir().join(format!("rcomm_wildcard_methods_{}", std::process::id()));
+        fs::create_dir_all(root.join("docs")).unwrap();
+        fs::write(root.join("docs/guide.html"), "guide").unwrap();
+
+        let mut router = Router::new(HashMap::new());
+        router.add_wildcard_route("/docs/*", root.join("docs")).unwrap();
+        let request = |method: HttpMethods| {
+            router.handle(&HttpRequest::build(method, String::from("/docs/guide.html"), String::from("HTTP/1.1")))
+        };
+        let head = request(HttpMethods::HEAD);
+        let post = request(HttpMethods::POST);
+        let options = request(HttpMethods::OPTIONS);
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(head.status_code(), 200);
+        assert_eq!(post.status_code(), 405);
+        assert_eq!(post.try_get_header("allow".to_string()), Some(String::from("GET, HEAD, OPTIONS")));
+        assert_eq!(options.status_code(), 204);
+        assert_eq!(options.try_get_header("allow".to_string()), Some(String::from("GET, HEAD, OPTIONS")));
+        assert_eq!(
+            router.allowed_methods("/docs/a/b.html"),
+            Some(vec![HttpMethods::GET, HttpMethods::HEAD, HttpMethods::OPTIONS])
+        );
+        assert_eq!(router.allowed_methods("/docs"), None);
+    }
+
+    #[test]
+    fn wildcard_route_skips_hidden_and_unrouted_files() {
+        let root = std::env::temp_dir().join(format!("rcomm_wildcard_hidden_{}", std::process::id()));
+        fs::create_dir_all(root.join("docs/.git")).unwrap();
+        fs::write(root.join("docs/.env"), "SECRET=1").unwrap();
+        fs::write(root.join("docs/.git/config"), "[core]").unwrap();
+        fs::write(root.join("docs/guide.html"), "guide").unwrap();
+        fs::write(root.join("docs/guide.html.headers"), "X-Frame-Options: DENY").unwrap();
+        fs::write(root.join("docs/not_found.html"), "missing").unwrap();
+
+        let mut router = Router::new(HashMap::new());
+        router.set_fallback(|_request| HttpResponse::build(String::from("HTTP/1.1"), 404));
+        router.add_wildcard_route("/docs/*", root.join("docs")).unwrap();
+        let targets = ["/docs/.env", "/docs/.git/config", "/docs/guide.html.headers", "/docs/not_found.html", "/docs/guide.html"];
+        let statuses: Vec<u16> = targets
+            .iter()
+            .map(|target| router.handle(&get(target)).status_code())
+            .collect();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(statuses, [404, 404, 404, 404, 200]);
+    }
+
+    #[test]
+    fn missing_not_found_page_falls_back_to_builtin_html() {
+        let page = std::env::temp_dir()
+            .join(format!("rcomm_missing_not_found_{}.html", std::process::id()));
+        let mut router = Router::new(HashMap::new());
+        router.set_not_found_page(&page);
+
+        let resp = router.handle(&get("/missing"));
+        assert_eq!(resp.status_code(), 404);
+        assert_eq!(resp.try_get_header("content-type".to_string()), Some(get_mime_type("html").to_string()));
+        assert_eq!(resp.try_get_body(), Some(BUILTIN_NOT_FOUND_HTML.as_bytes().to_vec()));
+    }
+
+    #[test]
+    fn headers_sidecar_applies_to_served_file() {
+        let root = std::env::temp_dir().join(format!("rcomm_headers_sidecar_{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("app.js"), "run();").unwrap();
+        fs::write(root.join("app.js.headers"), "Cache-Control: max-age=3600\nX-Frame-Options: DENY\n").unwrap();
+
+        let routes = build_routes(String::from(""), &root).unwrap();
+        let router = Router::new(routes);
+        let resp = router.handle(&get("/app.js"));
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(resp.status_code(), 200);
+        assert_eq!(resp.try_get_header("cache-control".to_string()), Some("max-age=3600".to_string()));
+        assert_eq!(resp.try_get_header("x-frame-options".to_string()), Some("DENY".to_string()));
+        assert_eq!(router.routes().list().len(), 1);
+    }
+
+    #[test]
+    fn replaced_routes_are_seen_by_later_requests() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        let table = |body: &str| {
+            Routes::from_virtual_files(vec![
+                (String::from("a.txt"), body.as_bytes().to_vec()),
+                (String::from("b.txt"), body.as_bytes().to_vec()),
+            ])
+        };
+        let router = Router::new(table("old"));
+        let swapped = AtomicBool::new(false);
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    for _ in 0..200 {
+                        let seen_swap = swapped.load(Ordering::SeqCst);
+                        let body = router.handle(&get("/a.txt")).try_get_body().unwrap();
+                        if seen_swap {
+                            assert_eq!(body, b"new");
+                        } else {
+                            assert!(body == b"old" || body == b"new");
+                        }
+                    }
+                });
+            }
+            router.replace_routes(table("new"));
+            swapped.store(true, Ordering::SeqCst);
+        });
+        assert_eq!(router.handle(&get("/b.txt")).try_get_body(), Some(b"new".to_vec()));
+    }
+
+    #[test]
+    fn requests_share_the_route_table_instead_of_copying_it() {
+        let routes: HashMap<String, PathBuf> =
+            (0..10_000).map(|i| (format!("/page{i}"), PathBuf::from(format!("page{i}.html")))).collect();
+        let router = Router::new(routes);
+        // Every request takes the same table; only the Arc count changes.
+        let snapshot = router.routes();
+        assert!(Arc::ptr_eq(&snapshot, &router.routes()));
+        assert_eq!(Arc::strong_count(&snapshot), 2);
+        assert_eq!(snapshot.list().len(), 10_000);
+    }
+
+    #[test]
+    fn overlay_prefers_earlier_roots() {
+        let base = std::env::temp_dir().join(format!("rcomm_overlay_a_{}", std::process::id()));
+        let tenant = std::env::temp_dir().join(format!("rcomm_overlay_b_{}", std::process::id()));
+        fs::create_dir_all(&base).unwrap();
+        fs::create_dir_all(&tenant).unwrap();
+        fs::write(base.join("index.html"), "base").unwrap();
+        fs::write(base.join("index.css"), "base css").unwrap();
+        fs::write(base.join("index.css.headers"), "Cache-Control: max-age=60").unwrap();
+        fs::write(tenant.join("index.css"), "tenant css").unwrap();
+
+        let routes = build_routes_overlay(&[tenant.clone(), base.clone()]).unwrap();
+        fs::remove_dir_all(&base).unwrap();
+        fs::remove_dir_all(&tenant).unwrap();
+
+        assert_eq!(routes.get("/index.css"), Some(&tenant.join("index.css")));
+        assert_eq!(routes.get("/"), Some(&base.join("index.html")));
+        assert!(routes.headers_for("/index.css").is_empty());
+    }
+}
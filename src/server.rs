@@ -0,0 +1,1581 @@
+use std::{
+    collections::HashMap,
+    io::{self, BufReader, BufWriter, prelude::*},
+    panic::{self, AssertUnwindSafe},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Weak,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+use crate::{PoolMetrics, ShutdownReport, ThreadPool, TrafficCounters};
+use crate::config::ServerConfig;
+use crate::access_log::{AccessLog, AccessLogEntry};
+use crate::compression::Compression;
+use crate::gzip::{COMPRESSION_LEVELS, DEFAULT_COMPRESSION_LEVEL};
+use crate::cors::CorsConfig;
+use crate::forwarded::{Cidr, client_ip};
+use crate::logger::{Logger, StderrLogger};
+use crate::maintenance::{HEALTH_PATH, Maintenance, health_response};
+use crate::metrics::{ResponseStats, render_metrics};
+use crate::models::{
+    http_response::{HttpResponse, RetryWriter},
+    http_date::format_rfc3339,
+    http_methods::HttpMethods,
+    http_range::{ByteRange, parse_byte_range},
+    mime_types::default_cache_control,
+    http_request::{HttpParseError, HttpRequest, ParseOptions, parse_http_version},
+};
+use crate::proxy::Proxy;
+use crate::router::{DEFAULT_NOT_FOUND_PAGE, Router, Routes, validate_routes};
+use crate::template::Templates;
+use crate::watch::RouteWatcher;
+
+/// A running server. Dropping the handle leaves the server running; call
+/// `shutdown` to stop it.
+pub struct ServerHandle {
+    local_addrs: Vec<SocketAddr>,
+    accept_threads: Vec<thread::JoinHandle<()>>,
+    pool: Arc<ThreadPool>,
+    context: Arc<ServerContext>,
+    shutdown_grace: Duration,
+}
+
+impl ServerHandle {
+    /// The addresses actually bound, with any `0` port resolved.
+    pub fn local_addrs(&self) -> &[SocketAddr] {
+        &self.local_addrs
+    }
+
+    pub fn metrics(&self) -> PoolMetrics {
+        self.pool.metrics()
+    }
+
+    /// Responses sent so far, by status code.
+    pub fn response_stats(&self) -> &ResponseStats {
+        &self.context.responses
+    }
+
+    /// Stops accepting connections, then gives in-flight requests up to the
+    /// configured grace period to finish.
+    pub fn shutdown(self) -> ShutdownReport {
+        self.context.logger.info("Shutdown requested; no longer accepting connections");
+        self.context.draining.store(true, Ordering::SeqCst);
+        for address in &self.local_addrs {
+            wake_listener(*address);
+        }
+        for thread in self.accept_threads {
+            let _ = thread.join();
+        }
+
+        let pool = Arc::into_inner(self.pool).expect("accept threads have exited");
+        let metrics = pool.metrics();
+        let report = pool.shutdown_timeout(self.shutdown_grace);
+        self.context.logger.info(&format!(
+            "Shutdown complete: {} jobs completed, {} abandoned, {} bytes in, {} bytes out",
+            report.completed, report.abandoned, metrics.bytes_in, metrics.bytes_out
+        ));
+        report
+    }
+}
+
+/// Binds every configured address, builds the route table and starts one
+/// accept thread per listener, all feeding a shared worker pool. Addresses
+/// that fail to bind are logged; it is an error only if none bind.
+pub fn serve(mut config: ServerConfig) -> io::Result<ServerHandle> {
+    let logger: Arc<dyn Logger> = Arc::new(StderrLogger::new(config.log_level));
+
+    let mut listeners = Vec::new();
+    let mut bind_error = None;
+    for full_address in bind_addresses(&config.address, &config.port.to_string()) {
+        match bind_listener(&full_address, config.backlog) {
+            Ok(listener) => listeners.push(listener),
+            Err(e) => {
+                logger.error(&format!("Failed to bind {full_address}: {e}"));
+                bind_error = Some(e);
+            }
+        }
+    }
+    if config.backlog.is_some() && !cfg!(feature = "backlog") {
+        logger.warn("RCOMM_BACKLOG needs the `backlog` feature; using the OS default");
+    }
+    if listeners.is_empty() {
+        return Err(bind_error.unwrap_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no address to bind")));
+    }
+    let local_addrs = listeners.iter().map(TcpListener::local_addr).collect::<io::Result<Vec<_>>>()?;
+
+    let not_found_page = config.not_found_page.take().unwrap_or_else(|| default_not_found_page(&config.roots));
+    #[cfg(feature = "embed")]
+    let routes = crate::embed::routes();
+    #[cfg(not(feature = "embed"))]
+    let routes = crate::router::build_routes_overlay(&config.roots)?;
+
+    logger.debug(&format!("Routes:\n{routes:#?}"));
+    // Embedded routes can't go missing, and the roots on disk aren't used.
+    if config.check_routes && !cfg!(feature = "embed") {
+        log_route_warnings(&routes, &not_found_page, logger.as_ref());
+    }
+    for address in &local_addrs {
+        logger.info(&format!("Listening on {address}"));
+    }
+
+    if config.compression && !COMPRESSION_LEVELS.contains(&config.compression_level) {
+        logger.warn(&format!(
+            "RCOMM_COMPRESSION_LEVEL {} is outside 1-9; using {DEFAULT_COMPRESSION_LEVEL}",
+            config.compression_level
+        ));
+        config.compression_level = DEFAULT_COMPRESSION_LEVEL;
+    }
+    #[cfg(feature = "embed")]
+    let embedded_not_found = crate::embed::file("not_found.html");
+    #[cfg(not(feature = "embed"))]
+    let embedded_not_found = None;
+    let site = Site {
+        router: configured_router(routes, not_found_page, embedded_not_found, &config, &logger),
+        maintenance: Maintenance::new(config.maintenance, &config.roots, config.maintenance_retry_after),
+    };
+    let mut vhosts = HashMap::new();
+    for (host, root) in &config.vhosts {
+        let roots = std::slice::from_ref(root);
+        let routes = crate::router::build_routes_overlay(roots)?;
+        let not_found_page = default_not_found_page(roots);
+        if config.check_routes {
+            log_route_warnings(&routes, &not_found_page, logger.as_ref());
+        }
+        logger.info(&format!("Serving {host} from {}", root.display()));
+        vhosts.insert(host.clone(), Site {
+            router: configured_router(routes, not_found_page, None, &config, &logger),
+            maintenance: Maintenance::new(config.maintenance, roots, config.maintenance_retry_after),
+        });
+    }
+
+    let pool = Arc::new(ThreadPool::new_with_logger(config.workers, Arc::clone(&logger)));
+    let context = Arc::new(ServerContext {
+        site,
+        vhosts,
+        strict_vhosts: config.strict_vhosts,
+        logger,
+        access_log: AccessLog::stdout(config.log_format),
+        cors: (!config.cors_origins.is_empty()).then(|| CorsConfig::new(config.cors_origins)),
+        keepalive_timeout: config.keepalive_timeout,
+        max_requests_per_conn: config.max_requests_per_conn,
+        request_timeout: config.request_timeout,
+        reject_unexpected_bodies: config.reject_unexpected_bodies,
+        parse_options: ParseOptions {
+            upload_routes: config.upload_routes,
+            upload_dir: config.upload_dir,
+            max_body_len: config.max_body,
+            max_request_line_len: config.max_request_line,
+            max_header_line_len: config.max_header_line,
+            header_deadline: Some(config.header_timeout),
+            ..ParseOptions::default()
+        },
+        cache_control: config.cache_control,
+        trusted_proxies: config.trusted_proxies,
+        traffic: pool.traffic(),
+        responses: ResponseStats::default(),
+        expose_metrics: config.expose_metrics,
+        read_buffer: config.read_buffer,
+        write_buffer: config.write_buffer,
+        spill_threshold: config.spill_threshold,
+        debug_errors: config.debug_errors,
+        debug_connections: config.debug_connections,
+        draining: AtomicBool::new(false),
+    });
+
+    if let Some(interval) = config.watch_interval {
+        if cfg!(feature = "embed") {
+            context.logger.warn("RCOMM_WATCH_INTERVAL has no effect on embedded routes");
+        } else {
+            spawn_route_watcher(None, config.roots, interval, Arc::downgrade(&context));
+        }
+        for (host, root) in config.vhosts {
+            spawn_route_watcher(Some(host), vec![root], interval, Arc::downgrade(&context));
+        }
+    }
+
+    let accept_threads = listeners
+        .into_iter()
+        .map(|listener| {
+            let pool = Arc::clone(&pool);
+            let context = Arc::clone(&context);
+            thread::spawn(move || loop {
+                let stream = accept_next(&listener, context.logger.as_ref(), ACCEPT_BACKOFF);
+                if context.draining.load(Ordering::SeqCst) {
+                    break;
+                }
+                let context = Arc::clone(&context);
+
+                pool.execute(move || {
+                    handle_connection(stream, &context);
+                });
+            })
+        })
+        .collect();
+
+    Ok(ServerHandle {
+        local_addrs,
+        accept_threads,
+        pool,
+        context,
+        shutdown_grace: config.shutdown_grace,
+    })
+}
+
+/// Builds a router over `routes` with the routing options and middleware
+/// from `config`. Every virtual host gets its own. `embedded_not_found`
+/// replaces the 404 page on disk; a proxy upstream replaces both.
+fn configured_router(
+    routes: Routes,
+    not_found_page: PathBuf,
+    embedded_not_found: Option<&[u8]>,
+    config: &ServerConfig,
+    logger: &Arc<dyn Logger>,
+) -> Router {
+    let mut router = Router::new(routes);
+    router.set_logger(Arc::clone(logger));
+    match embedded_not_found {
+        Some(page) => router.set_not_found_contents(page.to_vec()),
+        None => router.set_not_found_page(not_found_page),
+    };
+    if let Some(upstream) = &config.proxy_upstream {
+        let mut proxy = Proxy::new(upstream.as_str());
+        proxy.set_timeout(config.proxy_timeout);
+        router.set_fallback(move |request| proxy.forward(request));
+    }
+    router.set_trailing_slash(config.trailing_slash);
+    if let Some(prefix) = &config.path_prefix {
+        router.set_path_prefix(prefix, config.require_path_prefix);
+    }
+    router.set_expose_routes(config.expose_routes);
+    router.set_writable(config.writable);
+    // Registered first so it wraps the template renderer and compresses its output.
+    if config.compression {
+        let mut compression = Compression::new();
+        compression.set_threshold(config.compression_threshold);
+        compression.set_cache_budget(config.compression_cache);
+        compression.set_level(config.compression_level);
+        router.add_middleware(compression);
+    }
+    if let Some(unknown) = config.templates {
+        let mut templates = Templates::new();
+        templates.set_unknown(unknown);
+        templates.register("year", |_request| format_rfc3339(SystemTime::now())[..4].to_string());
+        router.add_middleware(templates);
+    }
+    router
+}
+
+fn log_route_warnings(routes: &Routes, not_found_page: &Path, logger: &dyn Logger) {
+    for warning in validate_routes(routes, not_found_page) {
+        if warning.is_error() {
+            logger.error(&warning.to_string());
+        } else {
+            logger.warn(&warning.to_string());
+        }
+    }
+}
+
+/// Polls the document roots every `interval`, swapping rebuilt routes into
+/// the router for `host` (the default router for `None`), until the server
+/// is dropped or starts shutting down.
+fn spawn_route_watcher(host: Option<String>, roots: Vec<PathBuf>, interval: Duration, context: Weak<ServerContext>) {
+    let mut watcher = RouteWatcher::new(roots);
+    thread::spawn(move || loop {
+        thread::sleep(interval);
+        let Some(context) = context.upgrade() else { return; };
+        if context.draining.load(Ordering::SeqCst) {
+            return;
+        }
+        match watcher.poll() {
+            Some(Ok(routes)) => {
+                context.logger.info(&format!("Document root changed; reloaded {} routes", routes.list().len()));
+                let site = host.as_ref().and_then(|host| context.vhosts.get(host)).unwrap_or(&context.site);
+                site.router.replace_routes(routes);
+            }
+            // Keep the old routes; the rebuild is retried on the next poll.
+            Some(Err(e)) => context.logger.error(&format!("Document root changed but routes could not be rebuilt: {e}")),
+            None => {}
+        }
+    });
+}
+
+/// Binds `address` (`host:port`), with an explicit listen `backlog` when one
+/// is given.
+#[cfg(feature = "backlog")]
+fn bind_listener(address: &str, backlog: Option<u32>) -> io::Result<TcpListener> {
+    use socket2::{Domain, Protocol, Socket, Type};
+    use std::net::ToSocketAddrs;
+
+    let Some(backlog) = backlog else { return TcpListener::bind(address) };
+    let mut last_error = None;
+    for address in address.to_socket_addrs()? {
+        let bound = Socket::new(Domain::for_address(address), Type::STREAM, Some(Protocol::TCP)).and_then(|socket| {
+            // Match std, which sets SO_REUSEADDR on Unix.
+            #[cfg(unix)]
+            socket.set_reuse_address(true)?;
+            socket.bind(&address.into())?;
+            socket.listen(backlog as i32)?;
+            Ok(socket)
+        });
+        match bound {
+            Ok(socket) => return Ok(socket.into()),
+            Err(e) => last_error = Some(e),
+        }
+    }
+    Err(last_error.unwrap_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "address resolved to nothing")))
+}
+
+/// Binds `address` (`host:port`) with the OS default backlog.
+#[cfg(not(feature = "backlog"))]
+fn bind_listener(address: &str, _backlog: Option<u32>) -> io::Result<TcpListener> {
+    TcpListener::bind(address)
+}
+
+/// The first `not_found.html` found across the document roots.
+fn default_not_found_page(roots: &[PathBuf]) -> PathBuf {
+    roots
+        .iter()
+        .map(|root| root.join("not_found.html"))
+        .find(|page| page.is_file())
+        .or_else(|| roots.first().map(|root| root.join("not_found.html")))
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_NOT_FOUND_PAGE))
+}
+
+/// One site's router and the maintenance switch for its own roots.
+struct Site {
+    router: Router,
+    maintenance: Maintenance,
+}
+
+struct ServerContext {
+    /// Serves requests whose `Host` isn't in `vhosts`.
+    site: Site,
+    /// Per-hostname sites, keyed by normalized host.
+    vhosts: HashMap<String, Site>,
+    /// Answer hosts missing from `vhosts` with `421` instead of `site`.
+    strict_vhosts: bool,
+    logger: Arc<dyn Logger>,
+    access_log: AccessLog,
+    cors: Option<CorsConfig>,
+    /// How long a persistent connection may sit idle between requests.
+    keepalive_timeout: Duration,
+    /// Requests served on one connection before it is closed.
+    max_requests_per_conn: usize,
+    /// How long a single read may stall once a request has started arriving.
+    request_timeout: Duration,
+    /// Answer GET/HEAD/DELETE requests that carry a body with 400.
+    reject_unexpected_bodies: bool,
+    parse_options: ParseOptions,
+    /// Add `default_cache_control` headers to successful responses.
+    cache_control: bool,
+    /// Peers allowed to report the client address via `X-Forwarded-For`.
+    trusted_proxies: Vec<Cidr>,
+    /// Byte totals for the pool's metrics.
+    traffic: Arc<TrafficCounters>,
+    responses: ResponseStats,
+    /// Serve `render_metrics` at `/metrics`.
+    expose_metrics: bool,
+    /// Capacity of each connection's `BufReader`.
+    read_buffer: usize,
+    /// Capacity of the `BufWriter` each response is written through.
+    write_buffer: usize,
+    /// Spill threshold for responses that didn't set their own.
+    spill_threshold: usize,
+    /// Show panic messages in 500 responses instead of only logging them.
+    debug_errors: bool,
+    /// Number each response on a connection with `X-Conn-Requests`.
+    debug_connections: bool,
+    /// Set once shutdown starts: stop accepting and stop reusing connections.
+    draining: AtomicBool,
+}
+
+
+/// Unblocks a thread parked in `accept` on the listener bound to `address`
+/// by connecting to it.
+fn wake_listener(mut address: SocketAddr) {
+    if address.ip().is_unspecified() {
+        let loopback = match address.ip() {
+            IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::LOCALHOST),
+            IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::LOCALHOST),
+        };
+        address.set_ip(loopback);
+    }
+    let _ = TcpStream::connect_timeout(&address, Duration::from_secs(1));
+}
+
+/// Expands a comma-separated `RCOMM_ADDRESS` into `host:port` bind strings.
+/// IPv6 literals may be given with or without brackets (`[::1]` or `::1`).
+fn bind_addresses(addresses: &str, port: &str) -> Vec<String> {
+    addresses
+        .split(',')
+        .map(str::trim)
+        .filter(|address| !address.is_empty())
+        .map(|address| {
+            if address.contains(':') && !address.starts_with('[') {
+                format!("[{address}]:{port}")
+            } else {
+                format!("{address}:{port}")
+            }
+        })
+        .collect()
+}
+
+/// How long to pause accepting once the process runs out of descriptors.
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+/// A source of incoming connections, so the accept loop can be exercised
+/// without a real listener.
+trait Acceptor {
+    type Stream;
+    fn accept_stream(&self) -> io::Result<Self::Stream>;
+}
+
+impl Acceptor for TcpListener {
+    type Stream = TcpStream;
+
+    fn accept_stream(&self) -> io::Result<TcpStream> {
+        self.accept().map(|(stream, _)| stream)
+    }
+}
+
+/// Blocks until `acceptor` yields a connection. Accept errors are logged and
+/// retried after a pause: `backoff` when descriptors are exhausted, so
+/// in-flight connections get a chance to close, and a tenth of it for any
+/// other error, so a persistent failure can't spin the thread.
+fn accept_next<A: Acceptor>(acceptor: &A, logger: &dyn Logger, backoff: Duration) -> A::Stream {
+    loop {
+        match acceptor.accept_stream() {
+            Ok(stream) => return stream,
+            Err(e) if is_fd_exhaustion(&e) => {
+                logger.error(&format!("Failed to accept connection: {e}; backing off"));
+                std::thread::sleep(backoff);
+            }
+            Err(e) => {
+                logger.warn(&format!("Failed to accept connection: {e}"));
+                std::thread::sleep(backoff / 10);
+            }
+        }
+    }
+}
+
+/// Whether `error` means the process or the system ran out of file
+/// descriptors (`EMFILE`/`ENFILE`). Always `false` off unix.
+#[cfg(unix)]
+fn is_fd_exhaustion(error: &io::Error) -> bool {
+    matches!(error.raw_os_error(), Some(libc::ENFILE | libc::EMFILE))
+}
+
+#[cfg(not(unix))]
+fn is_fd_exhaustion(_error: &io::Error) -> bool {
+    false
+}
+
+fn handle_connection(stream: TcpStream, context: &ServerContext) {
+    let mut reader = BufReader::with_capacity(context.read_buffer, Metered::new(&stream, &context.traffic));
+    let output = || Metered::new(&stream, &context.traffic);
+    let peer = stream.peer_addr().map(|addr| addr.ip()).ok();
+    let mut served = 0;
+
+    loop {
+        // Wait for the first byte of the next request under the idle timeout.
+        // A clean close or an expired timeout ends the connection silently.
+        let _ = stream.set_read_timeout(Some(context.keepalive_timeout));
+        match reader.fill_buf() {
+            Ok(buf) if !buf.is_empty() => {}
+            _ => return,
+        }
+        let _ = stream.set_read_timeout(Some(context.request_timeout));
+        let started = Instant::now();
+
+        let mut http_request = match HttpRequest::build_from_connection(&mut reader, &mut output(), &context.parse_options) {
+            Ok(req) => req,
+            Err(HttpParseError::ConnectionClosed) => return,
+            Err(e) => {
+                context.logger.warn(&format!("Bad request from {}: {e}", display_addr(peer)));
+                let mut response = HttpResponse::from_parse_error("HTTP/1.1", &e);
+                context.responses.record(response.status_code());
+                let sent = send_response(output(), &mut response, false, context);
+                log_access(context, None, peer, &response, sent.is_ok(), started);
+                return;
+            }
+        };
+
+        if let Some(peer) = peer {
+            let forwarded_for = http_request.try_get_header("X-Forwarded-For".to_string());
+            http_request.set_remote_addr(client_ip(peer, forwarded_for.as_deref(), &context.trusted_proxies));
+        }
+        let remote = display_addr(http_request.remote_addr());
+        crate::set_job_context(format!("{} {} from {remote}", http_request.method, http_request.target));
+
+        if context.reject_unexpected_bodies && has_unexpected_body(&http_request) {
+            let reason = format!("{} request must not carry a body", http_request.method);
+            context.logger.warn(&format!("Bad request from {remote}: {reason}"));
+            let mut response = bad_request(&reason);
+            context.responses.record(response.status_code());
+            let sent = send_response(output(), &mut response, false, context);
+            log_access(context, Some(&http_request), peer, &response, sent.is_ok(), started);
+            return;
+        }
+
+        context.logger.debug(&format!("Request from {remote}: {http_request}"));
+        if let Some(protocols) = requested_upgrade(&http_request) {
+            context.logger.debug(&format!("Declining Upgrade: {protocols} from {remote}; staying on HTTP/1.1"));
+        }
+
+        let site = select_site(context, &http_request);
+        // A panicking handler gets the client a 500 rather than a dropped connection.
+        let routed = panic::catch_unwind(AssertUnwindSafe(|| match (site, &context.cors) {
+            _ if http_request.normalized_path() == HEALTH_PATH => health_response(),
+            (Some(site), _) if site.maintenance.is_active() => site.maintenance.response(),
+            _ if is_unimplemented(&http_request.method) => not_implemented(&http_request.method),
+            _ if context.expose_metrics && http_request.normalized_path() == "/metrics" => metrics_response(context),
+            (None, _) => misdirected_request(),
+            (Some(_), Some(cors)) if CorsConfig::is_preflight(&http_request) => cors.preflight_response(&http_request),
+            (Some(site), Some(cors)) => {
+                let mut response = site.router.handle(&http_request);
+                cors.apply(&http_request, &mut response);
+                response
+            }
+            (Some(site), None) => site.router.handle(&http_request),
+        }));
+        let mut response = routed.unwrap_or_else(|payload| {
+            let message = crate::panic_message(payload.as_ref());
+            context.logger.error(&format!(
+                "Handler panicked while handling {} {} from {remote}: {message}",
+                http_request.method, http_request.target
+            ));
+            internal_error(context.debug_errors.then_some(message))
+        });
+
+        if context.cache_control {
+            apply_default_cache_control(&mut response);
+        }
+        apply_range(&http_request, &mut response);
+
+        served += 1;
+        if context.debug_connections {
+            response.add_header("X-Conn-Requests".to_string(), served.to_string());
+        }
+        // HTTP/1.0 has no chunked encoding, so a stream ends with the connection.
+        let close_delimited = response.is_streamed() && parse_http_version(&http_request.version) == Some((1, 0));
+        let keep_alive = wants_keep_alive(&http_request)
+            && !close_delimited
+            && !context.draining.load(Ordering::SeqCst)
+            && served < context.max_requests_per_conn;
+        if close_delimited {
+            response.close_delimit();
+        } else {
+            let connection = if keep_alive { "keep-alive" } else { "close" };
+            response.add_header("Connection".to_string(), connection.to_string());
+        }
+
+        context.logger.debug(&format!("Response: {response}"));
+        let head_only = http_request.method == HttpMethods::HEAD;
+        context.responses.record(response.status_code());
+        let sent = send_response(output(), &mut response, head_only, context);
+        log_access(context, Some(&http_request), peer, &response, sent.is_ok() && !head_only, started);
+        if let Err(e) = sent {
+            context.logger.warn(&format!("Failed to write response: {e}"));
+            return;
+        }
+
+        if !keep_alive {
+            return;
+        }
+    }
+}
+
+/// The site for the request's `Host`: its virtual host, else the default
+/// site, or `None` when strict virtual hosting refuses unknown hosts.
+fn select_site<'a>(context: &'a ServerContext, request: &HttpRequest) -> Option<&'a Site> {
+    match request.host().and_then(|host| context.vhosts.get(&host)) {
+        Some(site) => Some(site),
+        None if context.strict_vhosts && !context.vhosts.is_empty() => None,
+        None => Some(&context.site),
+    }
+}
+
+/// Wraps a connection so every byte read or written is added to `traffic`.
+struct Metered<'a, S> {
+    inner: S,
+    traffic: &'a TrafficCounters,
+}
+
+impl<'a, S> Metered<'a, S> {
+    fn new(inner: S, traffic: &'a TrafficCounters) -> Self {
+        Metered { inner, traffic }
+    }
+}
+
+impl<S: Read> Read for Metered<'_, S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.traffic.add_in(n as u64);
+        Ok(n)
+    }
+}
+
+impl<S: Write> Write for Metered<'_, S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.traffic.add_out(n as u64);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Writes the access log line for `response`. A request that couldn't be
+/// parsed is logged as `-`, and a body that wasn't fully sent as 0 bytes.
+fn log_access(
+    context: &ServerContext,
+    request: Option<&HttpRequest>,
+    peer: Option<IpAddr>,
+    response: &HttpResponse,
+    body_sent: bool,
+    started: Instant,
+) {
+    let method = request.map_or_else(|| String::from("-"), |request| request.method.to_string());
+    let path = request.map_or_else(|| String::from("-"), logged_path);
+    context.access_log.log(&AccessLogEntry {
+        time: SystemTime::now(),
+        method: &method,
+        path: &path,
+        version: request.map_or("-", |request| &request.version),
+        status: response.status_code(),
+        bytes: if body_sent { response.body_len() } else { 0 },
+        duration: started.elapsed(),
+        remote: request.and_then(HttpRequest::remote_addr).or(peer),
+        request_id: &next_request_id(),
+    });
+}
+
+/// The normalized path, plus the query string when there is one.
+fn logged_path(request: &HttpRequest) -> String {
+    match request.query() {
+        Some(query) => format!("{}?{query}", request.normalized_path()),
+        None => request.normalized_path(),
+    }
+}
+
+/// Process-unique id tying an access log line to its request.
+fn next_request_id() -> String {
+    static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+    format!("{:08x}", NEXT_ID.fetch_add(1, Ordering::Relaxed))
+}
+
+fn display_addr(addr: Option<IpAddr>) -> String {
+    addr.map_or_else(|| String::from("unknown"), |addr| addr.to_string())
+}
+
+fn metrics_response(context: &ServerContext) -> HttpResponse {
+    let mut response = HttpResponse::build(String::from("HTTP/1.1"), 200);
+    response.add_header("Content-Type".to_string(), "text/plain; version=0.0.4".to_string());
+    response.add_header("Cache-Control".to_string(), "no-store".to_string());
+    response.add_body(render_metrics(&context.responses, &context.traffic).into());
+    response
+}
+
+/// The protocols a request asks to switch to. Upgrading is optional for the
+/// server (RFC 9110 §7.8), and none are supported, so the request is simply
+/// answered over HTTP/1.1 without a `101`.
+fn requested_upgrade(request: &HttpRequest) -> Option<String> {
+    let connection = request.try_get_header("Connection".to_string())?;
+    if !connection.split(',').any(|token| token.trim().eq_ignore_ascii_case("upgrade")) {
+        return None;
+    }
+    request.try_get_header("Upgrade".to_string())
+}
+
+/// GET, HEAD and DELETE have no defined body semantics; one that declares a
+/// body anyway is more likely a request-smuggling attempt than a real client.
+fn has_unexpected_body(request: &HttpRequest) -> bool {
+    matches!(request.method, HttpMethods::GET | HttpMethods::HEAD | HttpMethods::DELETE)
+        && request.declares_body()
+}
+
+/// Methods the server has no support for at all, as opposed to ones a
+/// route merely doesn't allow: CONNECT needs tunnelling and TRACE would
+/// echo credentials back.
+fn is_unimplemented(method: &HttpMethods) -> bool {
+    matches!(method, HttpMethods::CONNECT | HttpMethods::TRACE)
+}
+
+/// Builds the `421` for a `Host` this server isn't configured to serve.
+fn misdirected_request() -> HttpResponse {
+    let mut response = HttpResponse::build(String::from("HTTP/1.1"), 421);
+    response.add_text_body("421 Misdirected Request: unknown host");
+    response
+}
+
+fn not_implemented(method: &HttpMethods) -> HttpResponse {
+    let mut response = HttpResponse::build(String::from("HTTP/1.1"), 501);
+    response.add_body(format!("Not Implemented: {method}").into());
+    response
+}
+
+/// Builds the `500` sent when a handler panics, with the panic message only
+/// when `detail` is given (`RCOMM_DEBUG_ERRORS`).
+fn internal_error(detail: Option<&str>) -> HttpResponse {
+    let mut response = HttpResponse::build(String::from("HTTP/1.1"), 500);
+    let body = match detail {
+        Some(detail) => format!("500 Internal Server Error: {detail}"),
+        None => String::from("500 Internal Server Error"),
+    };
+    response.add_text_body(body);
+    response
+}
+
+/// Builds the `400 Bad Request` sent before dropping a connection.
+fn bad_request(reason: &str) -> HttpResponse {
+    let mut response = HttpResponse::build(String::from("HTTP/1.1"), 400);
+    response.add_header("Connection".to_string(), "close".to_string());
+    response.add_body(format!("Bad Request: {reason}").into());
+    response
+}
+
+/// Adds a `Cache-Control` for the response's content type to 200 responses
+/// that don't already carry one (e.g. from a `.headers` sidecar).
+fn apply_default_cache_control(response: &mut HttpResponse) {
+    if response.status_code() != 200 || response.try_get_header("Cache-Control".to_string()).is_some() {
+        return;
+    }
+    let content_type = response.try_get_header("Content-Type".to_string()).unwrap_or_default();
+    if let Some(cache_control) = default_cache_control(&content_type) {
+        response.add_header("Cache-Control".to_string(), cache_control.to_string());
+    }
+}
+
+/// Serves the part of a 200 response a `Range` header asks for, as `206`
+/// or `416`. Only responses advertising `Accept-Ranges: bytes` (static
+/// files) are ranged; other 200s are marked `Accept-Ranges: none`.
+/// Conditional `If-Range` requests get the full body.
+fn apply_range(request: &HttpRequest, response: &mut HttpResponse) {
+    if response.status_code() != 200 {
+        return;
+    }
+    if response.try_get_header("Accept-Ranges".to_string()).as_deref() != Some("bytes") {
+        response.add_header("Accept-Ranges".to_string(), "none".to_string());
+        return;
+    }
+    if request.method != HttpMethods::GET || request.try_get_header("If-Range".to_string()).is_some() {
+        return;
+    }
+    let Some(header) = request.try_get_header("Range".to_string()) else { return; };
+    let Some(body) = response.try_get_body() else { return; };
+
+    match parse_byte_range(&header, body.len()) {
+        Some(ByteRange::Satisfiable { start, end }) => {
+            response.set_status_code(206);
+            response.add_header("Content-Range".to_string(), format!("bytes {start}-{end}/{}", body.len()));
+            response.add_body(body[start..=end].to_vec());
+        }
+        Some(ByteRange::Unsatisfiable) => {
+            response.set_status_code(416);
+            response.add_header("Content-Range".to_string(), format!("bytes */{}", body.len()));
+            response.add_body(Vec::new());
+        }
+        None => {}
+    }
+}
+
+/// Decides whether the connection may be reused after answering `request`:
+/// HTTP/1.1 persists unless the client sends `Connection: close`, HTTP/1.0
+/// only with an explicit `Connection: keep-alive`. Requests framed with
+/// `Transfer-Encoding` are never reused since their body isn't consumed.
+fn wants_keep_alive(request: &HttpRequest) -> bool {
+    if request.try_get_header("Transfer-Encoding".to_string()).is_some() {
+        return false;
+    }
+    let connection = request
+        .try_get_header("Connection".to_string())
+        .unwrap_or_default()
+        .to_lowercase();
+    let has_token = |token: &str| connection.split(',').any(|t| t.trim() == token);
+
+    match parse_http_version(&request.version) {
+        Some((1, 1)) => !has_token("close"),
+        Some((1, 0)) => has_token("keep-alive"),
+        _ => false,
+    }
+}
+
+/// Writes `response` through a `BufWriter` so the head and body are
+/// coalesced into as few writes on `stream` as possible, flushing once at
+/// the end. With `head_only` (HEAD requests) the body is left out while its
+/// `Content-Length` is kept. A response without its own spill threshold
+/// gets the server's first.
+fn send_response<W: Write>(stream: W, response: &mut HttpResponse, head_only: bool, context: &ServerContext) -> io::Result<()> {
+    if response.spill_threshold().is_none() {
+        response.set_spill_threshold(context.spill_threshold);
+    }
+    let mut writer = BufWriter::with_capacity(context.write_buffer, RetryWriter::new(stream));
+    if head_only {
+        write!(writer, "{response}")?;
+    } else {
+        response.write_to(&mut writer)?;
+    }
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::access_log::LogFormat;
+    use crate::config::DEFAULT_BUFFER_SIZE;
+    use crate::models::http_response::DEFAULT_SPILL_THRESHOLD;
+    use crate::logger::LogLevel;
+    use std::sync::Mutex;
+
+    struct CountingWriter {
+        writes: usize,
+        bytes: Vec<u8>,
+    }
+
+    impl Write for CountingWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.writes += 1;
+            self.bytes.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn send_response_uses_single_write_for_typical_response() {
+        let mut response = HttpResponse::build(String::from("HTTP/1.1"), 200);
+        response.add_header("Content-Type".to_string(), "text/html".to_string());
+        response.add_body(b"<h1>Hello</h1>".to_vec());
+
+        let mut counter = CountingWriter { writes: 0, bytes: Vec::new() };
+        send_response(&mut counter, &mut response, false, &test_context(Arc::new(TrafficCounters::default()))).unwrap();
+
+        assert_eq!(counter.writes, 1);
+        assert_eq!(counter.bytes, response.as_bytes());
+    }
+
+    #[test]
+    fn send_response_head_only_omits_body() {
+        let mut response = HttpResponse::build(String::from("HTTP/1.1"), 200);
+        response.add_body(b"<h1>Hello</h1>".to_vec());
+
+        let mut counter = CountingWriter { writes: 0, bytes: Vec::new() };
+        send_response(&mut counter, &mut response, true, &test_context(Arc::new(TrafficCounters::default()))).unwrap();
+
+        let text = String::from_utf8(counter.bytes).unwrap();
+        assert!(text.contains("content-length: 14\r\n"));
+        assert!(text.ends_with("\r\n\r\n"));
+    }
+
+    #[test]
+    fn send_response_applies_the_context_spill_threshold() {
+        let mut context = test_context(Arc::new(TrafficCounters::default()));
+        context.spill_threshold = 8;
+        let mut response = HttpResponse::build(String::from("HTTP/1.1"), 200);
+        response.extend_body(b"longer than eight bytes");
+        let mut own = HttpResponse::build(String::from("HTTP/1.1"), 200);
+        own.set_spill_threshold(1024).extend_body(b"longer than eight bytes");
+
+        let mut counter = CountingWriter { writes: 0, bytes: Vec::new() };
+        send_response(&mut counter, &mut response, false, &context).unwrap();
+        send_response(&mut counter, &mut own, false, &context).unwrap();
+
+        assert!(response.is_spilled());
+        assert_eq!(own.spill_threshold(), Some(1024));
+        assert!(!own.is_spilled());
+        assert!(String::from_utf8(counter.bytes).unwrap().ends_with("longer than eight bytes"));
+    }
+
+    fn request(version: &str, connection: Option<&str>) -> HttpRequest {
+        let mut req = HttpRequest::build(HttpMethods::GET, "/".to_string(), version.to_string());
+        if let Some(connection) = connection {
+            req.add_header("Connection".to_string(), connection.to_string());
+        }
+        req
+    }
+
+    #[test]
+    fn wants_keep_alive_follows_version_defaults() {
+        assert!(wants_keep_alive(&request("HTTP/1.1", None)));
+        assert!(!wants_keep_alive(&request("HTTP/1.1", Some("close"))));
+        assert!(!wants_keep_alive(&request("HTTP/1.0", None)));
+        assert!(wants_keep_alive(&request("HTTP/1.0", Some("Keep-Alive"))));
+    }
+
+    #[test]
+    fn wants_keep_alive_rejects_transfer_encoded_requests() {
+        let mut req = request("HTTP/1.1", None);
+        req.add_header("Transfer-Encoding".to_string(), "chunked".to_string());
+        assert!(!wants_keep_alive(&req));
+    }
+
+    #[test]
+    fn requested_upgrade_needs_connection_upgrade() {
+        let mut req = request("HTTP/1.1", Some("Upgrade, HTTP2-Settings"));
+        req.add_header("Upgrade".to_string(), "h2c".to_string());
+        assert_eq!(requested_upgrade(&req), Some("h2c".to_string()));
+
+        let mut req = request("HTTP/1.1", None);
+        req.add_header("Upgrade".to_string(), "h2c".to_string());
+        assert_eq!(requested_upgrade(&req), None);
+    }
+
+    #[test]
+    fn has_unexpected_body_only_flags_bodiless_methods() {
+        let mut get = request("HTTP/1.1", None);
+        assert!(!has_unexpected_body(&get));
+        get.add_body(b"hello".to_vec());
+        assert!(has_unexpected_body(&get));
+
+        let post = HttpRequest::build(HttpMethods::POST, "/".to_string(), "HTTP/1.1".to_string())
+            .with_body(b"hello".to_vec());
+        assert!(!has_unexpected_body(&post));
+    }
+
+    struct MockAcceptor {
+        results: std::cell::RefCell<std::collections::VecDeque<io::Result<u32>>>,
+    }
+
+    impl Acceptor for MockAcceptor {
+        type Stream = u32;
+
+        fn accept_stream(&self) -> io::Result<u32> {
+            self.results.borrow_mut().pop_front().expect("accept called after last result")
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn accept_next_survives_accept_errors() {
+        let acceptor = MockAcceptor {
+            results: std::cell::RefCell::new(
+                vec![
+                    Err(io::Error::from_raw_os_error(libc::EMFILE)),
+                    Err(io::Error::from(io::ErrorKind::ConnectionAborted)),
+                    Ok(7),
+                    Ok(8),
+                ]
+                .into(),
+            ),
+        };
+        let logger = StderrLogger::new(LogLevel::Error);
+
+        assert_eq!(accept_next(&acceptor, &logger, Duration::ZERO), 7);
+        assert_eq!(accept_next(&acceptor, &logger, Duration::ZERO), 8);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn is_fd_exhaustion_matches_emfile_and_enfile_only() {
+        assert!(is_fd_exhaustion(&io::Error::from_raw_os_error(libc::EMFILE)));
+        assert!(is_fd_exhaustion(&io::Error::from_raw_os_error(libc::ENFILE)));
+        assert!(!is_fd_exhaustion(&io::Error::from(io::ErrorKind::ConnectionAborted)));
+    }
+
+    #[test]
+    fn apply_default_cache_control_keeps_explicit_header() {
+        let mut css = HttpResponse::build(String::from("HTTP/1.1"), 200);
+        css.add_header("Content-Type".to_string(), "text/css; charset=utf-8".to_string());
+        apply_default_cache_control(&mut css);
+        assert_eq!(
+            css.try_get_header("cache-control".to_string()),
+            Some("public, max-age=31536000, immutable".to_string())
+        );
+
+        let mut overridden = HttpResponse::build(String::from("HTTP/1.1"), 200);
+        overridden.add_header("Content-Type".to_string(), "text/css; charset=utf-8".to_string());
+        overridden.add_header("Cache-Control".to_string(), "max-age=60".to_string());
+        apply_default_cache_control(&mut overridden);
+        assert_eq!(overridden.try_get_header("cache-control".to_string()), Some("max-age=60".to_string()));
+    }
+
+    #[test]
+    fn apply_range_slices_static_files_only() {
+        let file = || {
+            let mut response = HttpResponse::build(String::from("HTTP/1.1"), 200);
+            response.add_header("Accept-Ranges".to_string(), "bytes".to_string());
+            response.add_body(b"0123456789".to_vec());
+            response
+        };
+        let ranged = request("HTTP/1.1", None).with_header("Range".to_string(), "bytes=2-5".to_string());
+
+        let mut partial = file();
+        apply_range(&ranged, &mut partial);
+        assert_eq!(partial.status_code(), 206);
+        assert_eq!(partial.try_get_body(), Some(b"2345".to_vec()));
+        assert_eq!(partial.try_get_header("content-range".to_string()), Some("bytes 2-5/10".to_string()));
+
+        let mut unsatisfiable = file();
+        let past_end = request("HTTP/1.1", None).with_header("Range".to_string(), "bytes=10-".to_string());
+        apply_range(&past_end, &mut unsatisfiable);
+        assert_eq!(unsatisfiable.status_code(), 416);
+        assert_eq!(unsatisfiable.try_get_header("content-range".to_string()), Some("bytes */10".to_string()));
+
+        let mut generated = HttpResponse::build(String::from("HTTP/1.1"), 200);
+        generated.add_body(b"0123456789".to_vec());
+        apply_range(&ranged, &mut generated);
+        assert_eq!(generated.status_code(), 200);
+        assert_eq!(generated.try_get_header("accept-ranges".to_string()), Some("none".to_string()));
+    }
+
+    #[test]
+    fn bind_addresses_handles_lists_and_ipv6() {
+        assert_eq!(bind_addresses("127.0.0.1", "7878"), vec!["127.0.0.1:7878"]);
+        assert_eq!(
+            bind_addresses("127.0.0.1, [::1],::1", "0"),
+            vec!["127.0.0.1:0", "[::1]:0", "[::1]:0"]
+        );
+        for full_address in bind_addresses("127.0.0.1,[::1]", "0") {
+            assert!(TcpListener::bind(&full_address).is_ok(), "failed to bind {full_address}");
+        }
+    }
+
+    fn test_context(traffic: Arc<TrafficCounters>) -> ServerContext {
+        ServerContext {
+            site: Site {
+                router: Router::new(HashMap::new()),
+                maintenance: Maintenance::new(false, &[], Duration::from_secs(1)),
+            },
+            vhosts: HashMap::new(),
+            strict_vhosts: false,
+            logger: Arc::new(StderrLogger::new(LogLevel::Error)),
+            access_log: AccessLog::new(LogFormat::Plain, Box::new(io::sink())),
+            cors: None,
+            keepalive_timeout: Duration::from_secs(1),
+            max_requests_per_conn: 100,
+            request_timeout: Duration::from_secs(1),
+            reject_unexpected_bodies: true,
+            parse_options: ParseOptions::default(),
+            cache_control: true,
+            trusted_proxies: Vec::new(),
+            traffic,
+            responses: ResponseStats::default(),
+            expose_metrics: false,
+            read_buffer: DEFAULT_BUFFER_SIZE,
+            write_buffer: DEFAULT_BUFFER_SIZE,
+            spill_threshold: DEFAULT_SPILL_THRESHOLD,
+            debug_errors: false,
+            debug_connections: false,
+            draining: AtomicBool::new(false),
+        }
+    }
+
+    #[test]
+    fn handle_connection_counts_responses_by_status() {
+        let page = std::env::temp_dir().join(format!("rcomm_server_stats_{}.html", std::process::id()));
+        std::fs::write(&page, "hi").unwrap();
+        let mut context = test_context(Arc::new(TrafficCounters::default()));
+        context.site.router = Router::new(std::collections::HashMap::from([(String::from("/"), page.clone())]));
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let requests = b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\nGET /missing HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n";
+
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        client.write_all(requests).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        handle_connection(stream, &context);
+        std::fs::remove_file(&page).unwrap();
+
+        assert_eq!(context.responses.count(200), 1);
+        assert_eq!(context.responses.count(404), 1);
+        assert_eq!(context.responses.class_count(2), 1);
+        assert_eq!(context.responses.class_count(4), 1);
+    }
+
+    #[test]
+    fn panicking_handler_gets_500_with_detail_only_in_debug_mode() {
+        for debug_errors in [false, true] {
+            let mut context = test_context(Arc::new(TrafficCounters::default()));
+            context.debug_errors = debug_errors;
+            context.site.router.add_handler(HttpMethods::GET, "/boom", |_request| panic!("database on fire"));
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+
+            let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+            client.write_all(b"GET /boom HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").unwrap();
+            let (stream, _) = listener.accept().unwrap();
+            handle_connection(stream, &context);
+
+            let response = HttpResponse::build_from_reader(&mut BufReader::new(client)).unwrap();
+            let body = String::from_utf8(response.try_get_body().unwrap()).unwrap();
+            assert_eq!(response.status_code(), 500);
+            if debug_errors {
+                assert_eq!(body, "500 Internal Server Error: database on fire");
+            } else {
+                assert_eq!(body, "500 Internal Server Error");
+            }
+        }
+    }
+
+    #[test]
+    fn streamed_response_to_http_1_0_is_close_delimited() {
+        let mut context = test_context(Arc::new(TrafficCounters::default()));
+        context.site.router.add_handler(HttpMethods::GET, "/events", |_request| {
+            let mut response = HttpResponse::build(String::from("HTTP/1.1"), 200);
+            response.set_body_stream(&b"data: hi\n\n"[..]);
+            response
+        });
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        client.write_all(b"GET /events HTTP/1.0\r\nConnection: keep-alive\r\n\r\n").unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        handle_connection(stream, &context);
+
+        let mut wire = String::new();
+        client.read_to_string(&mut wire).unwrap();
+        let (head, body) = wire.split_once("\r\n\r\n").unwrap();
+        assert!(!head.contains("transfer-encoding"), "{head}");
+        assert!(head.contains("connection: close"), "{head}");
+        assert_eq!(body, "data: hi\n\n");
+    }
+
+    /// An access log sink the test can read back.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn rejected_requests_are_access_logged() {
+        let mut lines = Vec::new();
+        for raw in [&b"BOGUS\r\n\r\n"[..], b"GET /x HTTP/1.1\r\nHost: localhost\r\nContent-Length: 1\r\n\r\nz"] {
+            let log = SharedBuffer::default();
+            let mut context = test_context(Arc::new(TrafficCounters::default()));
+            context.access_log = AccessLog::new(LogFormat::Plain, Box::new(log.clone()));
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+
+            let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+            client.write_all(raw).unwrap();
+            let (stream, _) = listener.accept().unwrap();
+            handle_connection(stream, &context);
+            lines.push(String::from_utf8(log.0.lock().unwrap().clone()).unwrap());
+        }
+
+        assert!(lines[0].starts_with("127.0.0.1 ["), "{}", lines[0]);
+        assert!(lines[0].contains("\"- - -\" 400 "), "{}", lines[0]);
+        assert!(lines[1].contains("\"GET /x HTTP/1.1\" 400 "), "{}", lines[1]);
+    }
+
+    #[test]
+    fn expect_continue_gets_100_before_the_body_is_sent() {
+        let mut context = test_context(Arc::new(TrafficCounters::default()));
+        context.site.router.add_handler(HttpMethods::POST, "/echo", |request| {
+            let mut response = HttpResponse::build(String::from("HTTP/1.1"), 200);
+            response.add_body(request.try_get_body().unwrap_or_default());
+            response
+        });
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            handle_connection(stream, &context);
+        });
+
+        let mut client = TcpStream::connect(address).unwrap();
+        client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        client.write_all(b"POST /echo HTTP/1.1\r\nHost: x\r\nExpect: 100-continue\r\nContent-Length: 5\r\nConnection: close\r\n\r\n").unwrap();
+        let mut reader = BufReader::new(client.try_clone().unwrap());
+        let interim = HttpResponse::build_head_from_reader(&mut reader).unwrap();
+        client.write_all(b"hello").unwrap();
+        let response = HttpResponse::build_from_reader(&mut reader).unwrap();
+        server.join().unwrap();
+
+        assert_eq!(interim.status_code(), 100);
+        assert_eq!(response.status_code(), 200);
+        assert_eq!(response.try_get_body(), Some(b"hello".to_vec()));
+    }
+
+    #[test]
+    fn invalid_content_length_gets_400_and_closes_the_pipeline() {
+        let context = test_context(Arc::new(TrafficCounters::default()));
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        client
+            .write_all(b"POST / HTTP/1.1\r\nHost: x\r\nContent-Length: 5x\r\n\r\nhelloGET / HTTP/1.1\r\nHost: x\r\n\r\n")
+            .unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        handle_connection(stream, &context);
+
+        let mut reader = BufReader::new(client);
+        let response = HttpResponse::build_from_reader(&mut reader).unwrap();
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).unwrap();
+
+        assert_eq!(response.status_code(), 400);
+        assert_eq!(response.try_get_header("connection".to_string()), Some("close".to_string()));
+        assert!(rest.is_empty(), "pipelined request was answered: {}", String::from_utf8_lossy(&rest));
+    }
+
+    #[test]
+    fn stalled_headers_get_408_before_close() {
+        let mut context = test_context(Arc::new(TrafficCounters::default()));
+        context.request_timeout = Duration::from_millis(200);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        client.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nX-Partial: ").unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        handle_connection(stream, &context);
+
+        let response = HttpResponse::build_from_reader(&mut BufReader::new(client)).unwrap();
+        assert_eq!(response.status_code(), 408);
+        assert_eq!(response.try_get_header("connection".to_string()), Some("close".to_string()));
+        assert_eq!(context.responses.count(408), 1);
+    }
+
+    #[test]
+    fn unknown_host_gets_421_only_with_strict_vhosts() {
+        let page = std::env::temp_dir().join(format!("rcomm_server_vhost_{}.html", std::process::id()));
+        std::fs::write(&page, "default site").unwrap();
+        let request = |strict: bool, host: &str| {
+            let mut context = test_context(Arc::new(TrafficCounters::default()));
+            context.site.router = Router::new(HashMap::from([(String::from("/"), page.clone())]));
+            let known = Site { router: Router::new(HashMap::new()), maintenance: Maintenance::new(false, &[], Duration::from_secs(1)) };
+            context.vhosts.insert(String::from("known.test"), known);
+            context.strict_vhosts = strict;
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+            write!(client, "GET / HTTP/1.1\r\nHost: {host}\r\nConnection: close\r\n\r\n").unwrap();
+            let (stream, _) = listener.accept().unwrap();
+            handle_connection(stream, &context);
+            HttpResponse::build_from_reader(&mut BufReader::new(client)).unwrap()
+        };
+        let strict = request(true, "unknown.test");
+        let lenient = request(false, "unknown.test");
+        let known = request(true, "known.test");
+        std::fs::remove_file(&page).unwrap();
+
+        assert_eq!(strict.status_code(), 421);
+        assert_eq!(lenient.status_code(), 200);
+        assert_eq!(lenient.try_get_body(), Some(b"default site".to_vec()));
+        assert_eq!(known.status_code(), 404);
+    }
+
+    #[test]
+    fn maintenance_marker_only_affects_its_own_site() {
+        let root = std::env::temp_dir().join(format!("rcomm_server_vhost_maintenance_{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join(crate::maintenance::MARKER_FILE), "").unwrap();
+        let mut context = test_context(Arc::new(TrafficCounters::default()));
+        let down = Site {
+            router: Router::new(HashMap::new()),
+            maintenance: Maintenance::new(false, std::slice::from_ref(&root), Duration::from_secs(1)),
+        };
+        context.vhosts.insert(String::from("down.test"), down);
+        let status = |host: &str| {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+            write!(client, "GET / HTTP/1.1\r\nHost: {host}\r\nConnection: close\r\n\r\n").unwrap();
+            let (stream, _) = listener.accept().unwrap();
+            handle_connection(stream, &context);
+            HttpResponse::build_from_reader(&mut BufReader::new(client)).unwrap().status_code()
+        };
+        let down = status("down.test");
+        let up = status("up.test");
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(down, 503);
+        assert_eq!(up, 404);
+    }
+
+    #[test]
+    fn debug_connections_numbers_requests_on_a_connection() {
+        let mut context = test_context(Arc::new(TrafficCounters::default()));
+        context.debug_connections = true;
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let requests = b"GET /a HTTP/1.1\r\nHost: localhost\r\n\r\nGET /b HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n";
+
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        client.write_all(requests).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        handle_connection(stream, &context);
+
+        let mut reader = BufReader::new(client);
+        for expected in ["1", "2"] {
+            let response = HttpResponse::build_from_reader(&mut reader).unwrap();
+            assert_eq!(response.try_get_header("x-conn-requests".to_string()), Some(expected.to_string()));
+        }
+    }
+
+    #[test]
+    fn connection_closes_after_max_requests() {
+        let mut context = test_context(Arc::new(TrafficCounters::default()));
+        context.max_requests_per_conn = 2;
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let requests = b"GET /a HTTP/1.1\r\nHost: localhost\r\n\r\n".repeat(3);
+
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        client.write_all(&requests).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        handle_connection(stream, &context);
+
+        let mut reader = BufReader::new(client);
+        let first = HttpResponse::build_from_reader(&mut reader).unwrap();
+        let second = HttpResponse::build_from_reader(&mut reader).unwrap();
+        assert_eq!(first.try_get_header("connection".to_string()), Some("keep-alive".to_string()));
+        assert_eq!(second.try_get_header("connection".to_string()), Some("close".to_string()));
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).unwrap();
+        assert!(rest.is_empty(), "third request was answered");
+        assert_eq!(context.responses.class_count(4), 2);
+    }
+
+    #[test]
+    fn event_stream_delivers_events_until_client_leaves() {
+        let (gone_tx, gone_rx) = std::sync::mpsc::channel();
+        let gone_tx = std::sync::Mutex::new(gone_tx);
+        let mut context = test_context(Arc::new(TrafficCounters::default()));
+        context.site.router.add_handler(HttpMethods::GET, "/events", move |_request| {
+            let (events, response) = crate::sse::event_stream();
+            let gone_tx = gone_tx.lock().unwrap().clone();
+            thread::spawn(move || {
+                let mut n = 0;
+                while events.send(&format!("tick {n}")).is_ok() {
+                    n += 1;
+                    thread::sleep(Duration::from_millis(20));
+                }
+                let _ = gone_tx.send(());
+            });
+            response
+        });
+        let context = Arc::new(context);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        client.write_all(b"GET /events HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        let server = {
+            let context = Arc::clone(&context);
+            thread::spawn(move || handle_connection(stream, &context))
+        };
+
+        let mut received = String::new();
+        let mut buf = [0; 256];
+        while !received.contains("data: tick 1\n\n") {
+            let n = client.read(&mut buf).unwrap();
+            assert!(n > 0, "stream ended early: {received:?}");
+            received.push_str(&String::from_utf8_lossy(&buf[..n]));
+        }
+        assert!(received.contains("content-type: text/event-stream\r\n"));
+        assert!(received.contains("data: tick 0\n\n"));
+        drop(client);
+
+        gone_rx.recv_timeout(Duration::from_secs(5)).expect("producer noticed the disconnect");
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn handle_connection_counts_bytes_in_and_out() {
+        let pool = ThreadPool::new(1);
+        let context = test_context(pool.traffic());
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let request = b"GET /missing HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n";
+
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        client.write_all(request).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        handle_connection(stream, &context);
+
+        let mut response = Vec::new();
+        client.read_to_end(&mut response).unwrap();
+        let metrics = pool.metrics();
+        assert!(metrics.bytes_in >= request.len() as u64);
+        assert!(metrics.bytes_out >= response.len() as u64);
+        assert!(!response.is_empty());
+    }
+
+    struct CapturingLogger {
+        messages: std::sync::Mutex<Vec<String>>,
+    }
+
+    impl Logger for CapturingLogger {
+        fn log(&self, _level: LogLevel, message: &str) {
+            self.messages.lock().unwrap().push(message.to_string());
+        }
+    }
+
+    #[test]
+    fn large_response_survives_small_and_large_buffers() {
+        let page = std::env::temp_dir().join(format!("rcomm_server_buffers_{}.bin", std::process::id()));
+        let contents: Vec<u8> = (0..1_000_000u32).map(|i| (i % 251) as u8).collect();
+        std::fs::write(&page, &contents).unwrap();
+        for size in [16, 1024 * 1024] {
+            let mut context = test_context(Arc::new(TrafficCounters::default()));
+            context.site.router = Router::new(std::collections::HashMap::from([(String::from("/big"), page.clone())]));
+            context.read_buffer = size;
+            context.write_buffer = size;
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+
+            let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+            client.write_all(b"GET /big HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").unwrap();
+            let (stream, _) = listener.accept().unwrap();
+            let server = {
+                let context = Arc::new(context);
+                thread::spawn(move || handle_connection(stream, &context))
+            };
+
+            let response = HttpResponse::build_from_reader(&mut BufReader::new(client)).unwrap();
+            server.join().unwrap();
+            assert_eq!(response.status_code(), 200, "buffer size {size}");
+            assert!(response.try_get_body() == Some(contents.clone()), "buffer size {size}");
+        }
+        std::fs::remove_file(&page).unwrap();
+    }
+
+    #[test]
+    fn connection_closed_before_any_bytes_is_silent() {
+        let logger = Arc::new(CapturingLogger { messages: std::sync::Mutex::new(Vec::new()) });
+        let mut context = test_context(Arc::new(TrafficCounters::default()));
+        context.logger = logger.clone();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        drop(client);
+        let (stream, _) = listener.accept().unwrap();
+        handle_connection(stream, &context);
+
+        assert!(context.responses.snapshot().is_empty());
+        assert!(logger.messages.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn whitespace_request_line_gets_400() {
+        let context = test_context(Arc::new(TrafficCounters::default()));
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        client.write_all(b"   \r\n").unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        handle_connection(stream, &context);
+
+        let response = HttpResponse::build_from_reader(&mut BufReader::new(client)).unwrap();
+        assert_eq!(response.status_code(), 400);
+        assert_eq!(context.responses.count(400), 1);
+    }
+
+    #[test]
+    fn handler_panic_is_logged_with_request_path() {
+        let logger = Arc::new(CapturingLogger { messages: std::sync::Mutex::new(Vec::new()) });
+        let pool = ThreadPool::new_with_logger(1, logger.clone());
+        let mut context = test_context(pool.traffic());
+        context.logger = logger.clone();
+        context.site.router.set_fallback(|request| {
+            if request.normalized_path() == "/boom" {
+                panic!("handler exploded");
+            }
+            HttpResponse::build(String::from("HTTP/1.1"), 404)
+        });
+        let context = Arc::new(context);
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        client.write_all(b"GET /boom HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        pool.execute(move || handle_connection(stream, &context));
+        drop(pool);
+
+        let messages = logger.messages.lock().unwrap();
+        assert!(
+            messages.iter().any(|message| message.contains("panicked while handling GET /boom from 127.0.0.1")
+                && message.contains("handler exploded")),
+            "{messages:?}"
+        );
+    }
+
+    /// An access log sink that fails loudly, to get a panic past the
+    /// handler's `catch_unwind`.
+    struct PanickingSink;
+
+    impl Write for PanickingSink {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            panic!("access log exploded");
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn panic_escaping_the_handler_is_logged_by_the_worker_with_request() {
+        let logger = Arc::new(CapturingLogger { messages: std::sync::Mutex::new(Vec::new()) });
+        let pool = ThreadPool::new_with_logger(1, logger.clone());
+        let mut context = test_context(pool.traffic());
+        context.access_log = AccessLog::new(LogFormat::Plain, Box::new(PanickingSink));
+        let context = Arc::new(context);
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        client.write_all(b"GET /logged HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        pool.execute(move || handle_connection(stream, &context));
+        drop(pool);
+
+        let messages = logger.messages.lock().unwrap();
+        assert!(
+            messages.iter().any(|message| message == "Worker 0 panicked while handling GET /logged from 127.0.0.1: access log exploded"),
+            "{messages:?}"
+        );
+    }
+
+    #[cfg(feature = "backlog")]
+    #[test]
+    fn serve_accepts_connections_with_custom_backlog() {
+        let config = ServerConfig {
+            port: 0,
+            backlog: Some(8),
+            roots: vec![PathBuf::from("pages")],
+            log_level: LogLevel::Error,
+            check_routes: false,
+            ..ServerConfig::default()
+        };
+        let server = serve(config).unwrap();
+        let url = format!("http://{}/", server.local_addrs()[0]);
+
+        for _ in 0..3 {
+            let response = crate::client::HttpClient::new().get(&url).unwrap();
+            assert_eq!(response.status_code(), 200);
+        }
+        assert_eq!(server.shutdown().abandoned, 0);
+    }
+
+    #[test]
+    fn serve_answers_requests_and_shuts_down() {
+        let config = ServerConfig {
+            port: 0,
+            roots: vec![PathBuf::from("pages")],
+            log_level: LogLevel::Error,
+            check_routes: false,
+            ..ServerConfig::default()
+        };
+        let server = serve(config).unwrap();
+        let url = format!("http://{}/", server.local_addrs()[0]);
+
+        let response = crate::client::HttpClient::new().get(&url).unwrap();
+        assert_eq!(response.status_code(), 200);
+
+        let report = server.shutdown();
+        assert_eq!(report.abandoned, 0);
+        assert!(TcpStream::connect(url.trim_start_matches("http://").trim_end_matches('/')).is_err());
+    }
+}
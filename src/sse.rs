@@ -0,0 +1,129 @@
+//! Server-Sent Events. A handler returns the response from `event_stream`
+//! and hands the `EventSender` to whatever produces events; frames are
+//! written to the client as they are sent, and the stream ends when the
+//! sender is dropped.
+//!
+//! An open stream keeps its worker thread busy for as long as it lasts, so
+//! each subscriber takes one of the `RCOMM_WORKERS` workers away from
+//! ordinary requests. Size the pool for the number of concurrent streams.
+
+use std::{
+    fmt,
+    io::{self, Read},
+    sync::mpsc::{self, RecvTimeoutError},
+    time::Duration,
+};
+use crate::models::http_response::HttpResponse;
+
+/// How often an idle stream sends a comment line, so proxies keep it open
+/// and a departed client is noticed by the failed write.
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+
+/// The client went away; no further events can be delivered.
+#[derive(Debug, PartialEq)]
+pub struct Disconnected;
+
+impl fmt::Display for Disconnected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Event stream closed")
+    }
+}
+
+/// Sends events to one open stream.
+pub struct EventSender {
+    sender: mpsc::Sender<Vec<u8>>,
+}
+
+impl EventSender {
+    /// Sends an unnamed event. Multi-line `data` becomes one `data:` field
+    /// per line.
+    pub fn send(&self, data: &str) -> Result<(), Disconnected> {
+        self.sender.send(frame(None, data)).map_err(|_| Disconnected)
+    }
+
+    /// Sends an event the client can listen for by `event` name.
+    pub fn send_event(&self, event: &str, data: &str) -> Result<(), Disconnected> {
+        self.sender.send(frame(Some(event), data)).map_err(|_| Disconnected)
+    }
+}
+
+/// Creates a `text/event-stream` response and the sender feeding it.
+pub fn event_stream() -> (EventSender, HttpResponse) {
+    let (sender, receiver) = mpsc::channel();
+    let mut response = HttpResponse::build(String::from("HTTP/1.1"), 200);
+    response.add_header("Content-Type".to_string(), "text/event-stream".to_string());
+    response.add_header("Cache-Control".to_string(), "no-cache".to_string());
+    response.set_body_stream(EventReader { receiver, pending: Vec::new(), heartbeat: HEARTBEAT_INTERVAL });
+    (EventSender { sender }, response)
+}
+
+fn frame(event: Option<&str>, data: &str) -> Vec<u8> {
+    let mut frame = String::new();
+    if let Some(event) = event {
+        frame.push_str(&format!("event: {event}\n"));
+    }
+    for line in data.lines() {
+        frame.push_str(&format!("data: {line}\n"));
+    }
+    if data.is_empty() {
+        frame.push_str("data: \n");
+    }
+    frame.push('\n');
+    frame.into_bytes()
+}
+
+/// Blocks until the next frame is sent, yielding a heartbeat comment when
+/// none arrives in time; EOF once the sender is dropped.
+struct EventReader {
+    receiver: mpsc::Receiver<Vec<u8>>,
+    pending: Vec<u8>,
+    heartbeat: Duration,
+}
+
+impl Read for EventReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pending.is_empty() {
+            self.pending = match self.receiver.recv_timeout(self.heartbeat) {
+                Ok(frame) => frame,
+                Err(RecvTimeoutError::Timeout) => b":\n\n".to_vec(),
+                Err(RecvTimeoutError::Disconnected) => return Ok(0),
+            };
+        }
+        let n = buf.len().min(self.pending.len());
+        buf[..n].copy_from_slice(&self.pending[..n]);
+        self.pending.drain(..n);
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_events_and_ends_when_sender_drops() {
+        let (sender, response) = event_stream();
+        assert_eq!(response.try_get_header("content-type".to_string()), Some("text/event-stream".to_string()));
+        sender.send("hello").unwrap();
+        sender.send_event("update", "line one\nline two").unwrap();
+        drop(sender);
+
+        let mut written = Vec::new();
+        response.write_to(&mut written).unwrap();
+        let written = String::from_utf8(written).unwrap();
+        let body = written.split_once("\r\n\r\n").unwrap().1;
+        assert_eq!(
+            body,
+            "d\r\ndata: hello\n\n\r\n2d\r\nevent: update\ndata: line one\ndata: line two\n\n\r\n0\r\n\r\n"
+        );
+    }
+
+    #[test]
+    fn idle_stream_sends_heartbeat() {
+        let (_sender, receiver) = mpsc::channel();
+        let mut reader = EventReader { receiver, pending: Vec::new(), heartbeat: Duration::from_millis(1) };
+        let mut buf = [0; 16];
+        let n = reader.read(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b":\n\n");
+    }
+}
@@ -0,0 +1,187 @@
+use std::{collections::HashMap, fmt};
+use crate::middleware::Middleware;
+use crate::models::{
+    http_request::HttpRequest,
+    http_response::HttpResponse,
+};
+
+pub type TemplateFn = Box<dyn Fn(&HttpRequest) -> String + Send + Sync>;
+
+/// What to do with a `{{ name }}` placeholder that has no registered value.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnknownPlaceholder {
+    /// Leave the placeholder in the output as written.
+    Keep,
+    /// Fail rendering, which the middleware turns into a 500.
+    Error,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum TemplateError {
+    UnknownPlaceholder(String),
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TemplateError::UnknownPlaceholder(name) => write!(f, "Unknown template placeholder: {name}"),
+        }
+    }
+}
+
+/// A registry of `{{ name }}` substitutions. Used as middleware, it renders
+/// every successful `text/html` response; substituted values are
+/// HTML-escaped.
+pub struct Templates {
+    values: HashMap<String, TemplateFn>,
+    unknown: UnknownPlaceholder,
+}
+
+impl Default for Templates {
+    fn default() -> Self {
+        Templates::new()
+    }
+}
+
+impl Templates {
+    pub fn new() -> Templates {
+        Templates { values: HashMap::new(), unknown: UnknownPlaceholder::Keep }
+    }
+
+    /// Registers `value` to produce the text for `{{ name }}`.
+    pub fn register<F>(&mut self, name: &str, value: F) -> &mut Templates
+    where
+        F: Fn(&HttpRequest) -> String + Send + Sync + 'static,
+    {
+        self.values.insert(name.to_string(), Box::new(value));
+        self
+    }
+
+    pub fn set_unknown(&mut self, policy: UnknownPlaceholder) -> &mut Templates {
+        self.unknown = policy;
+        self
+    }
+
+    pub fn render(&self, input: &str, request: &HttpRequest) -> Result<String, TemplateError> {
+        let mut output = String::with_capacity(input.len());
+        let mut rest = input;
+
+        while let Some(start) = rest.find("{{") {
+            let Some(len) = rest[start + 2..].find("}}") else { break; };
+            let placeholder = &rest[start..start + 2 + len + 2];
+            let name = placeholder[2..placeholder.len() - 2].trim();
+            output.push_str(&rest[..start]);
+
+            match self.values.get(name) {
+                Some(value) => output.push_str(&escape_html(&value(request))),
+                None if self.unknown == UnknownPlaceholder::Error && is_identifier(name) => {
+                    return Err(TemplateError::UnknownPlaceholder(name.to_string()));
+                }
+                None => output.push_str(placeholder),
+            }
+            rest = &rest[start + placeholder.len()..];
+        }
+        output.push_str(rest);
+        Ok(output)
+    }
+}
+
+impl Middleware for Templates {
+    fn handle(&self, request: &HttpRequest, next: &dyn Fn(&HttpRequest) -> HttpResponse) -> HttpResponse {
+        let mut response = next(request);
+        let is_html = response
+            .try_get_header("Content-Type".to_string())
+            .is_some_and(|content_type| content_type.starts_with("text/html"));
+        let encoded = response.try_get_header("Content-Encoding".to_string()).is_some();
+        if response.status_code() != 200 || !is_html || encoded {
+            return response;
+        }
+        let Some(body) = response.try_get_body() else { return response; };
+        let Ok(body) = String::from_utf8(body) else { return response; };
+
+        match self.render(&body, request) {
+            Ok(rendered) => {
+                // The rendered page differs per request, so byte offsets into
+                // it mean nothing and the file's validators don't describe it.
+                response.add_header("Accept-Ranges".to_string(), "none".to_string());
+                response.remove_header("ETag".to_string());
+                response.remove_header("Last-Modified".to_string());
+                response.add_body(rendered.into());
+                response
+            }
+            Err(e) => {
+                let mut error = HttpResponse::build(String::from("HTTP/1.1"), 500);
+                error.add_header("Content-Type".to_string(), "text/plain; charset=utf-8".to_string());
+                error.add_body(format!("500 Internal Server Error: {e}").into());
+                error
+            }
+        }
+    }
+}
+
+fn is_identifier(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+pub fn escape_html(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, path::PathBuf};
+    use crate::models::http_methods::HttpMethods;
+    use crate::router::Router;
+
+    fn get(target: &str) -> HttpRequest {
+        HttpRequest::build(HttpMethods::GET, target.to_string(), "HTTP/1.1".to_string())
+    }
+
+    #[test]
+    fn renders_served_html_through_middleware() {
+        let path = std::env::temp_dir()
+            .join(format!("rcomm_template_{}.html", std::process::id()));
+        fs::write(&path, "<h1>{{ greeting }}</h1><p>{{unknown}}</p>").unwrap();
+        let mut routes = HashMap::new();
+        routes.insert(String::from("/hello"), PathBuf::from(&path));
+
+        let mut templates = Templates::new();
+        templates.register("greeting", |_request| String::from("Hello & <welcome>"));
+        let mut router = Router::new(routes);
+        router.add_middleware(templates);
+
+        let resp = router.handle(&get("/hello"));
+        fs::remove_file(&path).unwrap();
+
+        let body = b"<h1>Hello &amp; &lt;welcome&gt;</h1><p>{{unknown}}</p>".to_vec();
+        assert_eq!(resp.try_get_header("content-length".to_string()), Some(body.len().to_string()));
+        assert_eq!(resp.try_get_body(), Some(body));
+    }
+
+    #[test]
+    fn unknown_placeholder_policy() {
+        let mut templates = Templates::new();
+        templates.register("path", |request| request.target.clone());
+        let request = get("/here");
+
+        assert_eq!(templates.render("{{ path }} {{ nope }} {{", &request), Ok(String::from("/here {{ nope }} {{")));
+
+        templates.set_unknown(UnknownPlaceholder::Error);
+        assert_eq!(
+            templates.render("{{ nope }}", &request),
+            Err(TemplateError::UnknownPlaceholder(String::from("nope")))
+        );
+    }
+}
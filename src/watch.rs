@@ -0,0 +1,110 @@
+//! Development-time reloading of the route table. `RouteWatcher` rescans
+//! the document roots when polled and hands back a rebuilt table whenever a
+//! file was added, removed or modified; `serve` polls it on a timer when
+//! `RCOMM_WATCH_INTERVAL` is set and swaps the result into the router.
+
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+use crate::router::{Routes, build_routes_overlay};
+
+/// Every file under the roots with its modification time.
+type Snapshot = BTreeMap<PathBuf, Option<SystemTime>>;
+
+pub struct RouteWatcher {
+    roots: Vec<PathBuf>,
+    snapshot: Snapshot,
+}
+
+impl RouteWatcher {
+    /// Starts watching `roots` from their current contents.
+    pub fn new(roots: Vec<PathBuf>) -> RouteWatcher {
+        let snapshot = snapshot(&roots);
+        RouteWatcher { roots, snapshot }
+    }
+
+    /// Rescans the roots, returning a freshly built route table if anything
+    /// changed since the last poll. A failed rebuild is tried again on the
+    /// next poll.
+    pub fn poll(&mut self) -> Option<io::Result<Routes>> {
+        let current = snapshot(&self.roots);
+        if current == self.snapshot {
+            return None;
+        }
+        let routes = build_routes_overlay(&self.roots);
+        if routes.is_ok() {
+            self.snapshot = current;
+        }
+        Some(routes)
+    }
+}
+
+fn snapshot(roots: &[PathBuf]) -> Snapshot {
+    let mut files = Snapshot::new();
+    for root in roots {
+        scan(root, &mut files);
+    }
+    files
+}
+
+fn scan(directory: &Path, files: &mut Snapshot) {
+    let Ok(entries) = fs::read_dir(directory) else { return; };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            scan(&path, files);
+        } else {
+            let modified = entry.metadata().and_then(|metadata| metadata.modified()).ok();
+            files.insert(path, modified);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{http_methods::HttpMethods, http_request::HttpRequest};
+    use crate::router::Router;
+
+    #[test]
+    fn new_file_becomes_routable_after_poll() {
+        let dir = std::env::temp_dir().join(format!("rcomm_watch_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("index.html"), "home").unwrap();
+        let mut watcher = RouteWatcher::new(vec![dir.clone()]);
+        let router = Router::new(build_routes_overlay(std::slice::from_ref(&dir)).unwrap());
+        let request = HttpRequest::build(HttpMethods::GET, "/new.html".to_string(), "HTTP/1.1".to_string());
+        assert!(watcher.poll().is_none());
+        assert_eq!(router.handle(&request).status_code(), 404);
+
+        fs::write(dir.join("new.html"), "fresh").unwrap();
+        router.replace_routes(watcher.poll().expect("change detected").unwrap());
+        let response = router.handle(&request);
+        assert!(watcher.poll().is_none());
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(response.status_code(), 200);
+        assert_eq!(response.try_get_body(), Some(b"fresh".to_vec()));
+    }
+
+    #[test]
+    fn failed_rebuild_is_reported_and_retried() {
+        let dir = std::env::temp_dir().join(format!("rcomm_watch_missing_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("index.html"), "home").unwrap();
+        let mut watcher = RouteWatcher::new(vec![dir.clone()]);
+
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(watcher.poll().expect("change detected").is_err());
+        assert!(watcher.poll().expect("retried").is_err());
+
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("index.html"), "back").unwrap();
+        let routes = watcher.poll().expect("retried").unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(routes.contains("/"));
+    }
+}